@@ -0,0 +1,78 @@
+use criterion::{BatchSize, Criterion, black_box, criterion_group, criterion_main};
+use kasa_core::tags::{AI_TAGGER_SOURCE, insert_tags_with_source_types};
+use kasa_python::ExtractedTag;
+use rand::seq::SliceRandom;
+use sqlx::{Pool, Sqlite, migrate::MigrateDatabase, sqlite::SqlitePoolOptions};
+use tokio::runtime::Runtime;
+
+/// Distinct tags the tagger can predict
+const VOCABULARY_SIZE: usize = 5000;
+/// Tags predicted for every image
+const TAGS_PER_IMAGE: usize = 40;
+
+async fn create_db(path: &str) -> Pool<Sqlite> {
+    Sqlite::create_database(path).await.unwrap();
+
+    let pool = SqlitePoolOptions::new()
+        .max_connections(6)
+        .connect(path)
+        .await
+        .unwrap();
+
+    sqlx::migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    pool
+}
+
+fn random_tags(vocabulary: &[String]) -> Vec<ExtractedTag> {
+    vocabulary
+        .choose_multiple(&mut rand::thread_rng(), TAGS_PER_IMAGE)
+        .map(|name| ExtractedTag {
+            _type: "General".to_string(),
+            name: name.clone(),
+        })
+        .collect()
+}
+
+/// Tags images like `kasa_cli_utils ai-tag-images` does, one call per image on a database that already has
+/// most of the vocabulary
+fn criterion_benchmark(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("bench.kasa").to_string_lossy().to_string();
+    let pool = runtime.block_on(create_db(&db_path));
+
+    let vocabulary: Vec<String> = (0..VOCABULARY_SIZE).map(|i| format!("tag_{i}")).collect();
+
+    // warm the database up with existing tags and media
+    for i in 0..1000 {
+        runtime.block_on(insert_tags_with_source_types(
+            random_tags(&vocabulary),
+            &pool,
+            Some(format!("warmup_{i}")),
+            Some(AI_TAGGER_SOURCE.to_string()),
+        ));
+    }
+
+    let mut image = 0;
+    c.bench_function("insert_tags_with_source_types", |b| {
+        b.iter_batched(
+            || {
+                image += 1;
+                (format!("image_{image}"), random_tags(&vocabulary))
+            },
+            |(hash, tags)| {
+                runtime.block_on(insert_tags_with_source_types(
+                    black_box(tags),
+                    &pool,
+                    Some(hash),
+                    Some(AI_TAGGER_SOURCE.to_string()),
+                ))
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -1,7 +1,10 @@
 use std::env;
 
 use kasa_ai::wdv_tagger::{prepare_labels, prepare_session, tag_image_wdv};
-use kasa_core::{config::global_config::get_config_impl, tags::insert_tags_with_source_types};
+use kasa_core::{
+    config::global_config::get_config_impl,
+    tags::{AI_TAGGER_SOURCE, insert_media_tags_with_source_types},
+};
 use kasa_python::ExtractedTag;
 use sqlx::{query_scalar, sqlite::SqlitePoolOptions};
 
@@ -59,29 +62,14 @@ pub async fn ai_tag_images() {
             name: tags.ratings.name,
         }];
 
-        insert_tags_with_source_types(
-            characters,
+        // one insert per image, so the implied tags are only refreshed once
+        insert_media_tags_with_source_types(
+            vec![(hash, [characters, general, ratings].concat())],
+            AI_TAGGER_SOURCE,
             &pool,
-            Some(hash.clone()),
-            Some("AI Tagger".to_string()),
         )
-        .await;
-
-        insert_tags_with_source_types(
-            general,
-            &pool,
-            Some(hash.clone()),
-            Some("AI Tagger".to_string()),
-        )
-        .await;
-
-        insert_tags_with_source_types(
-            ratings,
-            &pool,
-            Some(hash.clone()),
-            Some("AI Tagger".to_string()),
-        )
-        .await;
+        .await
+        .unwrap();
         counter += 1;
         println!("Tagged {}/{} images", counter, hash_count);
     }
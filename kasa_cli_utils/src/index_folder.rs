@@ -49,5 +49,16 @@ pub async fn index_folder(args: IndexFolderArgs) {
         .await
         .unwrap();
 
-    index(args.folder.to_str().unwrap(), &pool, &pool_thumbs).await;
+    let report = index(args.folder.to_str().unwrap(), &pool, &pool_thumbs).await;
+
+    for error in &report.errors {
+        println!(
+            "Couldn't index {} ({:?}): {}",
+            error.path, error.stage, error.message
+        );
+    }
+    println!(
+        "Indexed {} files, {} unchanged, {} failed",
+        report.progress.written, report.progress.skipped, report.progress.failed
+    );
 }
@@ -5,12 +5,30 @@ pub mod tags;
 
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use sqlx::{Pool, Sqlite, prelude::FromRow, query_as};
+use sqlx::{Pool, QueryBuilder, Sqlite, prelude::FromRow, query_as};
 
-use crate::index::media_types::FirstPass;
+use crate::{
+    index::media_types::FirstPass,
+    tags::groups::{GROUP_JOIN_SQL, RESOLVED_COLOR_SQL},
+};
 
 use schema::{Media, TagDetail};
 
+/// Most bind parameters SQLite allows in a single statement
+pub(crate) const MAX_BINDS: usize = 32766;
+
+/// Implements `From<sqlx::Error>` for error enums with a `Database(String)` variant, so `?` works on queries
+macro_rules! impl_from_sqlx_error {
+    ($error:ty) => {
+        impl From<sqlx::Error> for $error {
+            fn from(error: sqlx::Error) -> Self {
+                Self::Database(error.to_string())
+            }
+        }
+    };
+}
+pub(crate) use impl_from_sqlx_error;
+
 #[derive(Serialize, Deserialize, FromRow, Debug, Clone, specta::Type)]
 pub struct TagQueryOutput {
     name: String,
@@ -19,6 +37,22 @@ pub struct TagQueryOutput {
 
     #[sqlx(flatten)]
     tag_details: TagDetail,
+
+    /// Group of the tag, `None` if it has none or it doesn't exist anymore
+    resolved_group: Option<String>,
+    /// Color the tag is shown with, see `tags::groups`
+    resolved_color: Option<String>,
+}
+
+fn tag_query(pattern: String, limit: i64) -> QueryBuilder<Sqlite> {
+    let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+        "SELECT Tag.* , COUNT(tag) AS tag_count , TagDetail.* , TagGroup.name AS resolved_group , {RESOLVED_COLOR_SQL} AS resolved_color FROM Tag LEFT JOIN HashTagPair ON HashTagPair.tag_name = Tag.name LEFT JOIN TagDetail ON TagDetail.name = Tag.name{GROUP_JOIN_SQL}WHERE tag_name LIKE "
+    ));
+    query_builder.push_bind(pattern);
+    query_builder.push(" GROUP BY Tag.name ORDER BY tag_count DESC LIMIT ");
+    query_builder.push_bind(limit);
+
+    query_builder
 }
 
 /// Queries for the first n tags using `tag*`, if there is less than n it queries `*tag*` for (limit - n) tags
@@ -36,9 +70,8 @@ pub async fn query_tags_impl(
     // https://stackoverflow.com/questions/4784545/sql-how-to-order-using-count-from-another-table
     // SELECT * FROM Tag WHERE name LIKE ? ORDER BY count DESC LIMIT 
     // SELECT Tag.* , COUNT(tag) AS tag_count , TagDetail.* FROM Tag LEFT JOIN HashTagPair ON HashTagPair.tag_name = Tag.name LEFT JOIN TagDetail ON TagDetail.name = Tag.name WHERE tag_name LIKE "x" GROUP BY Tag.name ORDER BY tag_count DESC LIMIT 5;
-        query_as("SELECT Tag.* , COUNT(tag) AS tag_count , TagDetail.* FROM Tag LEFT JOIN HashTagPair ON HashTagPair.tag_name = Tag.name LEFT JOIN TagDetail ON TagDetail.name = Tag.name WHERE tag_name LIKE ? GROUP BY Tag.name ORDER BY tag_count DESC LIMIT ?")
-            .bind(wildcard)
-            .bind(limit)
+        tag_query(wildcard, limit)
+            .build_query_as()
             .fetch_all(pool)
             .await
             .unwrap();
@@ -48,13 +81,11 @@ pub async fn query_tags_impl(
     if (tags.len() as i64) < limit {
         let new_limit = limit - (tags.len() as i64);
         let wildcard = format!("%{}%", _query);
-        let mut q: Vec<TagQueryOutput> =
-            query_as("SELECT Tag.* , COUNT(tag) AS tag_count , TagDetail.* FROM Tag LEFT JOIN HashTagPair ON HashTagPair.tag_name = Tag.name LEFT JOIN TagDetail ON TagDetail.name = Tag.name WHERE tag_name LIKE ? GROUP BY Tag.name ORDER BY tag_count DESC LIMIT ?")
-                .bind(wildcard)
-                .bind(new_limit)
-                .fetch_all(pool)
-                .await
-                .unwrap();
+        let mut q: Vec<TagQueryOutput> = tag_query(wildcard, new_limit)
+            .build_query_as()
+            .fetch_all(pool)
+            .await
+            .unwrap();
 
         tags.append(&mut q);
     }
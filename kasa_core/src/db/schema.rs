@@ -5,10 +5,10 @@ use strum::EnumString;
 
 use sqlx::types::Json as SqlxJson;
 
-use crate::tags::presets::TagPresetData;
+use crate::tags::search::SearchCriteria;
 
 /// Info about Media of all types
-#[derive(FromRow, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(FromRow, Serialize, Deserialize, Debug, Clone, PartialEq, specta::Type)]
 pub struct Media {
     pub hash: String,
     pub media_type: String,
@@ -61,6 +61,34 @@ pub struct Path {
     pub hash: String,
     pub path: String,
     pub imported_from: String, // TODO add this
+    /// Size of the file when it was last hashed
+    pub filesize: Option<i64>,
+    /// Modification time of the file in nanoseconds when it was last hashed
+    pub mtime: Option<i64>,
+}
+
+/// Files that couldn't be indexed in the last index of their source
+#[derive(Debug, Clone, PartialEq, FromRow, Serialize, Deserialize, specta::Type)]
+pub struct IndexError {
+    pub path: String,
+    pub imported_from: String,
+    pub stage: IndexStage,
+    /// `io::ErrorKind` of the error, `Database` if it couldn't be written to the db
+    pub kind: String,
+    pub message: String,
+    pub time: i64,
+}
+
+/// The step of indexing a file failed at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, specta::Type)]
+pub enum IndexStage {
+    /// Walking the directories of the index source
+    Walk,
+    /// Reading the size and the modification time of the file
+    Metadata,
+    Hash,
+    /// Writing the file and its metadata to the db
+    Write,
 }
 
 /// Basic `Tag` table only used for tag names and FTS searching in tags
@@ -70,7 +98,7 @@ pub struct Tag {
 }
 
 /// File-tag pairs
-#[derive(Serialize, Deserialize, FromRow, Debug, Clone, specta::Type)]
+#[derive(Serialize, Deserialize, FromRow, Debug, Clone, PartialEq, specta::Type)]
 pub struct HashTagPair {
     pub hash: String,
     pub tag_name: String,
@@ -87,6 +115,29 @@ pub struct Image {
     pub resolution_y: i64,
 }
 
+/// Camera metadata of `Image`s from their EXIF data
+#[derive(Debug, Default, FromRow, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub struct Exif {
+    pub hash: String,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub lens: Option<String>,
+    /// In seconds
+    pub exposure_time: Option<f64>,
+    pub f_number: Option<f64>,
+    pub iso: Option<i64>,
+    /// In millimeters
+    pub focal_length: Option<f64>,
+    /// `DateTimeOriginal` as an Unix timestamp in milliseconds, in local time if the file has no offset
+    pub taken_at: Option<i64>,
+    /// EXIF orientation, 1 is upright
+    pub orientation: Option<i64>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// In meters, negative below sea level
+    pub altitude: Option<f64>,
+}
+
 /// Raw user input of the tags field
 #[derive(Debug, FromRow, Clone)]
 pub struct RawTagsField {
@@ -109,10 +160,11 @@ pub struct TagDetail {
     override_group_color: bool,
 }
 
-#[allow(unused)]
+/// Group of tags, tags reference it by name in `TagDetail._group`
+#[derive(Debug, FromRow, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
 pub struct TagGroup {
-    name: String,
-    color: Option<String>,
+    pub name: String,
+    pub color: Option<String>,
 }
 
 #[allow(unused)]
@@ -135,5 +187,5 @@ pub struct MediaSource {
 #[derive(FromRow, Clone, Serialize, Deserialize)]
 pub struct TagPreset {
     pub name: String,
-    pub preset: SqlxJson<TagPresetData>,
+    pub preset: SqlxJson<SearchCriteria>,
 }
@@ -9,8 +9,9 @@ use sqlx::{Pool, Sqlite, query_scalar};
 use thiserror::Error;
 
 use crate::{
-    config::global_config::get_config_impl, index::indexer::index,
-    tags::insert_tags_with_source_types,
+    config::global_config::get_config_impl,
+    index::indexer::index,
+    tags::{GALLERY_DL_SOURCE, insert_tags_with_source_types},
 };
 
 // fuck...
@@ -51,8 +52,13 @@ pub async fn download_and_index_impl<F: Fn() + Send + Sync>(
 
         //dbg!(&extractor.get_tags());
 
-        insert_tags_with_source_types(extractor.get_tags(extractors)?, pool, Some(hash), None)
-            .await;
+        insert_tags_with_source_types(
+            extractor.get_tags(extractors)?,
+            pool,
+            Some(hash),
+            Some(GALLERY_DL_SOURCE.to_string()),
+        )
+        .await;
     }
 
     when_done();
@@ -5,12 +5,13 @@ use sqlx::{Pool, QueryBuilder, Sqlite, prelude::FromRow, query, query_as, query_
 use xxhash_rust::xxh3::xxh3_64;
 
 use crate::{
-    db::schema::{MediaType, media_type_to_string},
+    db::{
+        MAX_BINDS,
+        schema::{MediaType, media_type_to_string},
+    },
     media::{MediaInfo, get_info_impl},
 };
 
-const MAX_BINDS: usize = 32766;
-
 #[allow(unused)]
 async fn create_group(
     media_hashes: Vec<String>,
@@ -0,0 +1,117 @@
+use std::{fmt::Display, io};
+
+use chrono::Utc;
+use sqlx::{Pool, QueryBuilder, Sqlite, query, query_as};
+
+use crate::db::{
+    MAX_BINDS,
+    schema::{IndexError, IndexStage},
+};
+
+/// Error of a file that failed while it was being indexed from `source`
+pub(super) fn index_error(
+    path: &str,
+    source: &str,
+    stage: IndexStage,
+    kind: &str,
+    error: &impl Display,
+) -> IndexError {
+    IndexError {
+        path: path.to_string(),
+        imported_from: source.to_string(),
+        stage,
+        kind: kind.to_string(),
+        message: error.to_string(),
+        time: Utc::now().timestamp_millis(),
+    }
+}
+
+pub(super) fn io_error(
+    path: &str,
+    source: &str,
+    stage: IndexStage,
+    error: &io::Error,
+) -> IndexError {
+    index_error(path, source, stage, &format!("{:?}", error.kind()), error)
+}
+
+pub(super) fn database_error(path: &str, source: &str, error: &sqlx::Error) -> IndexError {
+    index_error(path, source, IndexStage::Write, "Database", error)
+}
+
+/// Stores the errors, replacing the earlier errors of the same paths
+pub async fn write_index_errors_impl(
+    errors: &[IndexError],
+    pool: &Pool<Sqlite>,
+) -> sqlx::Result<()> {
+    for chunk in errors.chunks(MAX_BINDS / 6) {
+        let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(
+            "INSERT OR REPLACE INTO IndexError(path, imported_from, stage, kind, message, time) ",
+        );
+        query_builder.push_values(chunk, |mut b, error| {
+            b.push_bind(&error.path)
+                .push_bind(&error.imported_from)
+                .push_bind(error.stage)
+                .push_bind(&error.kind)
+                .push_bind(&error.message)
+                .push_bind(error.time);
+        });
+
+        query_builder.build().execute(pool).await?;
+    }
+
+    Ok(())
+}
+
+/// Removes the errors of the index source, done before it gets indexed again
+pub async fn clear_index_errors_impl(imported_from: &str, pool: &Pool<Sqlite>) -> sqlx::Result<()> {
+    query("DELETE FROM IndexError WHERE imported_from = ?")
+        .bind(imported_from)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Gets the errors of all index sources
+pub async fn get_index_errors_impl(pool: &Pool<Sqlite>) -> sqlx::Result<Vec<IndexError>> {
+    query_as("SELECT * FROM IndexError ORDER BY imported_from, path")
+        .fetch_all(pool)
+        .await
+}
+
+#[sqlx::test]
+async fn test_index_errors(pool: Pool<Sqlite>) {
+    use sqlx::migrate;
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+    let errors = [
+        io_error("/a/1.png", "/a", IndexStage::Hash, &denied),
+        io_error("/a/2.png", "/a", IndexStage::Metadata, &denied),
+        io_error("/b/3.png", "/b", IndexStage::Walk, &denied),
+    ];
+    write_index_errors_impl(&errors, &pool).await.unwrap();
+
+    // the same path fails again in a later stage
+    let locked = sqlx::Error::Protocol("database is locked".to_string());
+    write_index_errors_impl(&[database_error("/a/1.png", "/a", &locked)], &pool)
+        .await
+        .unwrap();
+
+    let stored = get_index_errors_impl(&pool).await.unwrap();
+    assert_eq!(stored.len(), 3);
+    assert_eq!(
+        (stored[0].stage, stored[0].kind.as_str()),
+        (IndexStage::Write, "Database")
+    );
+    assert_eq!(stored[1], errors[1]);
+    assert_eq!(stored[1].kind, "PermissionDenied");
+
+    clear_index_errors_impl("/a", &pool).await.unwrap();
+    assert_eq!(
+        get_index_errors_impl(&pool).await.unwrap(),
+        vec![errors[2].clone()]
+    );
+}
@@ -0,0 +1,240 @@
+use std::{fs::File, io::BufReader};
+
+use chrono::{FixedOffset, Local, NaiveDate, TimeZone};
+use exif::{In, Tag, Value};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::db::schema::Exif;
+
+use super::media_types::FirstPass;
+
+/// Reads the EXIF data of a batch of images, images without any are skipped
+pub fn index_exif_batch(first_passes: &[FirstPass]) -> Vec<Exif> {
+    first_passes
+        .par_iter()
+        .filter_map(|img| {
+            let file = File::open(&img.path).ok()?;
+            let exif = exif::Reader::new()
+                .read_from_container(&mut BufReader::new(file))
+                .ok()?;
+
+            let exif = exif_from_fields(&img.hash, &exif);
+            let empty = Exif {
+                hash: img.hash.clone(),
+                ..Default::default()
+            };
+            (exif != empty).then_some(exif)
+        })
+        .collect()
+}
+
+fn exif_from_fields(hash: &str, exif: &exif::Exif) -> Exif {
+    let value = |tag| exif.get_field(tag, In::PRIMARY).map(|field| &field.value);
+    let text = |tag| value(tag).and_then(ascii);
+    let decimal = |tag| value(tag).and_then(rational);
+    let uint = |tag| {
+        value(tag)
+            .and_then(|value| value.get_uint(0))
+            .map(i64::from)
+    };
+
+    let taken_at = value(Tag::DateTimeOriginal)
+        .and_then(|date_time| date_time_millis(date_time, value(Tag::OffsetTimeOriginal)));
+
+    let altitude = decimal(Tag::GPSAltitude).map(|altitude| {
+        // 1 is below sea level
+        match value(Tag::GPSAltitudeRef).and_then(|value| value.get_uint(0)) {
+            Some(1) => -altitude,
+            _ => altitude,
+        }
+    });
+
+    Exif {
+        hash: hash.to_string(),
+        make: text(Tag::Make),
+        model: text(Tag::Model),
+        lens: text(Tag::LensModel),
+        exposure_time: decimal(Tag::ExposureTime),
+        f_number: decimal(Tag::FNumber),
+        iso: uint(Tag::PhotographicSensitivity),
+        focal_length: decimal(Tag::FocalLength),
+        taken_at,
+        orientation: uint(Tag::Orientation),
+        latitude: coordinate(value(Tag::GPSLatitude), text(Tag::GPSLatitudeRef), "S"),
+        longitude: coordinate(value(Tag::GPSLongitude), text(Tag::GPSLongitudeRef), "W"),
+        altitude,
+    }
+}
+
+fn ascii(value: &Value) -> Option<String> {
+    let Value::Ascii(values) = value else {
+        return None;
+    };
+
+    let text = String::from_utf8_lossy(values.first()?)
+        .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+        .to_string();
+
+    (!text.is_empty()).then_some(text)
+}
+
+fn rational(value: &Value) -> Option<f64> {
+    let decimal = match value {
+        Value::Rational(values) => values.first()?.to_f64(),
+        Value::SRational(values) => values.first()?.to_f64(),
+        _ => return None,
+    };
+
+    decimal.is_finite().then_some(decimal)
+}
+
+/// Degrees, minutes and seconds as signed degrees, negative for the `negative` reference
+fn coordinate(value: Option<&Value>, reference: Option<String>, negative: &str) -> Option<f64> {
+    let Some(Value::Rational(dms)) = value else {
+        return None;
+    };
+
+    let degrees = dms
+        .iter()
+        .zip([1.0, 60.0, 3600.0])
+        .map(|(part, divisor)| part.to_f64() / divisor)
+        .sum::<f64>();
+    if !degrees.is_finite() {
+        return None;
+    }
+
+    match reference {
+        Some(reference) if reference.eq_ignore_ascii_case(negative) => Some(-degrees),
+        _ => Some(degrees),
+    }
+}
+
+/// `DateTimeOriginal` as milliseconds, it has no timezone so it is local time unless there is an offset
+fn date_time_millis(date_time: &Value, offset: Option<&Value>) -> Option<i64> {
+    let Value::Ascii(values) = date_time else {
+        return None;
+    };
+    let mut date_time = exif::DateTime::from_ascii(values.first()?).ok()?;
+
+    if let Some(Value::Ascii(offset)) = offset
+        && let Some(offset) = offset.first()
+    {
+        // a broken offset is treated like a missing one
+        let _ = date_time.parse_offset(offset);
+    }
+
+    let naive = NaiveDate::from_ymd_opt(
+        date_time.year.into(),
+        date_time.month.into(),
+        date_time.day.into(),
+    )?
+    .and_hms_opt(
+        date_time.hour.into(),
+        date_time.minute.into(),
+        date_time.second.into(),
+    )?;
+
+    let taken_at = match date_time.offset {
+        Some(offset) => FixedOffset::east_opt(i32::from(offset) * 60)?
+            .from_local_datetime(&naive)
+            .earliest()?
+            .timestamp_millis(),
+        None => Local
+            .from_local_datetime(&naive)
+            .earliest()?
+            .timestamp_millis(),
+    };
+
+    Some(taken_at)
+}
+
+#[test]
+fn test_index_exif() {
+    use exif::{Field, Rational, experimental::Writer};
+
+    let field = |tag, value| Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value,
+    };
+    let ascii = |text: &str| Value::Ascii(vec![text.as_bytes().to_vec()]);
+    let rational = |values: &[(u32, u32)]| {
+        Value::Rational(
+            values
+                .iter()
+                .map(|&(num, denom)| Rational { num, denom })
+                .collect(),
+        )
+    };
+
+    let fields = [
+        field(Tag::Make, ascii("FUJIFILM")),
+        field(Tag::Model, ascii("X100V ")),
+        field(Tag::Orientation, Value::Short(vec![6])),
+        field(Tag::ExposureTime, rational(&[(1, 250)])),
+        field(Tag::FNumber, rational(&[(28, 10)])),
+        field(Tag::PhotographicSensitivity, Value::Short(vec![400])),
+        field(Tag::FocalLength, rational(&[(23, 1)])),
+        field(Tag::DateTimeOriginal, ascii("2023:05:14 18:30:00")),
+        field(Tag::OffsetTimeOriginal, ascii("+09:00")),
+        field(Tag::GPSLatitudeRef, ascii("N")),
+        field(Tag::GPSLatitude, rational(&[(35, 1), (30, 1), (0, 1)])),
+        field(Tag::GPSLongitudeRef, ascii("W")),
+        field(Tag::GPSLongitude, rational(&[(139, 1), (45, 1), (36, 1)])),
+        field(Tag::GPSAltitudeRef, Value::Byte(vec![1])),
+        field(Tag::GPSAltitude, rational(&[(5, 2)])),
+    ];
+    let mut writer = Writer::new();
+    for field in &fields {
+        writer.push_field(field);
+    }
+    let mut tiff = std::io::Cursor::new(vec![]);
+    writer.write(&mut tiff, false).unwrap();
+    let tiff = tiff.into_inner();
+
+    // the EXIF data in the APP1 segment of a JPEG
+    let mut jpeg = b"\xFF\xD8\xFF\xE1".to_vec();
+    jpeg.extend((tiff.len() as u16 + 8).to_be_bytes());
+    jpeg.extend(b"Exif\0\0");
+    jpeg.extend(tiff);
+    jpeg.extend(b"\xFF\xD9");
+
+    let dir = tempfile::tempdir().unwrap();
+    let with_exif = dir.path().join("1.jpg");
+    let without_exif = dir.path().join("2.jpg");
+    std::fs::write(&with_exif, jpeg).unwrap();
+    std::fs::write(&without_exif, b"\xFF\xD8\xFF\xD9").unwrap();
+
+    let first_pass = |hash: &str, path: &std::path::Path| FirstPass {
+        hash: hash.to_string(),
+        path: path.to_string_lossy().to_string(),
+        mime: "image/jpeg".to_string(),
+        size: 0,
+        mtime: 0,
+    };
+    let exif = index_exif_batch(&[first_pass("1", &with_exif), first_pass("2", &without_exif)]);
+
+    let taken_at = FixedOffset::east_opt(9 * 3600)
+        .unwrap()
+        .with_ymd_and_hms(2023, 5, 14, 18, 30, 0)
+        .unwrap()
+        .timestamp_millis();
+    assert_eq!(
+        exif,
+        vec![Exif {
+            hash: "1".to_string(),
+            make: Some("FUJIFILM".to_string()),
+            model: Some("X100V".to_string()),
+            lens: None,
+            exposure_time: Some(0.004),
+            f_number: Some(2.8),
+            iso: Some(400),
+            focal_length: Some(23.0),
+            taken_at: Some(taken_at),
+            orientation: Some(6),
+            latitude: Some(35.5),
+            longitude: Some(-139.76),
+            altitude: Some(-2.5),
+        }]
+    );
+}
@@ -1,6 +1,11 @@
 use sqlx::{Pool, QueryBuilder, Sqlite, query, query_scalar};
 
-use super::indexer::index;
+use super::{
+    indexer::index_with_progress,
+    progress::{CancellationToken, IndexProgress, IndexReport},
+};
+use crate::tags::cleanup::cleanup_orphaned_tags_impl;
+
 /// Adds a single index source from the path, does not index that path without calling index_path()
 pub async fn add_index_source_impl(path: &str, pool: &Pool<Sqlite>) {
     query("INSERT INTO IndexSource(path) VALUES (?)")
@@ -28,17 +33,32 @@ pub async fn remove_index_source_impl(path: &str, pool: &Pool<Sqlite>) {
     query("UPDATE Media SET has_file_ref = false WHERE NOT EXISTS (SELECT 1 FROM Path WHERE Path.hash = Media.hash)").execute(pool).await.unwrap();
 }
 
-/// Indexes all paths stored in the db
-pub async fn index_all_impl(pool: &Pool<Sqlite>, pool_thumbs: &Pool<Sqlite>) {
+/// Indexes all paths stored in the db, the progress of each of them is reported separately with `on_progress`
+///
+/// The paths after the one being indexed when `cancel` gets cancelled are skipped, only failing to get the paths
+/// fails the whole index
+pub async fn index_all_impl<F: Fn(&IndexProgress) + Send + Sync>(
+    pool: &Pool<Sqlite>,
+    pool_thumbs: &Pool<Sqlite>,
+    on_progress: F,
+    cancel: &CancellationToken,
+) -> sqlx::Result<Vec<IndexReport>> {
     let paths: Vec<String> = query_scalar("SELECT * FROM IndexSource")
         .fetch_all(pool)
-        .await
-        .unwrap();
+        .await?;
+
+    let mut reports = vec![];
 
     // not parallelized as indexing is parallel anyways
     for path in paths {
-        index(&path, pool, pool_thumbs).await;
+        if cancel.is_cancelled() {
+            break;
+        }
+
+        reports.push(index_with_progress(&path, pool, pool_thumbs, &on_progress, cancel).await);
     }
+
+    Ok(reports)
 }
 
 /// Gets all indexed paths stored in the db
@@ -54,12 +74,15 @@ pub async fn get_index_paths_impl(pool: &Pool<Sqlite>) -> Vec<String> {
 pub async fn cleanup_unreferenced_files_impl(pool: &Pool<Sqlite>, pool_thumbs: &Pool<Sqlite>) {
     query("DELETE FROM HashTagPair WHERE HashTagPair.hash IN (SELECT Media.hash FROM Media WHERE Media.has_file_ref = false)").execute(pool).await.unwrap();
     query("DELETE FROM Image WHERE Image.hash IN (SELECT Media.hash FROM Media WHERE Media.has_file_ref = false)").execute(pool).await.unwrap();
+    query("DELETE FROM Exif WHERE Exif.hash IN (SELECT Media.hash FROM Media WHERE Media.has_file_ref = false)").execute(pool).await.unwrap();
     query("DELETE FROM MediaGroupEntry WHERE MediaGroupEntry.hash IN (SELECT Media.hash FROM Media WHERE Media.has_file_ref = false)").execute(pool).await.unwrap();
     query("DELETE FROM Media WHERE has_file_ref = false")
         .execute(pool)
         .await
         .unwrap();
 
+    cleanup_orphaned_tags_impl(false, pool).await.unwrap();
+
     let hashes_to_delete: Vec<String> =
         query_scalar("SELECT Media.hash FROM Media WHERE Media.has_file_ref = false")
             .fetch_all(pool)
@@ -106,6 +129,12 @@ pub async fn nuke_selected_index_impl(
         .await
         .unwrap();
 
+    query("DELETE FROM Exif WHERE Exif.hash IN (SELECT Path.hash FROM Path WHERE Path.imported_from = ? GROUP BY Path.path HAVING COUNT(*) =1)")
+        .bind(path)
+        .execute(pool)
+        .await
+        .unwrap();
+
     // delete any group entries
     query("DELETE FROM MediaGroupEntry WHERE MediaGroupEntry.hash IN (SELECT Path.hash FROM Path WHERE Path.imported_from = ? GROUP BY Path.path HAVING COUNT(*) =1)")
         .bind(path)
@@ -1,16 +1,32 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    time::Instant,
+};
+
 use itertools::Itertools;
-use sqlx::{Pool, Sqlite};
+use log::error;
+use sqlx::{Pool, QueryBuilder, Sqlite};
 
 use crate::{
+    db::schema::{IndexError, IndexStage, MediaType},
     index::{
-        indexer_first::index_first_batch, indexer_second::indexer_second_batch,
+        index_errors::{
+            clear_index_errors_impl, database_error, io_error, write_index_errors_impl,
+        },
+        indexer_first::index_first_batch,
+        indexer_second::indexer_second_batch,
+        media_types::{FirstPass, KnownFile},
+        paths::{prune_missing_paths_impl, update_file_refs_impl},
+        postprocess::embedded_tags::get_embedded_tags_from_ids_batch,
+        progress::{CancellationToken, IndexProgress, IndexReport},
         write_to_db::write_to_db,
     },
     supported_formats::get_type,
 };
 use walkdir::WalkDir;
 
-const CHUNK_SIZE: usize = 1000;
+pub(super) const CHUNK_SIZE: usize = 1000;
 
 /// Inserting thousands of rows sequentially to a Sqlite database is really slow, this would be easily solved
 /// by simply bulking the inserts but we have multiple tables to insert because of MediaType specific metadata
@@ -22,7 +38,8 @@ const CHUNK_SIZE: usize = 1000;
 ///
 ///
 /// First Pass:
-/// Filters the files to only include supported types
+/// Filters the files to only include supported types, files whose size and modification time match the ones
+/// stored in `Path` are skipped without being hashed again
 /// Files are packed into `Vec<FirstPass>` with some basic metadata
 ///
 /// Files are then categorized with their types to batches
@@ -40,39 +57,311 @@ const CHUNK_SIZE: usize = 1000;
 ///
 /// `Vec<GenericMetadata>` : Basic metadata that is required for all media types,
 ///
-/// All of them are bulk inserted with write_to_db_function
+/// All of them are bulk inserted with write_to_db_function, `Path` rows of files that were moved are pointed to their
+/// new paths and the ones of files that are gone are removed after the whole source is indexed
 ///
 ///
+/// Files that can't be read or written don't stop the rest of the source, their errors are stored in `IndexError` and
+/// returned in the `IndexReport`
+///
 /// This is done in chunks so it doesn't set someones pc on fire when someone tries to index 1 million files
 ///
 /// TODOS?: Inserting all GenericData before grouping them could be better, is this good enough? There shouldn't be
 /// noticeable difference. Optimize this later!
 ///
 /// In that case second_pass should only return Vec<MediaTypeWithData>
-pub async fn index(path: &str, pool: &Pool<Sqlite>, pool_thumbs: &Pool<Sqlite>) {
+pub async fn index(path: &str, pool: &Pool<Sqlite>, pool_thumbs: &Pool<Sqlite>) -> IndexReport {
+    index_with_progress(
+        path,
+        pool,
+        pool_thumbs,
+        |_| {},
+        &CancellationToken::default(),
+    )
+    .await
+}
+
+/// `index` that calls `on_progress` after every chunk and stops before the next chunk when `cancel` is cancelled
+pub async fn index_with_progress<F: Fn(&IndexProgress) + Send + Sync>(
+    path: &str,
+    pool: &Pool<Sqlite>,
+    pool_thumbs: &Pool<Sqlite>,
+    on_progress: F,
+    cancel: &CancellationToken,
+) -> IndexReport {
+    let started = Instant::now();
+    let mut report = IndexReport {
+        progress: IndexProgress::new(path),
+        errors: vec![],
+    };
+
+    if let Err(e) = clear_index_errors_impl(path, pool).await {
+        error!("Couldn't clear the previous index errors of {path}: {e}");
+    }
+
     let mut walkdir = WalkDir::new(path)
         .into_iter()
-        .filter_map(|p| p.ok())
         //.filter(|p| p.file_type().is_file())
         //.filter_map(|p| p.path().to_str().map(String::from))
         .peekable();
 
     while walkdir.peek().is_some() {
-        let chunk: Chunk = walkdir.by_ref().take(CHUNK_SIZE).collect();
+        if cancel.is_cancelled() {
+            report.progress.cancelled = true;
+            break;
+        }
 
-        let first_passes = index_first_batch(chunk);
+        let (chunk, walk_errors): (Chunk, Vec<walkdir::Error>) =
+            walkdir.by_ref().take(CHUNK_SIZE).partition_result();
 
-        let first_pass_groups = first_passes
+        let mut errors = walk_errors
             .into_iter()
-            .map(|p| (get_type(&p.mime), p))
-            .into_group_map();
+            .map(|e| {
+                let error_path = e
+                    .path()
+                    .map_or(path.to_string(), |p| p.to_string_lossy().to_string());
+                io_error(&error_path, path, IndexStage::Walk, &io::Error::from(e))
+            })
+            .collect_vec();
+        errors.extend(index_chunk(chunk, path, pool, pool_thumbs, &mut report.progress).await);
+
+        if let Err(e) = write_index_errors_impl(&errors, pool).await {
+            error!("Couldn't store the index errors of {path}: {e}");
+        }
+        report.progress.failed += errors.len() as u64;
+        report.errors.extend(errors);
+
+        report.progress.update_throughput(started);
+        on_progress(&report.progress);
+    }
+
+    // moved files already took their rows with them, anything left is gone from the disk
+    if let Err(e) = prune_missing_paths_impl(path, pool).await {
+        error!("Couldn't prune the missing paths of {path}: {e}");
+    }
+    if let Err(e) = update_file_refs_impl(pool).await {
+        error!("Couldn't update the file references: {e}");
+    }
+
+    report
+}
+
+/// Runs both passes for the files of a chunk and writes them to the db, `source` is the `IndexSource` they are
+/// imported from
+///
+/// Returns the errors of the files that couldn't be indexed, the others are indexed regardless
+pub(super) async fn index_chunk(
+    chunk: Chunk,
+    source: &str,
+    pool: &Pool<Sqlite>,
+    pool_thumbs: &Pool<Sqlite>,
+    progress: &mut IndexProgress,
+) -> Vec<IndexError> {
+    let files = chunk.iter().filter(|entry| entry.file_type().is_file());
+    progress.discovered += files.clone().count() as u64;
+    if let Some(last) = files.last() {
+        progress.current_path = Some(last.path().to_string_lossy().to_string());
+    }
+
+    // everything gets hashed again if the known files can't be looked up
+    let known_files = get_known_files(&chunk, pool).await.unwrap_or_else(|e| {
+        error!("Couldn't get the indexed files of the chunk: {e}");
+        HashMap::new()
+    });
+    let first_batch = index_first_batch(chunk, source, &known_files);
+    let mut errors = first_batch.errors;
+
+    progress.skipped += first_batch.skipped as u64;
+    progress.hashed += first_batch.first_passes.len() as u64;
+
+    let first_pass_groups = first_batch
+        .first_passes
+        .into_iter()
+        .map(|p| (get_type(&p.mime), p))
+        .into_group_map();
+
+    for (_type, group) in first_pass_groups {
+        let paths = group.iter().map(|p| p.path.clone()).collect_vec();
+        let hashes = group.iter().map(|p| p.hash.clone()).collect_vec();
+
+        let written = async {
+            let new_images = match _type {
+                MediaType::Image => get_new_hashes(&group, pool).await?,
+                _ => vec![],
+            };
 
-        for (_type, group) in first_pass_groups {
             let batch = indexer_second_batch(_type, group);
 
-            write_to_db(batch, _type, pool, pool_thumbs, path).await;
+            write_to_db(batch, _type, pool, pool_thumbs, source).await?;
+
+            Ok::<_, sqlx::Error>(new_images)
+        }
+        .await;
+
+        match written {
+            Ok(new_images) => {
+                progress.written += paths.len() as u64;
+
+                if let Err(e) = get_embedded_tags_from_ids_batch(&new_images, pool).await {
+                    error!(
+                        "Couldn't write the embedded tags of {} files to the db: {e}",
+                        new_images.len()
+                    );
+                    let new_images: HashSet<&String> = new_images.iter().collect();
+                    errors.extend(
+                        paths
+                            .iter()
+                            .zip(&hashes)
+                            .filter(|(_, hash)| new_images.contains(hash))
+                            .map(|(path, _)| database_error(path, source, &e)),
+                    );
+                }
+            }
+            Err(e) => {
+                error!(
+                    "Couldn't write a batch of {} files to the db: {e}",
+                    paths.len()
+                );
+                errors.extend(paths.iter().map(|p| database_error(p, source, &e)));
+            }
         }
     }
+
+    errors
+}
+
+/// Paths of the chunk that are already in `Path`, mapped by their paths
+async fn get_known_files(
+    chunk: &Chunk,
+    pool: &Pool<Sqlite>,
+) -> sqlx::Result<HashMap<String, KnownFile>> {
+    let paths = chunk
+        .iter()
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.path().to_string_lossy().to_string())
+        .collect_vec();
+    if paths.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let mut query_builder: QueryBuilder<Sqlite> =
+        QueryBuilder::new("SELECT path, hash, filesize, mtime FROM Path WHERE path IN (");
+    let mut separated = query_builder.separated(", ");
+    for path in &paths {
+        separated.push_bind(path);
+    }
+    separated.push_unseparated(")");
+
+    let known_files: Vec<KnownFile> = query_builder.build_query_as().fetch_all(pool).await?;
+
+    Ok(known_files
+        .into_iter()
+        .map(|known| (known.path.clone(), known))
+        .collect())
+}
+
+/// Hashes of the files that aren't in `Media` yet
+async fn get_new_hashes(
+    first_passes: &[FirstPass],
+    pool: &Pool<Sqlite>,
+) -> sqlx::Result<Vec<String>> {
+    let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT column1 FROM (VALUES ");
+    let mut separated = query_builder.separated(", ");
+    for first_pass in first_passes {
+        separated.push("(");
+        separated.push_bind_unseparated(&first_pass.hash);
+        separated.push_unseparated(")");
+    }
+    query_builder.push(") WHERE column1 NOT IN (SELECT hash FROM Media)");
+
+    query_builder.build_query_scalar().fetch_all(pool).await
 }
 
 pub type Chunk = Vec<walkdir::DirEntry>;
+
+#[sqlx::test]
+async fn test_index_cancelled(pool: Pool<Sqlite>) {
+    use sqlx::{migrate, query_scalar};
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().to_string_lossy().to_string();
+    std::fs::copy(
+        "src/ai_slop/test_assets/a1111_example.png",
+        dir.path().join("a.png"),
+    )
+    .unwrap();
+
+    let cancel = CancellationToken::default();
+    cancel.cancel();
+    let report = index_with_progress(&source, &pool, &pool, |_| {}, &cancel).await;
+
+    assert!(report.progress.cancelled);
+    assert_eq!(report.progress.discovered, 0);
+    let media_count: i64 = query_scalar("SELECT COUNT(*) FROM Media")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let path_count: i64 = query_scalar("SELECT COUNT(*) FROM Path")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!((media_count, path_count), (0, 0));
+}
+
+#[sqlx::test]
+async fn test_index_progress(pool: Pool<Sqlite>) {
+    use std::{
+        fs::File,
+        time::{Duration, SystemTime},
+    };
+
+    use sqlx::migrate;
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().to_string_lossy().to_string();
+    for (asset, name) in [
+        ("a1111_example.png", "a.png"),
+        ("comfy_example.png", "b.png"),
+    ] {
+        std::fs::copy(
+            format!("src/ai_slop/test_assets/{asset}"),
+            dir.path().join(name),
+        )
+        .unwrap();
+    }
+    // found while walking, but not a supported format
+    std::fs::write(dir.path().join("notes.txt"), "notes").unwrap();
+
+    let counters = |report: &IndexReport| {
+        let progress = &report.progress;
+        (
+            progress.discovered,
+            progress.hashed,
+            progress.skipped,
+            progress.written,
+        )
+    };
+
+    let report = index(&source, &pool, &pool).await;
+    assert_eq!(counters(&report), (3, 2, 0, 2));
+    assert!(!report.progress.cancelled);
+    assert!(report.errors.is_empty());
+
+    // nothing changed, both images are skipped
+    let report = index(&source, &pool, &pool).await;
+    assert_eq!(counters(&report), (3, 0, 2, 0));
+
+    // only the touched image is hashed again
+    File::options()
+        .write(true)
+        .open(dir.path().join("a.png"))
+        .unwrap()
+        .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1))
+        .unwrap();
+    let report = index(&source, &pool, &pool).await;
+    assert_eq!(counters(&report), (3, 1, 1, 1));
+}
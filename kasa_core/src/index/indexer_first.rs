@@ -1,33 +1,90 @@
+use std::{
+    collections::HashMap,
+    fs::Metadata,
+    io,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::UNIX_EPOCH,
+};
+
+use itertools::Itertools;
 use rayon::prelude::*;
 
+use crate::db::schema::{IndexError, IndexStage};
 use crate::supported_formats::SUPPORTED_FORMATS;
 use crate::xxhash::streaming_xxhash;
 
+use super::index_errors::io_error;
 use super::indexer::Chunk;
-use super::media_types::FirstPass;
+use super::media_types::{FirstPass, KnownFile};
 
 /// Only indexes the hashes and types of the file
 /// Inserting thousands of rows without grouping is really slow
 /// so we first group the items with their types to process them in the second pass later
 ///
-pub fn index_first_batch(chunk: Chunk) -> Vec<FirstPass> {
-    chunk
+/// Files in `known_files` whose size and modification time didn't change since they were hashed are skipped, files
+/// that can't be read end up in the errors instead of stopping the batch
+pub fn index_first_batch(
+    chunk: Chunk,
+    source: &str,
+    known_files: &HashMap<String, KnownFile>,
+) -> FirstBatch {
+    let skipped = AtomicUsize::new(0);
+
+    let results: Vec<Result<FirstPass, IndexError>> = chunk
         .into_par_iter()
         .filter_map(|chunk| {
             //if let Ok(f) = chunk {
             if chunk.file_type().is_file() {
-                let hash = streaming_xxhash(chunk.path());
                 let path = chunk.path();
+                let path_string = path.to_string_lossy().to_string();
+
+                // filter the unsupported formats out before reading them, TODO add a log here if it doesn't match
+                let mime = mime_guess::from_path(path)
+                    .first_or_octet_stream()
+                    .to_string();
+                if !SUPPORTED_FORMATS.contains(&mime.as_ref()) {
+                    return None;
+                }
+
+                let metadata = match chunk.metadata() {
+                    Ok(metadata) => metadata,
+                    Err(e) => {
+                        let e = io::Error::from(e);
+                        return Some(Err(io_error(
+                            &path_string,
+                            source,
+                            IndexStage::Metadata,
+                            &e,
+                        )));
+                    }
+                };
+                let size = metadata.len();
+                let mtime = get_mtime(&metadata);
+
+                if let Some(known) = known_files.get(&path_string)
+                    && known.filesize == Some(size as i64)
+                    && known.mtime == Some(mtime)
+                {
+                    skipped.fetch_add(1, Ordering::Relaxed);
+                    return None;
+                }
+
+                let hash = match streaming_xxhash(path) {
+                    Ok(hash) => hash,
+                    Err(e) => {
+                        return Some(Err(io_error(&path_string, source, IndexStage::Hash, &e)));
+                    }
+                };
 
                 let _media = FirstPass {
                     hash: hash.to_string(),
-                    path: path.to_string_lossy().to_string(),
-                    mime: mime_guess::from_path(path)
-                        .first_or_octet_stream()
-                        .to_string(),
+                    path: path_string,
+                    mime,
+                    size,
+                    mtime,
                 };
 
-                Some(_media)
+                Some(Ok(_media))
             } else {
                 // DirEntry is a path (or something like that)
                 None
@@ -37,12 +94,33 @@ pub fn index_first_batch(chunk: Chunk) -> Vec<FirstPass> {
             //     None
             //}
         })
-        .filter(|f| {
-            // filter the unsupported formats out, TODO add a log here if it doesn't match
-            let mime = mime_guess::from_path(&f.path)
-                .first_or_octet_stream()
-                .to_string();
-            SUPPORTED_FORMATS.contains(&mime.as_ref())
-        })
-        .collect()
+        .collect();
+
+    let (first_passes, errors) = results.into_iter().partition_result();
+
+    FirstBatch {
+        first_passes,
+        skipped: skipped.into_inner(),
+        errors,
+    }
+}
+
+/// The files of a chunk that were hashed
+pub struct FirstBatch {
+    /// Only the ones with supported formats
+    pub first_passes: Vec<FirstPass>,
+    /// Files that weren't hashed as they didn't change
+    pub skipped: usize,
+    /// Files that couldn't be read
+    pub errors: Vec<IndexError>,
+}
+
+/// Modification time in nanoseconds, 0 if the platform doesn't have it
+fn get_mtime(metadata: &Metadata) -> i64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_nanos() as i64)
+        .unwrap_or_default()
 }
@@ -1,9 +1,10 @@
-use std::{fs, os::unix::fs::MetadataExt};
-
 use chrono::Utc;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
-use crate::{db::schema::MediaType, index::index_image::index_image_batch};
+use crate::{
+    db::schema::MediaType,
+    index::{index_exif::index_exif_batch, index_image::index_image_batch},
+};
 
 use super::{
     media_types::{DbWritableMediaDataBatch, FirstPass, GenericMediaData, PathData},
@@ -35,6 +36,11 @@ pub fn indexer_second_batch(
         MediaType::Flash => vec![], // TODO flash meta
     };
 
+    let exif = match media_type {
+        MediaType::Image => index_exif_batch(&first_passes),
+        _ => vec![],
+    };
+
     let (generic_media_data, paths): (Vec<GenericMediaData>, Vec<PathData>) = first_passes
         .par_iter()
         .map(|i| {
@@ -42,7 +48,7 @@ pub fn indexer_second_batch(
 
             let generic_media_data = GenericMediaData {
                 hash: i.hash.clone(),
-                size: fs::metadata(&i.path).unwrap().size(),
+                size: i.size,
                 mime: i.mime.to_string(),
                 thumb_path: None,
                 time_added: Utc::now().timestamp_millis(),
@@ -53,6 +59,8 @@ pub fn indexer_second_batch(
             let path_data = PathData {
                 path: i.path.to_string(),
                 hash: i.hash.to_string(),
+                size: i.size,
+                mtime: i.mtime,
             };
 
             (generic_media_data, path_data)
@@ -64,5 +72,6 @@ pub fn indexer_second_batch(
         media_data,
         generic_media_data,
         paths,
+        exif,
     }
 }
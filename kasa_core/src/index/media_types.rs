@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use sqlx::prelude::FromRow;
 
-use crate::db::schema::{Image, MediaType};
+use crate::db::schema::{Exif, Image, MediaType};
 
 #[derive(Debug)]
 pub struct DbWritableMediaDataBatch {
@@ -9,12 +9,16 @@ pub struct DbWritableMediaDataBatch {
     pub media_data: Vec<MediaTypeWithData>,
     pub generic_media_data: Vec<GenericMediaData>,
     pub paths: Vec<PathData>,
+    /// Only images with EXIF data have an entry
+    pub exif: Vec<Exif>,
 }
 #[derive(Debug)]
 
 pub struct PathData {
     pub path: String,
     pub hash: String,
+    pub size: u64,
+    pub mtime: i64,
 }
 
 #[derive(Debug)]
@@ -52,4 +56,18 @@ pub struct FirstPass {
     pub hash: String,
     pub path: String,
     pub mime: String,
+    #[sqlx(skip)]
+    pub size: u64,
+    /// Modification time in nanoseconds
+    #[sqlx(skip)]
+    pub mtime: i64,
+}
+
+/// A path that was indexed before, along with the file metadata it had when it was hashed
+#[derive(FromRow, Debug)]
+pub struct KnownFile {
+    pub path: String,
+    pub hash: String,
+    pub filesize: Option<i64>,
+    pub mtime: Option<i64>,
 }
@@ -1,9 +1,14 @@
+pub mod index_errors;
+mod index_exif;
 mod index_image;
 pub mod index_sources;
 pub mod indexer;
 pub mod indexer_first;
 mod indexer_second;
 pub mod media_types;
+pub mod paths;
 pub mod postprocess;
+pub mod progress;
 mod thumbnail_sizes;
+pub mod watcher;
 mod write_to_db;
@@ -0,0 +1,253 @@
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+use sqlx::{Pool, QueryBuilder, Sqlite, query, query_scalar};
+
+use crate::db::MAX_BINDS;
+
+use super::media_types::PathData;
+
+/// Points the `Path` rows of a moved file or directory to its new location, paths that were already at the new
+/// location are replaced as the files there got overwritten
+pub async fn move_path_impl(
+    from: &str,
+    to: &str,
+    imported_from: &str,
+    pool: &Pool<Sqlite>,
+) -> sqlx::Result<()> {
+    remove_path_impl(to, pool).await?;
+
+    query("UPDATE OR REPLACE Path SET path = ? || substr(path, length(?) + 1), imported_from = ? WHERE path = ? OR substr(path, 1, length(?) + 1) = ? || '/'")
+        .bind(to)
+        .bind(from)
+        .bind(imported_from)
+        .bind(from)
+        .bind(from)
+        .bind(from)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Removes the `Path` rows of a file or everything under a directory
+pub async fn remove_path_impl(path: &str, pool: &Pool<Sqlite>) -> sqlx::Result<()> {
+    query("DELETE FROM Path WHERE path = ? OR substr(path, 1, length(?) + 1) = ? || '/'")
+        .bind(path)
+        .bind(path)
+        .bind(path)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Moves the `Path` rows of files that vanished from disk to the new paths their hashes reappeared at, so moved
+/// files keep their rows instead of getting new ones next to stale ones
+pub async fn reconcile_moved_paths_impl(
+    paths: &[PathData],
+    imported_from: &str,
+    pool: &Pool<Sqlite>,
+) -> sqlx::Result<()> {
+    if paths.is_empty() {
+        return Ok(());
+    }
+
+    let mut query_builder: QueryBuilder<Sqlite> =
+        QueryBuilder::new("SELECT hash, path FROM Path WHERE hash IN (");
+    let mut separated = query_builder.separated(", ");
+    for hash in paths.iter().map(|data| &data.hash).unique() {
+        separated.push_bind(hash);
+    }
+    separated.push_unseparated(")");
+    let known: Vec<(String, String)> = query_builder.build_query_as().fetch_all(pool).await?;
+
+    let known_pairs: HashSet<(&str, &str)> = known
+        .iter()
+        .map(|(hash, path)| (hash.as_str(), path.as_str()))
+        .collect();
+    let mut vanished: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (hash, path) in &known {
+        if !std::path::Path::new(path).exists() {
+            vanished.entry(hash).or_default().push(path);
+        }
+    }
+
+    for data in paths {
+        if known_pairs.contains(&(data.hash.as_str(), data.path.as_str())) {
+            continue;
+        }
+        let Some(old_path) = vanished.get_mut(data.hash.as_str()).and_then(Vec::pop) else {
+            continue;
+        };
+
+        query("UPDATE Path SET path = ?, imported_from = ? WHERE hash = ? AND path = ?")
+            .bind(&data.path)
+            .bind(imported_from)
+            .bind(&data.hash)
+            .bind(old_path)
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Removes the `Path` rows of the index source whose files don't exist on disk anymore
+pub async fn prune_missing_paths_impl(
+    imported_from: &str,
+    pool: &Pool<Sqlite>,
+) -> sqlx::Result<()> {
+    let paths: Vec<String> = query_scalar("SELECT path FROM Path WHERE imported_from = ?")
+        .bind(imported_from)
+        .fetch_all(pool)
+        .await?;
+
+    let missing = paths
+        .into_iter()
+        .filter(|path| !std::path::Path::new(path).exists())
+        .collect_vec();
+
+    for chunk in missing.chunks(MAX_BINDS - 1) {
+        let mut query_builder: QueryBuilder<Sqlite> =
+            QueryBuilder::new("DELETE FROM Path WHERE imported_from = ");
+        query_builder.push_bind(imported_from);
+        query_builder.push(" AND path IN (");
+        let mut separated = query_builder.separated(", ");
+        for path in chunk {
+            separated.push_bind(path);
+        }
+        separated.push_unseparated(")");
+
+        query_builder.build().execute(pool).await?;
+    }
+
+    Ok(())
+}
+
+/// Sets `has_file_ref` of all media according to whether they have any `Path`s left
+pub async fn update_file_refs_impl(pool: &Pool<Sqlite>) -> sqlx::Result<()> {
+    query(
+        "UPDATE Media SET has_file_ref = EXISTS (SELECT 1 FROM Path WHERE Path.hash = Media.hash)",
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_path_updates(pool: Pool<Sqlite>) {
+    use sqlx::{migrate, query_as, query_scalar};
+
+    use crate::test_util::db_utils::{insert_image, insert_path_row};
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    for (hash, path) in [
+        ("1", "/a/1.png"),
+        ("2", "/a/b/2.png"),
+        ("3", "/a/bc/3.png"),
+        ("4", "/c/4.png"),
+    ] {
+        insert_image(&pool, hash).await;
+        insert_path_row(&pool, hash, path, "/a").await;
+    }
+
+    let get_paths = async || -> Vec<(String, String, String)> {
+        query_as("SELECT hash, path, imported_from FROM Path ORDER BY hash")
+            .fetch_all(&pool)
+            .await
+            .unwrap()
+    };
+    let path = |hash: &str, path: &str, imported_from: &str| {
+        (
+            hash.to_string(),
+            path.to_string(),
+            imported_from.to_string(),
+        )
+    };
+
+    // `/a/bc` only shares a prefix with `/a/b`, it isn't inside it
+    move_path_impl("/a/b", "/c/b", "/c", &pool).await.unwrap();
+    assert_eq!(
+        get_paths().await,
+        vec![
+            path("1", "/a/1.png", "/a"),
+            path("2", "/c/b/2.png", "/c"),
+            path("3", "/a/bc/3.png", "/a"),
+            path("4", "/c/4.png", "/a"),
+        ]
+    );
+
+    // overwrites 4
+    move_path_impl("/a/1.png", "/c/4.png", "/c", &pool)
+        .await
+        .unwrap();
+    remove_path_impl("/a/bc", &pool).await.unwrap();
+    assert_eq!(
+        get_paths().await,
+        vec![path("1", "/c/4.png", "/c"), path("2", "/c/b/2.png", "/c")]
+    );
+
+    update_file_refs_impl(&pool).await.unwrap();
+    let unreferenced: Vec<String> =
+        query_scalar("SELECT hash FROM Media WHERE has_file_ref = false ORDER BY hash")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(unreferenced, vec!["3", "4"]);
+}
+
+#[sqlx::test]
+async fn test_reconcile_paths(pool: Pool<Sqlite>) {
+    use sqlx::{migrate, query_as};
+
+    use crate::test_util::db_utils::insert_path_row;
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().to_string_lossy().to_string();
+    let file = |name: &str| dir.path().join(name).to_string_lossy().to_string();
+    for name in ["kept.png", "moved.png", "copy.png"] {
+        std::fs::write(file(name), name).unwrap();
+    }
+
+    insert_path_row(&pool, "1", &file("kept.png"), &source).await;
+    insert_path_row(&pool, "2", &file("old/moved.png"), "/elsewhere").await;
+    insert_path_row(&pool, "3", &file("gone.png"), &source).await;
+
+    let path_data = |hash: &str, name: &str| PathData {
+        path: file(name),
+        hash: hash.to_string(),
+        size: 0,
+        mtime: 0,
+    };
+    reconcile_moved_paths_impl(
+        &[
+            path_data("1", "kept.png"),
+            path_data("1", "copy.png"),
+            path_data("2", "moved.png"),
+        ],
+        &source,
+        &pool,
+    )
+    .await
+    .unwrap();
+    prune_missing_paths_impl(&source, &pool).await.unwrap();
+
+    let paths: Vec<(String, String, String)> =
+        query_as("SELECT hash, path, imported_from FROM Path ORDER BY hash")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    // the copy of an existing file isn't a move, it gets its own row when the paths are written
+    assert_eq!(
+        paths,
+        vec![
+            ("1".to_string(), file("kept.png"), source.clone()),
+            ("2".to_string(), file("moved.png"), source.clone()),
+        ]
+    );
+}
@@ -10,7 +10,7 @@ use std::{fs::File, io::BufReader, path::PathBuf};
 use crate::{
     ai_slop::{SlopTag, comfy::ComfyExifJson},
     db::{self},
-    tags::insert_tags,
+    tags::{AI_PROMPT_SOURCE, insert_tags},
 };
 
 use crate::ai_slop::{
@@ -202,7 +202,7 @@ pub async fn get_prompt_tags_from_ids_batch(
                 t.tags,
                 pool,
                 Some(t.hash),
-                Some("ai_prompt_import".to_string()),
+                Some(AI_PROMPT_SOURCE.to_string()),
             )
             .await;
         }
@@ -0,0 +1,294 @@
+//! Tags read from the metadata other tools embed in images
+//!
+//! Keywords come from XMP `dc:subject`, IPTC keywords and the Windows `XPKeywords` EXIF field, captions from
+//! XMP `dc:description`, the IPTC caption and the EXIF `ImageDescription`, and the rating from XMP or EXIF.
+//! Captions are split on commas like the tag field, ratings become `rating_1` to `rating_5`. The tags are
+//! inserted with `EMBEDDED_SOURCE` as their source.
+
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use exif::{In, Tag};
+use itertools::Itertools;
+use kasa_python::ExtractedTag;
+use memchr::memmem;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use sqlx::{Pool, QueryBuilder, Sqlite};
+
+use crate::{
+    db,
+    tags::{
+        EMBEDDED_SOURCE, insert_media_tags_with_source_types, parse_tags,
+        xmp::{parse_xmp_description, parse_xmp_rating, parse_xmp_subjects},
+    },
+};
+
+/// `ExtractedTag._type` of keywords
+pub const KEYWORD_TYPE: &str = "Keyword";
+/// `ExtractedTag._type` of tags from captions
+pub const CAPTION_TYPE: &str = "Caption";
+/// `ExtractedTag._type` of ratings
+pub const RATING_TYPE: &str = "Rating";
+
+const EMBEDDED_PATHS_DB_LIMIT: usize = 1000;
+
+/// Windows keywords, UTF-16 separated with semicolons
+const EXIF_XP_KEYWORDS: Tag = Tag(exif::Context::Tiff, 0x9C9E);
+const EXIF_RATING: Tag = Tag(exif::Context::Tiff, 0x4746);
+
+/// IPTC-IIM datasets of the application record
+const IPTC_KEYWORDS: u8 = 25;
+const IPTC_CAPTION: u8 = 120;
+
+#[derive(Debug, Default, PartialEq)]
+struct EmbeddedMetadata {
+    keywords: Vec<String>,
+    captions: Vec<String>,
+    rating: Option<i64>,
+}
+
+impl EmbeddedMetadata {
+    fn into_tags(self) -> Vec<ExtractedTag> {
+        let keywords = self
+            .keywords
+            .iter()
+            .map(|keyword| keyword.trim())
+            .filter(|keyword| !keyword.is_empty())
+            .map(|keyword| ExtractedTag::new(KEYWORD_TYPE, keyword));
+
+        let captions = self
+            .captions
+            .iter()
+            .flat_map(|caption| parse_tags(caption))
+            .map(|tag| ExtractedTag::new(CAPTION_TYPE, tag));
+
+        let rating = self
+            .rating
+            .filter(|rating| (1..=5).contains(rating))
+            .map(|rating| ExtractedTag::new(RATING_TYPE, &format!("rating_{rating}")));
+
+        keywords
+            .chain(captions)
+            .chain(rating)
+            .unique_by(|tag| tag.name.clone())
+            .collect()
+    }
+}
+
+/// The XMP packet in the file, it is stored as plain text in every format that supports it
+fn find_xmp_packet(bytes: &[u8]) -> Option<String> {
+    for (start, end) in [
+        (&b"<x:xmpmeta"[..], &b"</x:xmpmeta>"[..]),
+        (b"<rdf:RDF", b"</rdf:RDF>"),
+    ] {
+        let Some(start) = memmem::find(bytes, start) else {
+            continue;
+        };
+        let Some(length) = memmem::find(&bytes[start..], end) else {
+            continue;
+        };
+
+        let packet = &bytes[start..start + length + end.len()];
+        return Some(String::from_utf8_lossy(packet).to_string());
+    }
+
+    None
+}
+
+/// IPTC keywords and caption from the Photoshop image resource block of the file
+fn parse_iptc(bytes: &[u8], metadata: &mut EmbeddedMetadata) {
+    // image resource 0x0404 is the IPTC-IIM data
+    let Some(resource) = memmem::find(bytes, b"8BIM\x04\x04") else {
+        return;
+    };
+    let rest = &bytes[resource + 6..];
+
+    // the name is a pascal string padded to an even length
+    let Some(&name_length) = rest.first() else {
+        return;
+    };
+    let name_length = (name_length as usize + 2) & !1;
+    let Some(size) = rest.get(name_length..name_length + 4) else {
+        return;
+    };
+    let size = u32::from_be_bytes(size.try_into().unwrap()) as usize;
+    let data = &rest[name_length + 4..];
+    let mut data = &data[..size.min(data.len())];
+
+    // datasets are `0x1C record dataset length(u16) value`
+    while let [0x1C, record, dataset, length_high, length_low, rest @ ..] = data {
+        let length = u16::from_be_bytes([*length_high, *length_low]) as usize;
+        // extended datasets aren't used by text fields
+        if length & 0x8000 != 0 || length > rest.len() {
+            return;
+        }
+
+        let value = String::from_utf8_lossy(&rest[..length]).to_string();
+        match (record, dataset) {
+            (2, &IPTC_KEYWORDS) => metadata.keywords.push(value),
+            (2, &IPTC_CAPTION) => metadata.captions.push(value),
+            _ => {}
+        }
+
+        data = &rest[length..];
+    }
+}
+
+fn parse_exif(bytes: &[u8], metadata: &mut EmbeddedMetadata) {
+    let Ok(exif) = exif::Reader::new().read_from_container(&mut Cursor::new(bytes)) else {
+        return;
+    };
+
+    if let Some(field) = exif.get_field(Tag::ImageDescription, In::PRIMARY)
+        && let exif::Value::Ascii(values) = &field.value
+    {
+        for value in values {
+            metadata
+                .captions
+                .push(String::from_utf8_lossy(value).to_string());
+        }
+    }
+
+    if let Some(field) = exif.get_field(EXIF_XP_KEYWORDS, In::PRIMARY)
+        && let exif::Value::Byte(bytes) = &field.value
+    {
+        let utf16: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .take_while(|c| *c != 0)
+            .collect();
+        let keywords = String::from_utf16_lossy(&utf16);
+        metadata
+            .keywords
+            .extend(keywords.split(';').map(str::to_string));
+    }
+
+    if metadata.rating.is_none() {
+        metadata.rating = exif
+            .get_field(EXIF_RATING, In::PRIMARY)
+            .and_then(|field| field.value.get_uint(0))
+            .map(i64::from);
+    }
+}
+
+/// Keywords, captions and the rating embedded in the image as tags
+pub fn get_embedded_tags_from_img(path: &Path) -> Result<Vec<ExtractedTag>> {
+    let bytes = std::fs::read(path)?;
+    let mut metadata = EmbeddedMetadata::default();
+
+    if let Some(xmp) = find_xmp_packet(&bytes) {
+        metadata.keywords.extend(parse_xmp_subjects(&xmp));
+        metadata.captions.extend(parse_xmp_description(&xmp));
+        metadata.rating = parse_xmp_rating(&xmp);
+    }
+    parse_iptc(&bytes, &mut metadata);
+    parse_exif(&bytes, &mut metadata);
+
+    Ok(metadata.into_tags())
+}
+
+/// Run after an index, only for newly indexed media so tags removed by hand don't come back
+///
+/// Images that can't be read are skipped, db errors stop the batch.
+pub async fn get_embedded_tags_from_ids_batch(
+    input_ids: &[String],
+    pool: &Pool<Sqlite>,
+) -> sqlx::Result<()> {
+    for ids_batch in input_ids.chunks(EMBEDDED_PATHS_DB_LIMIT) {
+        let mut query_builder: QueryBuilder<Sqlite> =
+            QueryBuilder::new("SELECT * FROM Path WHERE hash IN (");
+
+        let mut separated = query_builder.separated(", ");
+        for hash in ids_batch {
+            separated.push_bind(hash);
+        }
+        separated.push_unseparated(") ");
+
+        let paths: Vec<db::schema::Path> = query_builder.build_query_as().fetch_all(pool).await?;
+
+        // every path of a media has the same contents
+        let paths: Vec<db::schema::Path> = paths
+            .into_iter()
+            .unique_by(|path| path.hash.clone())
+            .collect();
+
+        let embedded_tags: Vec<(String, Vec<ExtractedTag>)> = paths
+            .into_par_iter()
+            .filter_map(|path| {
+                get_embedded_tags_from_img(&PathBuf::from(path.path))
+                    .ok()
+                    .map(|tags| (path.hash, tags))
+            })
+            .filter(|(_, tags)| !tags.is_empty())
+            .collect();
+
+        insert_media_tags_with_source_types(embedded_tags, EMBEDDED_SOURCE, pool).await?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_embedded_tags() {
+    use crate::tags::xmp::xmp_packet;
+
+    // an IPTC block, as written in the APP13 segment of JPEGs
+    let mut iptc = vec![];
+    for (dataset, value) in [
+        (IPTC_KEYWORDS, "harbor"),
+        (5, "Title"),
+        (IPTC_CAPTION, "boats, sunset"),
+    ] {
+        iptc.extend([0x1C, 2, dataset]);
+        iptc.extend((value.len() as u16).to_be_bytes());
+        iptc.extend(value.as_bytes());
+    }
+    let mut resource = b"Photoshop 3.0\x008BIM\x04\x04\x00\x00".to_vec();
+    resource.extend((iptc.len() as u32).to_be_bytes());
+    resource.extend(iptc);
+
+    let mut metadata = EmbeddedMetadata::default();
+    parse_iptc(&resource, &mut metadata);
+    assert_eq!(
+        metadata,
+        EmbeddedMetadata {
+            keywords: vec!["harbor".to_string()],
+            captions: vec!["boats, sunset".to_string()],
+            rating: None,
+        }
+    );
+
+    let mut file = b"\xFF\xD8garbage".to_vec();
+    file.extend(xmp_packet(&["sunset".to_string(), "sea".to_string()]).as_bytes());
+    file.extend(&resource);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("image.jpg");
+    std::fs::write(&path, file).unwrap();
+
+    let tags = get_embedded_tags_from_img(&path).unwrap();
+    assert_eq!(
+        tags,
+        vec![
+            ExtractedTag::new(KEYWORD_TYPE, "sunset"),
+            ExtractedTag::new(KEYWORD_TYPE, "sea"),
+            ExtractedTag::new(KEYWORD_TYPE, "harbor"),
+            ExtractedTag::new(CAPTION_TYPE, "boats"),
+        ]
+    );
+
+    // unrated and rejected images don't get a rating tag
+    let rating = |rating| {
+        EmbeddedMetadata {
+            rating: Some(rating),
+            ..Default::default()
+        }
+        .into_tags()
+    };
+    assert_eq!(rating(4), vec![ExtractedTag::new(RATING_TYPE, "rating_4")]);
+    assert_eq!(rating(0), vec![]);
+    assert_eq!(rating(-1), vec![]);
+}
@@ -1 +1,2 @@
 pub mod ai_indexer;
+pub mod embedded_tags;
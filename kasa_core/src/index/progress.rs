@@ -0,0 +1,75 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::db::schema::IndexError;
+
+/// Progress of indexing an index source, reported after every chunk
+#[derive(Debug, Clone, Default, Serialize, Deserialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexProgress {
+    /// The index source being indexed
+    pub source: String,
+    /// Files found while walking the source
+    pub discovered: u64,
+    /// Files of supported formats that were hashed
+    pub hashed: u64,
+    /// Files that weren't hashed again as their size and modification time didn't change
+    pub skipped: u64,
+    /// Files of supported formats written to the db
+    pub written: u64,
+    /// Files that couldn't be indexed, their errors are in `IndexError`
+    pub failed: u64,
+    /// The last path of the latest chunk
+    pub current_path: Option<String>,
+    /// Discovered files per second
+    pub throughput: f64,
+    /// The indexing was stopped with a `CancellationToken` before the whole source was walked
+    pub cancelled: bool,
+}
+
+impl IndexProgress {
+    pub fn new(source: &str) -> Self {
+        IndexProgress {
+            source: source.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub(super) fn update_throughput(&mut self, started: Instant) {
+        let elapsed = started.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            self.throughput = self.discovered as f64 / elapsed;
+        }
+    }
+}
+
+/// Result of indexing an index source
+#[derive(Debug, Clone, Default, Serialize, Deserialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexReport {
+    pub progress: IndexProgress,
+    /// Files that couldn't be indexed, the rest of the source is indexed regardless
+    pub errors: Vec<IndexError>,
+}
+
+/// Stops indexing between chunks after being cancelled, the clones share the same state so one can be kept to
+/// cancel the indexing from elsewhere
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
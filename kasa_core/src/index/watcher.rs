@@ -0,0 +1,252 @@
+use std::{collections::HashMap, path::Path, time::Duration};
+
+use indexmap::{IndexMap, IndexSet};
+use itertools::Itertools;
+use log::{error, trace};
+use notify::{
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+    event::{AccessKind, AccessMode, ModifyKind, RenameMode},
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite, query_scalar};
+use thiserror::Error;
+use tokio::{
+    sync::mpsc,
+    time::{Instant, timeout},
+};
+use walkdir::WalkDir;
+
+use crate::db::impl_from_sqlx_error;
+
+use super::{
+    index_errors::write_index_errors_impl,
+    indexer::{CHUNK_SIZE, Chunk, index_chunk},
+    paths::{move_path_impl, remove_path_impl, update_file_refs_impl},
+    progress::IndexProgress,
+};
+
+/// How long the events have to stop coming before they get applied, copying a directory creates lots of them
+const DEBOUNCE: Duration = Duration::from_millis(500);
+/// Longest time events are held back, so the files of a long copy show up while it's still going
+const MAX_DEBOUNCE: Duration = Duration::from_secs(5);
+
+/// Watches the index sources until it is dropped
+pub struct IndexWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+#[derive(Debug, Error, Serialize, Deserialize, specta::Type)]
+pub enum WatcherError {
+    #[error("Couldn't start the file watcher: {0}")]
+    Watch(String),
+    #[error("Database error: {0}")]
+    Database(String),
+}
+
+impl_from_sqlx_error!(WatcherError);
+
+/// Watches all `IndexSource`s (with inotify on Linux) and keeps the index in sync with them, new and modified files
+/// are indexed, moved ones keep their `Path` rows and deleted ones lose them
+///
+/// `on_update` is called after each debounced batch of changes is written to the db
+pub async fn watch_index_sources_impl(
+    pool: &Pool<Sqlite>,
+    pool_thumbs: &Pool<Sqlite>,
+    on_update: impl Fn() + Send + 'static,
+) -> Result<IndexWatcher, WatcherError> {
+    let sources: Vec<String> = query_scalar("SELECT path FROM IndexSource")
+        .fetch_all(pool)
+        .await?;
+
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        match event {
+            // the receiver is only gone when the watcher is being dropped
+            Ok(event) => _ = sender.send(event),
+            Err(e) => error!("File watcher error: {e}"),
+        }
+    })
+    .map_err(|e| WatcherError::Watch(e.to_string()))?;
+
+    for source in &sources {
+        // a missing source shouldn't stop the others from being watched
+        if let Err(e) = watcher.watch(Path::new(source), RecursiveMode::Recursive) {
+            error!("Couldn't watch the index source {source}: {e}");
+        }
+    }
+
+    let pool = pool.clone();
+    let pool_thumbs = pool_thumbs.clone();
+    tokio::spawn(async move {
+        while let Some(event) = receiver.recv().await {
+            let mut events = vec![event];
+            let flush_at = Instant::now() + MAX_DEBOUNCE;
+            while let Ok(Some(event)) = timeout(
+                DEBOUNCE.min(flush_at.saturating_duration_since(Instant::now())),
+                receiver.recv(),
+            )
+            .await
+            {
+                events.push(event);
+            }
+
+            trace!("Applying {} file events", events.len());
+            match apply_events(&events, &sources, &pool, &pool_thumbs).await {
+                Ok(()) => on_update(),
+                Err(e) => error!("Couldn't apply the file changes: {e}"),
+            }
+        }
+    });
+
+    Ok(IndexWatcher { _watcher: watcher })
+}
+
+/// Moves are applied in order, then every path that was touched is either indexed if it still exists or removed
+///
+/// inotify reports both sides of a move separately too, so files moved in or out of the sources are handled like
+/// created and deleted ones
+async fn apply_events(
+    events: &[Event],
+    sources: &[String],
+    pool: &Pool<Sqlite>,
+    pool_thumbs: &Pool<Sqlite>,
+) -> sqlx::Result<()> {
+    let mut changed = IndexSet::new();
+
+    for event in events {
+        match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                if let [from, to] = event.paths.as_slice()
+                    && let Some(source) = get_source(to, sources)
+                {
+                    move_path_impl(&from.to_string_lossy(), &to.to_string_lossy(), source, pool)
+                        .await?;
+                }
+                changed.extend(event.paths.iter().cloned());
+            }
+            EventKind::Create(_)
+            | EventKind::Modify(_)
+            | EventKind::Remove(_)
+            | EventKind::Access(AccessKind::Close(AccessMode::Write)) => {
+                changed.extend(event.paths.iter().cloned())
+            }
+            _ => {}
+        }
+    }
+
+    // files in changed directories come up twice, the upsert of `Path` can't have the same row twice
+    let mut to_index: HashMap<&str, IndexMap<_, _>> = HashMap::new();
+    for path in changed {
+        let Some(source) = get_source(&path, sources) else {
+            continue;
+        };
+
+        if path.exists() {
+            to_index.entry(source).or_default().extend(
+                WalkDir::new(&path)
+                    .into_iter()
+                    .filter_map(|p| p.ok())
+                    .map(|entry| (entry.path().to_path_buf(), entry)),
+            );
+        } else {
+            remove_path_impl(&path.to_string_lossy(), pool).await?;
+        }
+    }
+
+    for (source, entries) in to_index {
+        let chunks: Vec<Chunk> = entries
+            .into_values()
+            .chunks(CHUNK_SIZE)
+            .into_iter()
+            .map(|chunk| chunk.collect())
+            .collect();
+
+        for chunk in chunks {
+            let errors = index_chunk(
+                chunk,
+                source,
+                pool,
+                pool_thumbs,
+                &mut IndexProgress::new(source),
+            )
+            .await;
+
+            write_index_errors_impl(&errors, pool).await?;
+        }
+    }
+
+    update_file_refs_impl(pool).await
+}
+
+/// The innermost index source the path is in
+fn get_source<'a>(path: &Path, sources: &'a [String]) -> Option<&'a str> {
+    sources
+        .iter()
+        .filter(|source| path.starts_with(source))
+        .max_by_key(|source| source.len())
+        .map(String::as_str)
+}
+
+#[sqlx::test]
+async fn test_apply_events(pool: Pool<Sqlite>) {
+    use std::path::PathBuf;
+
+    use notify::event::{CreateKind, RemoveKind};
+    use sqlx::{migrate, query_as};
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let sources = vec![dir.path().to_string_lossy().to_string()];
+    let file = |name: &str| dir.path().join(name);
+    let event = |kind: EventKind, paths: &[PathBuf]| {
+        paths
+            .iter()
+            .fold(Event::new(kind), |event, path| event.add_path(path.clone()))
+    };
+    let get_state = async || -> Vec<(Option<String>, bool)> {
+        query_as(
+            "SELECT Path.path, Media.has_file_ref FROM Media LEFT JOIN Path ON Path.hash = Media.hash",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap()
+    };
+
+    std::fs::copy("src/ai_slop/test_assets/a1111_example.png", file("a.png")).unwrap();
+    // a file being copied is created and written to, it's indexed once
+    let created = [
+        event(EventKind::Create(CreateKind::File), &[file("a.png")]),
+        event(
+            EventKind::Access(AccessKind::Close(AccessMode::Write)),
+            &[file("a.png")],
+        ),
+    ];
+    apply_events(&created, &sources, &pool, &pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        get_state().await,
+        vec![(Some(file("a.png").to_string_lossy().to_string()), true)]
+    );
+
+    std::fs::rename(file("a.png"), file("b.png")).unwrap();
+    let renamed = [event(
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+        &[file("a.png"), file("b.png")],
+    )];
+    apply_events(&renamed, &sources, &pool, &pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        get_state().await,
+        vec![(Some(file("b.png").to_string_lossy().to_string()), true)]
+    );
+
+    std::fs::remove_file(file("b.png")).unwrap();
+    let removed = [event(EventKind::Remove(RemoveKind::File), &[file("b.png")])];
+    apply_events(&removed, &sources, &pool, &pool)
+        .await
+        .unwrap();
+    assert_eq!(get_state().await, vec![(None, false)]);
+}
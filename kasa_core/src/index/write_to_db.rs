@@ -1,11 +1,14 @@
 use core::hash;
 
 use log::trace;
-use sqlx::{Execute, Pool, QueryBuilder, Sqlite, query as sqlx_query};
+use sqlx::{Execute, Pool, QueryBuilder, Sqlite};
 
 use crate::db::schema::{MediaType, media_type_to_string};
 
-use super::media_types::{DbWritableMediaDataBatch, MediaTypeWithData};
+use super::{
+    media_types::{DbWritableMediaDataBatch, MediaTypeWithData},
+    paths::{reconcile_moved_paths_impl, update_file_refs_impl},
+};
 
 pub async fn write_to_db(
     inputs: DbWritableMediaDataBatch,
@@ -13,7 +16,7 @@ pub async fn write_to_db(
     pool: &Pool<Sqlite>,
     _pool_thumbs: &Pool<Sqlite>, // TODO delete this unused
     path: &str,
-) {
+) -> sqlx::Result<()> {
     // Write the basic Media data to the db
 
     // Ignore any duplicate hashes
@@ -35,18 +38,43 @@ pub async fn write_to_db(
 
     let query = query_builder.build();
 
-    query.execute(pool).await.unwrap();
+    query.execute(pool).await?;
 
     // Write the path info to DB
 
+    reconcile_moved_paths_impl(&inputs.paths, path, pool).await?;
+
+    // Paths that were rehashed because their files changed no longer point to the old hashes
+    let mut query_builder: QueryBuilder<Sqlite> =
+        QueryBuilder::new("DELETE FROM Path WHERE EXISTS (SELECT 1 FROM (VALUES ");
+    let mut separated = query_builder.separated(", ");
+    for data in &inputs.paths {
+        separated.push("(");
+        separated.push_bind_unseparated(&data.path);
+        separated.push_unseparated(", ");
+        separated.push_bind_unseparated(&data.hash);
+        separated.push_unseparated(")");
+    }
+    query_builder.push(
+        ") AS Rehashed WHERE Rehashed.column1 = Path.path AND Rehashed.column2 != Path.hash)",
+    );
+    query_builder.build().execute(pool).await?;
+
     let mut query_builder: QueryBuilder<Sqlite> =
-        QueryBuilder::new("INSERT OR IGNORE INTO Path(hash, path, imported_from) ");
+        QueryBuilder::new("INSERT INTO Path(hash, path, imported_from, filesize, mtime) ");
     query_builder.push_values(inputs.paths.into_iter(), |mut b, data| {
-        b.push_bind(data.hash).push_bind(data.path).push_bind(path);
+        b.push_bind(data.hash)
+            .push_bind(data.path)
+            .push_bind(path)
+            .push_bind(data.size as i64)
+            .push_bind(data.mtime);
     });
+    query_builder.push(
+        " ON CONFLICT(hash, path) DO UPDATE SET filesize = excluded.filesize, mtime = excluded.mtime",
+    );
     let query = query_builder.build();
 
-    query.execute(pool).await.unwrap();
+    query.execute(pool).await?;
 
     // Write specific file metadata
 
@@ -74,7 +102,30 @@ pub async fn write_to_db(
             });
 
             let query = query_builder.build();
-            query.execute(pool).await.unwrap();
+            query.execute(pool).await?;
+
+            if !inputs.exif.is_empty() {
+                let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(
+                    "INSERT OR IGNORE INTO Exif(hash, make, model, lens, exposure_time, f_number, iso, focal_length, taken_at, orientation, latitude, longitude, altitude) ",
+                );
+                query_builder.push_values(inputs.exif, |mut b, exif| {
+                    b.push_bind(exif.hash)
+                        .push_bind(exif.make)
+                        .push_bind(exif.model)
+                        .push_bind(exif.lens)
+                        .push_bind(exif.exposure_time)
+                        .push_bind(exif.f_number)
+                        .push_bind(exif.iso)
+                        .push_bind(exif.focal_length)
+                        .push_bind(exif.taken_at)
+                        .push_bind(exif.orientation)
+                        .push_bind(exif.latitude)
+                        .push_bind(exif.longitude)
+                        .push_bind(exif.altitude);
+                });
+
+                query_builder.build().execute(pool).await?;
+            }
         }
         MediaType::Video => { /* TODO implement video meta */ }
         MediaType::Game => todo!(),
@@ -83,8 +134,8 @@ pub async fn write_to_db(
         MediaType::Flash => { /*TODO implement Flash meta */ }
     }
 
-    // Mark any unreferenced files
-    sqlx_query("UPDATE Media SET has_file_ref = false WHERE NOT EXISTS (SELECT 1 FROM Path WHERE Path.hash = Media.hash)").execute(pool).await.unwrap();
+    // Mark any unreferenced files, and the ones that got their files back
+    update_file_refs_impl(pool).await?;
 
     if !invalid_media_to_be_tagged.is_empty() {
         let mut query_builder: QueryBuilder<Sqlite> =
@@ -97,7 +148,7 @@ pub async fn write_to_db(
         }
         separated.push_unseparated(") ");
 
-        query_builder.build().execute(pool).await.unwrap();
+        query_builder.build().execute(pool).await?;
         trace!(
             "Invalid media marked with is_valid = false: {:?}",
             invalid_media_to_be_tagged
@@ -148,4 +199,93 @@ pub async fn write_to_db(
     }
 
     */
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_write_paths(pool: Pool<Sqlite>) {
+    use sqlx::{migrate, query_as};
+
+    use super::media_types::{GenericMediaData, PathData};
+    use crate::db::schema::Path;
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    let batch = |hash: &str, size: u64, mtime: i64| DbWritableMediaDataBatch {
+        media_type_identifier: MediaType::Video,
+        media_data: vec![],
+        generic_media_data: vec![GenericMediaData {
+            hash: hash.to_string(),
+            size,
+            mime: "video/mp4".to_string(),
+            thumb_path: None,
+            time_added: 0,
+            thumbnail_x: 1,
+            thumbnail_y: 1,
+        }],
+        paths: vec![PathData {
+            path: "/videos/1.mp4".to_string(),
+            hash: hash.to_string(),
+            size,
+            mtime,
+        }],
+        exif: vec![],
+    };
+    let get_paths = async || -> Vec<Path> {
+        query_as("SELECT * FROM Path")
+            .fetch_all(&pool)
+            .await
+            .unwrap()
+    };
+
+    write_to_db(
+        batch("1", 10, 100),
+        MediaType::Video,
+        &pool,
+        &pool,
+        "/videos",
+    )
+    .await
+    .unwrap();
+    let paths = get_paths().await;
+    assert_eq!(paths.len(), 1);
+    assert_eq!((paths[0].filesize, paths[0].mtime), (Some(10), Some(100)));
+
+    // touched without changing the contents
+    write_to_db(
+        batch("1", 10, 200),
+        MediaType::Video,
+        &pool,
+        &pool,
+        "/videos",
+    )
+    .await
+    .unwrap();
+    let paths = get_paths().await;
+    assert_eq!(paths.len(), 1);
+    assert_eq!(paths[0].mtime, Some(200));
+
+    // modified, the path moves to the new hash and the old media loses its file
+    write_to_db(
+        batch("2", 20, 300),
+        MediaType::Video,
+        &pool,
+        &pool,
+        "/videos",
+    )
+    .await
+    .unwrap();
+    let paths = get_paths().await;
+    assert_eq!(paths.len(), 1);
+    assert_eq!(
+        (paths[0].hash.as_str(), paths[0].filesize, paths[0].mtime),
+        ("2", Some(20), Some(300))
+    );
+
+    let has_file_ref: bool = sqlx::query_scalar("SELECT has_file_ref FROM Media WHERE hash = '1'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert!(!has_file_ref);
 }
@@ -11,9 +11,9 @@ use human_bytes::human_bytes;
 use itertools::Itertools;
 use rustpython_vm::common::str;
 use serde::{Deserialize, Serialize};
-use sqlx::{Pool, Sqlite, query_as, query_scalar};
+use sqlx::{Pool, Sqlite, query, query_as, query_scalar};
 
-use crate::db::schema::{HashTagPair, Image, Media, MediaType, RawTagsField, TagDetail};
+use crate::db::schema::{Exif, HashTagPair, Image, Media, MediaType, RawTagsField, TagDetail};
 
 /// Gets all the info to show to user in the sidebar for a piece of media
 pub async fn get_info_impl(hash: &str, pool: &Pool<Sqlite>) -> MediaInfo {
@@ -85,7 +85,17 @@ pub async fn get_info_impl(hash: &str, pool: &Pool<Sqlite>) -> MediaInfo {
                 value: resolution,
                 is_value_monospaced: true,
                 is_one_line: true,
-            })
+            });
+
+            let exif: Option<Exif> = query_as("SELECT * FROM Exif WHERE hash = ?")
+                .bind(hash)
+                .fetch_optional(pool)
+                .await
+                .unwrap();
+
+            if let Some(exif) = exif {
+                meta.extend(get_exif_meta_entries(&exif));
+            }
         }
         MediaType::Video => { /* TODO implement video meta */ }
         MediaType::Game => unimplemented!(),
@@ -161,6 +171,87 @@ pub async fn get_info_impl(hash: &str, pool: &Pool<Sqlite>) -> MediaInfo {
     }
 }
 
+/// Meta entries of the camera metadata, missing values are skipped
+fn get_exif_meta_entries(exif: &Exif) -> Vec<MetaEntry> {
+    let mut meta = vec![];
+    let mut push = |name: &str, value: String, is_value_monospaced: bool| {
+        meta.push(MetaEntry {
+            name: name.to_string(),
+            value,
+            is_value_monospaced,
+            is_one_line: true,
+        })
+    };
+
+    let camera = match (&exif.make, &exif.model) {
+        // most models already start with the make, `Canon Canon EOS R5`
+        (Some(make), Some(model)) if model.to_lowercase().starts_with(&make.to_lowercase()) => {
+            Some(model.clone())
+        }
+        (Some(make), Some(model)) => Some(format!("{make} {model}")),
+        (make, model) => make.clone().or(model.clone()),
+    };
+    if let Some(camera) = camera {
+        push("Camera", camera, false);
+    }
+    if let Some(lens) = &exif.lens {
+        push("Lens", lens.clone(), false);
+    }
+
+    if let Some(exposure_time) = exif.exposure_time {
+        let exposure_time = if exposure_time > 0.0 && exposure_time < 1.0 {
+            format!("1/{} s", (1.0 / exposure_time).round())
+        } else {
+            format!("{exposure_time} s")
+        };
+        push("Exposure Time", exposure_time, true);
+    }
+    if let Some(f_number) = exif.f_number {
+        push("Aperture", format!("f/{f_number:.1}"), true);
+    }
+    if let Some(iso) = exif.iso {
+        push("ISO", iso.to_string(), true);
+    }
+    if let Some(focal_length) = exif.focal_length {
+        push("Focal Length", format!("{focal_length} mm"), true);
+    }
+
+    if let Some(taken_at) = exif.taken_at
+        && let Some(datetime) = Utc.timestamp_millis_opt(taken_at).single()
+    {
+        let local_datetime: DateTime<Local> = DateTime::from(datetime);
+        push(
+            "Date Taken",
+            local_datetime.format("%d %b %y %X").to_string(),
+            false,
+        );
+    }
+
+    let orientation = match exif.orientation {
+        Some(2) => Some("Mirrored"),
+        Some(3) => Some("Rotated 180°"),
+        Some(4) => Some("Mirrored, rotated 180°"),
+        Some(5) => Some("Mirrored, rotated 90° CCW"),
+        Some(6) => Some("Rotated 90° CW"),
+        Some(7) => Some("Mirrored, rotated 90° CW"),
+        Some(8) => Some("Rotated 90° CCW"),
+        _ => None,
+    };
+    if let Some(orientation) = orientation {
+        push("Orientation", orientation.to_string(), false);
+    }
+
+    if let (Some(latitude), Some(longitude)) = (exif.latitude, exif.longitude) {
+        let location = match exif.altitude {
+            Some(altitude) => format!("{latitude:.5}, {longitude:.5}, {altitude:.0} m"),
+            None => format!("{latitude:.5}, {longitude:.5}"),
+        };
+        push("Location", location, true);
+    }
+
+    meta
+}
+
 pub async fn get_tags_grouped_by_source_categories_from_tags(
     tags: &[TagWithDetails],
 ) -> SourceCategoryGroupedTags {
@@ -226,6 +317,16 @@ pub async fn get_media_name_impl(hash: &str, pool: &Pool<Sqlite>) -> String {
         .to_string()
 }
 
+/// Records that the media was opened now, used by `order by viewed` in search
+pub async fn mark_viewed_impl(hash: &str, pool: &Pool<Sqlite>) {
+    query("INSERT INTO MediaView(hash, last_viewed) VALUES (?, ?) ON CONFLICT(hash) DO UPDATE SET last_viewed = excluded.last_viewed")
+        .bind(hash)
+        .bind(Utc::now().timestamp_millis())
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
 #[derive(Debug, Serialize, Deserialize, specta::Type)]
 #[serde(rename_all = "camelCase")]
 pub struct MediaInfo {
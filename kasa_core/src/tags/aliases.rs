@@ -0,0 +1,326 @@
+//! Alternative names of tags, like `blue hair` for `blue_hair`
+//!
+//! Aliases are never stored in `HashTagPair`, tag names are resolved to their canonical name when they are
+//! inserted, removed and searched, and adding an alias moves the media tagged with it to the canonical tag.
+
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use kasa_python::ExtractedTag;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, QueryBuilder, Sqlite, prelude::FromRow, query, query_as, query_scalar};
+use thiserror::Error;
+
+use crate::db::{MAX_BINDS, impl_from_sqlx_error};
+
+use super::{
+    implications::{find_implication_cycle, refresh_implied_tags_around},
+    rename::rewrite_tag,
+    validate_name,
+};
+
+#[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub enum AliasError {
+    #[error("Tag names can't be empty")]
+    EmptyName,
+    #[error("`{0}` can't be an alias of itself")]
+    AliasOfItself(String),
+    #[error("`{0}` is not an alias")]
+    NotFound(String),
+    #[error("The alias would create the implication cycle {}", .0.join(" -> "))]
+    ImplicationCycle(Vec<String>),
+    #[error("Database error: {0}")]
+    Database(String),
+}
+
+impl_from_sqlx_error!(AliasError);
+
+#[derive(Debug, Clone, PartialEq, FromRow, Serialize, Deserialize, specta::Type)]
+pub struct TagAlias {
+    pub alias: String,
+    pub canonical: String,
+}
+
+/// Canonical name of the tag, the tag itself if it isn't an alias
+pub async fn resolve_alias(tag: &str, pool: &Pool<Sqlite>) -> sqlx::Result<String> {
+    let canonical: Option<String> = query_scalar("SELECT canonical FROM TagAlias WHERE alias = ?")
+        .bind(tag)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(canonical.unwrap_or_else(|| tag.to_string()))
+}
+
+/// Canonical names of the tags that are aliases, keyed by the alias
+async fn canonical_names(
+    tags: &[String],
+    pool: &Pool<Sqlite>,
+) -> sqlx::Result<HashMap<String, String>> {
+    let mut canonical_names = HashMap::new();
+
+    for chunk in tags.chunks(MAX_BINDS) {
+        let mut query_builder: QueryBuilder<Sqlite> =
+            QueryBuilder::new("SELECT alias, canonical FROM TagAlias WHERE alias IN (");
+        let mut separated = query_builder.separated(", ");
+        for tag in chunk {
+            separated.push_bind(tag.clone());
+        }
+        query_builder.push(")");
+
+        let aliases: Vec<TagAlias> = query_builder.build_query_as().fetch_all(pool).await?;
+        canonical_names.extend(aliases.into_iter().map(|a| (a.alias, a.canonical)));
+    }
+
+    Ok(canonical_names)
+}
+
+/// Canonical names of the tags in the same order, tags that resolve to the same name are only kept once
+pub async fn resolve_aliases(tags: Vec<String>, pool: &Pool<Sqlite>) -> sqlx::Result<Vec<String>> {
+    let canonical_names = canonical_names(&tags, pool).await?;
+
+    Ok(tags
+        .into_iter()
+        .map(|tag| canonical_names.get(&tag).cloned().unwrap_or(tag))
+        .unique()
+        .collect())
+}
+
+/// `resolve_aliases` for tags with a type, the first tag with a name keeps its type
+pub async fn resolve_extracted_tags(
+    tags: Vec<ExtractedTag>,
+    pool: &Pool<Sqlite>,
+) -> sqlx::Result<Vec<ExtractedTag>> {
+    let names: Vec<String> = tags.iter().map(|t| t.name.clone()).collect();
+    let canonical_names = canonical_names(&names, pool).await?;
+
+    Ok(tags
+        .into_iter()
+        .map(|tag| match canonical_names.get(&tag.name) {
+            Some(canonical) => ExtractedTag {
+                name: canonical.clone(),
+                ..tag
+            },
+            None => tag,
+        })
+        .unique_by(|tag| tag.name.clone())
+        .collect())
+}
+
+/// Makes `alias` an alias of `canonical`, media tagged with `alias` is tagged with `canonical` instead
+///
+/// If `canonical` is an alias itself the alias points to its canonical tag, aliases of `alias` are moved to
+/// `canonical` too.
+pub async fn add_alias_impl(
+    alias: &str,
+    canonical: &str,
+    pool: &Pool<Sqlite>,
+) -> Result<(), AliasError> {
+    let alias = validate_name(alias, AliasError::EmptyName)?;
+    let canonical = validate_name(canonical, AliasError::EmptyName)?;
+
+    let canonical = resolve_alias(canonical, pool).await?;
+
+    if alias == canonical {
+        return Err(AliasError::AliasOfItself(alias.to_string()));
+    }
+
+    let mut tx = pool.begin().await?;
+
+    query("INSERT INTO TagAlias(alias, canonical) VALUES (?, ?) ON CONFLICT(alias) DO UPDATE SET canonical = excluded.canonical")
+        .bind(alias)
+        .bind(&canonical)
+        .execute(&mut *tx)
+        .await?;
+
+    // media tagged with the alias, its implications and the aliases pointing to it move to the canonical tag
+    rewrite_tag(alias, &canonical, &mut tx).await?;
+
+    if let Some(cycle) = find_implication_cycle(&mut tx).await? {
+        return Err(AliasError::ImplicationCycle(cycle));
+    }
+
+    refresh_implied_tags_around(&canonical, &mut tx).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Removes the alias, media that was moved to the canonical tag stays there
+pub async fn remove_alias_impl(alias: &str, pool: &Pool<Sqlite>) -> Result<(), AliasError> {
+    let result = query("DELETE FROM TagAlias WHERE alias = ?")
+        .bind(alias)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AliasError::NotFound(alias.to_string()));
+    }
+
+    Ok(())
+}
+
+/// Every alias, grouped by their canonical tag
+pub async fn get_aliases_impl(pool: &Pool<Sqlite>) -> Result<Vec<TagAlias>, AliasError> {
+    let aliases = query_as("SELECT alias, canonical FROM TagAlias ORDER BY canonical, alias")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(aliases)
+}
+
+#[sqlx::test]
+async fn test_aliases(pool: Pool<Sqlite>) {
+    use sqlx::migrate;
+
+    use super::insert_tags;
+    use crate::test_util::db_utils::{insert_image, search_hashes};
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    for hash in ["1", "2", "3"] {
+        insert_image(&pool, hash).await;
+    }
+
+    let tags = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect_vec();
+
+    insert_tags(tags(&["blue_hair"]), &pool, Some("1".to_string()), None).await;
+    insert_tags(tags(&["blue hair"]), &pool, Some("2".to_string()), None).await;
+
+    add_alias_impl("blue hair", "blue_hair", &pool)
+        .await
+        .unwrap();
+
+    // existing media is moved to the canonical tag
+    let tag_names: Vec<String> = query_scalar("SELECT DISTINCT tag_name FROM HashTagPair")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(tag_names, vec!["blue_hair"]);
+
+    // new tags are resolved when inserted
+    insert_tags(
+        tags(&["blue hair", "blue_hair"]),
+        &pool,
+        Some("3".to_string()),
+        None,
+    )
+    .await;
+
+    assert_eq!(search_hashes("blue hair", &pool).await, vec!["1", "2", "3"]);
+    assert_eq!(search_hashes("blue_hair", &pool).await, vec!["1", "2", "3"]);
+
+    // aliases of an alias point to the canonical tag
+    add_alias_impl("bluehair", "blue hair", &pool)
+        .await
+        .unwrap();
+    add_alias_impl("blue_hair", "azure hair", &pool)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        get_aliases_impl(&pool).await.unwrap(),
+        vec![
+            TagAlias {
+                alias: "blue hair".to_string(),
+                canonical: "azure hair".to_string()
+            },
+            TagAlias {
+                alias: "blue_hair".to_string(),
+                canonical: "azure hair".to_string()
+            },
+            TagAlias {
+                alias: "bluehair".to_string(),
+                canonical: "azure hair".to_string()
+            },
+        ]
+    );
+    assert_eq!(search_hashes("bluehair", &pool).await, vec!["1", "2", "3"]);
+
+    assert_eq!(
+        add_alias_impl("azure hair", "bluehair", &pool).await,
+        Err(AliasError::AliasOfItself("azure hair".to_string()))
+    );
+
+    remove_alias_impl("bluehair", &pool).await.unwrap();
+    assert_eq!(
+        remove_alias_impl("bluehair", &pool).await,
+        Err(AliasError::NotFound("bluehair".to_string()))
+    );
+}
+
+#[sqlx::test]
+async fn test_alias_implied_tags(pool: Pool<Sqlite>) {
+    use sqlx::migrate;
+
+    use super::{
+        implications::{add_implication_impl, remove_implication_impl},
+        insert_tags,
+    };
+    use crate::test_util::db_utils::insert_image;
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    for hash in ["1", "2"] {
+        insert_image(&pool, hash).await;
+    }
+
+    let tags = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect_vec();
+
+    // `blue_hair` is only implied for 1, which has `blue hair` typed
+    add_implication_impl("cyan_hair", "blue_hair", &pool)
+        .await
+        .unwrap();
+    add_implication_impl("blue hair", "hair", &pool)
+        .await
+        .unwrap();
+    insert_tags(
+        tags(&["cyan_hair", "blue hair"]),
+        &pool,
+        Some("1".to_string()),
+        None,
+    )
+    .await;
+    insert_tags(tags(&["blue_hair"]), &pool, Some("2".to_string()), None).await;
+
+    add_alias_impl("blue hair", "blue_hair", &pool)
+        .await
+        .unwrap();
+
+    let media_tags = async |hash: &str| -> Vec<String> {
+        query_scalar("SELECT tag_name FROM HashTagPair WHERE hash = ? ORDER BY tag_name")
+            .bind(hash)
+            .fetch_all(&pool)
+            .await
+            .unwrap()
+    };
+
+    // the implication of the alias applies to the canonical tag right away
+    assert_eq!(media_tags("2").await, vec!["blue_hair", "hair"]);
+
+    // the typed alias became a regular `blue_hair`, it stays after the implication is gone
+    remove_implication_impl("cyan_hair", "blue_hair", &pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        media_tags("1").await,
+        vec!["blue_hair", "cyan_hair", "hair"]
+    );
+
+    // the implications would become `blue_hair -> hair -> blue_hair`, the alias isn't added
+    add_implication_impl("hair", "long_hair", &pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        add_alias_impl("long_hair", "blue_hair", &pool).await,
+        Err(AliasError::ImplicationCycle(tags(&[
+            "blue_hair",
+            "hair",
+            "blue_hair"
+        ])))
+    );
+    assert_eq!(
+        resolve_alias("long_hair", &pool).await.unwrap(),
+        "long_hair"
+    );
+}
@@ -0,0 +1,443 @@
+//! Adding, removing and replacing the tags of many media at once
+//!
+//! The media and the tags are loaded into temporary tables so every change is a few set based queries in a
+//! single transaction, instead of a query per media and tag. Every edit returns the `HashTagPair`s it added
+//! and removed, which is all that is needed to undo it, and is recorded in the tag history.
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, QueryBuilder, Sqlite, SqliteConnection, query, query_as};
+use thiserror::Error;
+
+use crate::db::{MAX_BINDS, impl_from_sqlx_error, schema::HashTagPair};
+
+use super::{
+    IMPLICATION_SOURCE,
+    aliases::resolve_aliases,
+    cleanup::remove_orphaned_tags,
+    history::record_tag_changes,
+    implications::{MediaScope, refresh_implied_tags_of},
+    search::SearchCriteria,
+};
+
+const TARGET_HASHES_SQL: &str = "SELECT hash FROM temp.bulk_target";
+
+#[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub enum BulkTagError {
+    #[error("Couldn't expand the search: {0}")]
+    Search(String),
+    #[error("Database error: {0}")]
+    Database(String),
+}
+
+impl_from_sqlx_error!(BulkTagError);
+
+/// Media a bulk edit applies to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub enum BulkTarget {
+    Hashes(Vec<String>),
+    /// Every media matching the search when the edit runs
+    Search(SearchCriteria),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub enum BulkTagOperation {
+    Add(Vec<String>),
+    Remove(Vec<String>),
+    /// The media has exactly these tags afterwards, besides the implied ones
+    Replace(Vec<String>),
+}
+
+/// Changes made by a bulk edit, passed to `undo_bulk_tag_edit_impl` to revert it
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub struct BulkTagUndo {
+    pub added: Vec<HashTagPair>,
+    pub removed: Vec<HashTagPair>,
+}
+
+async fn create_temp_tables(conn: &mut SqliteConnection) -> sqlx::Result<()> {
+    query("CREATE TEMP TABLE bulk_target (hash TEXT PRIMARY KEY)")
+        .execute(&mut *conn)
+        .await?;
+    query("CREATE TEMP TABLE bulk_tag (name TEXT PRIMARY KEY)")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+async fn drop_temp_tables(conn: &mut SqliteConnection) -> sqlx::Result<()> {
+    query("DROP TABLE temp.bulk_target")
+        .execute(&mut *conn)
+        .await?;
+    query("DROP TABLE temp.bulk_tag")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Inserts the values into a single column temporary table
+async fn fill_temp_table(
+    table: &str,
+    values: impl IntoIterator<Item = String>,
+    conn: &mut SqliteConnection,
+) -> sqlx::Result<()> {
+    for chunk in &values.into_iter().chunks(MAX_BINDS) {
+        let mut query_builder: QueryBuilder<Sqlite> =
+            QueryBuilder::new(format!("INSERT OR IGNORE INTO temp.{table} "));
+        query_builder.push_values(chunk, |mut builder, value| {
+            builder.push_bind(value);
+        });
+
+        query_builder.build().execute(&mut *conn).await?;
+    }
+
+    Ok(())
+}
+
+/// Creates the `Tag` and `TagDetail` rows of the tags in `bulk_tag` that don't have them
+async fn insert_missing_tags(conn: &mut SqliteConnection) -> sqlx::Result<()> {
+    query("INSERT INTO Tag(name) SELECT name FROM temp.bulk_tag WHERE name NOT IN (SELECT name FROM Tag)")
+        .execute(&mut *conn)
+        .await?;
+
+    query("INSERT INTO TagDetail(name) SELECT name FROM temp.bulk_tag WHERE name NOT IN (SELECT name FROM TagDetail)")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Deletes the `HashTagPair`s of the target media matching `condition` on `h`, returns them
+async fn delete_pairs(
+    condition: &str,
+    conn: &mut SqliteConnection,
+) -> sqlx::Result<Vec<HashTagPair>> {
+    let selection =
+        format!("FROM HashTagPair h WHERE h.hash IN ({TARGET_HASHES_SQL}) AND {condition}");
+
+    let mut query_builder: QueryBuilder<Sqlite> =
+        QueryBuilder::new(format!("SELECT h.* {selection}"));
+    let pairs = query_builder.build_query_as().fetch_all(&mut *conn).await?;
+
+    let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+        "DELETE FROM HashTagPair WHERE rowid IN (SELECT h.rowid {selection})"
+    ));
+    query_builder.build().execute(&mut *conn).await?;
+
+    Ok(pairs)
+}
+
+/// Adds every tag of `bulk_tag` to every target media that doesn't have it yet, returns the added pairs
+async fn insert_pairs(
+    source: Option<&str>,
+    conn: &mut SqliteConnection,
+) -> sqlx::Result<Vec<HashTagPair>> {
+    let added: Vec<HashTagPair> = query_as(
+        "SELECT bt.hash, tg.name AS tag_name, ? AS source, NULL AS source_type \
+        FROM temp.bulk_target bt, temp.bulk_tag tg \
+        WHERE NOT EXISTS (SELECT 1 FROM HashTagPair h WHERE h.hash = bt.hash AND h.tag_name = tg.name)",
+    )
+    .bind(source)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    query(
+        "INSERT OR IGNORE INTO HashTagPair(hash, tag_name, source) \
+        SELECT bt.hash, tg.name, ? FROM temp.bulk_target bt, temp.bulk_tag tg",
+    )
+    .bind(source)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(added)
+}
+
+/// Removes tags left without media and updates the implied tags of the target media
+async fn finish_edit(removed: &[HashTagPair], conn: &mut SqliteConnection) -> sqlx::Result<()> {
+    let removed_tags: Vec<String> = removed
+        .iter()
+        .map(|pair| pair.tag_name.clone())
+        .unique()
+        .collect();
+    remove_orphaned_tags(Some(&removed_tags), false, conn).await?;
+
+    refresh_implied_tags_of(&MediaScope::Subquery(TARGET_HASHES_SQL), conn).await?;
+
+    drop_temp_tables(conn).await
+}
+
+/// Applies the operation to every target media in one transaction
+///
+/// Aliases are resolved to their canonical tags, added tags use `source` as their `HashTagPair.source`. Tags
+/// that were only implied become regular tags when they are added. Implied tags can't be removed, they come
+/// back as long as the media has a tag implying them.
+pub async fn bulk_edit_tags_impl(
+    target: BulkTarget,
+    operation: BulkTagOperation,
+    source: Option<String>,
+    pool: &Pool<Sqlite>,
+) -> Result<BulkTagUndo, BulkTagError> {
+    let (tags, adds, is_replace) = match operation {
+        BulkTagOperation::Add(tags) => (tags, true, false),
+        BulkTagOperation::Remove(tags) => (tags, false, false),
+        BulkTagOperation::Replace(tags) => (tags, true, true),
+    };
+
+    let tags: Vec<String> = tags
+        .iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    let tags = resolve_aliases(tags, pool).await?;
+
+    let target = match target {
+        BulkTarget::Search(mut criteria) => {
+            criteria
+                .expand_tags(pool)
+                .await
+                .map_err(|e| BulkTagError::Search(e.to_string()))?;
+            BulkTarget::Search(criteria)
+        }
+        target => target,
+    };
+
+    let mut tx = pool.begin().await?;
+
+    create_temp_tables(&mut tx).await?;
+
+    match target {
+        BulkTarget::Hashes(hashes) => fill_temp_table("bulk_target", hashes, &mut tx).await?,
+        BulkTarget::Search(criteria) => {
+            let mut query_builder: QueryBuilder<Sqlite> =
+                QueryBuilder::new("INSERT OR IGNORE INTO temp.bulk_target(hash) ");
+            criteria.push_hash_query(&mut query_builder);
+            query_builder.build().execute(&mut *tx).await?;
+        }
+    }
+
+    fill_temp_table("bulk_tag", tags, &mut tx).await?;
+
+    let in_tags = "h.tag_name IN (SELECT name FROM temp.bulk_tag)";
+    let is_implied = format!("h.source IS '{IMPLICATION_SOURCE}'");
+
+    // implied pairs of added tags are replaced with regular ones
+    let removal_condition = match (adds, is_replace) {
+        (false, _) => format!("{in_tags} AND NOT {is_implied}"),
+        (true, false) => format!("{in_tags} AND {is_implied}"),
+        (true, true) => format!("({in_tags}) = ({is_implied})"),
+    };
+
+    let removed = delete_pairs(&removal_condition, &mut tx).await?;
+
+    let added = if adds {
+        insert_missing_tags(&mut tx).await?;
+        insert_pairs(source.as_deref(), &mut tx).await?
+    } else {
+        vec![]
+    };
+
+    record_tag_changes(&added, &removed, &mut tx).await?;
+
+    finish_edit(&removed, &mut tx).await?;
+
+    tx.commit().await?;
+
+    Ok(BulkTagUndo { added, removed })
+}
+
+/// Reverts a bulk edit, removing the pairs it added and restoring the ones it removed
+///
+/// Details of tags that were cleaned up because the edit left them without media aren't restored.
+pub async fn undo_bulk_tag_edit_impl(
+    undo: BulkTagUndo,
+    pool: &Pool<Sqlite>,
+) -> Result<(), BulkTagError> {
+    let mut tx = pool.begin().await?;
+    revert_changes(&undo, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Removes the added pairs and restores the removed ones, pairs that are there again are kept as they are
+///
+/// The revert is recorded in the tag history, returns its batch or `None` if nothing changed.
+pub(super) async fn revert_changes(
+    undo: &BulkTagUndo,
+    conn: &mut SqliteConnection,
+) -> sqlx::Result<Option<i64>> {
+    create_temp_tables(conn).await?;
+
+    let hashes = undo
+        .added
+        .iter()
+        .chain(&undo.removed)
+        .map(|pair| pair.hash.clone());
+    fill_temp_table("bulk_target", hashes, conn).await?;
+
+    let restored_tags = undo.removed.iter().map(|pair| pair.tag_name.clone());
+    fill_temp_table("bulk_tag", restored_tags, conn).await?;
+
+    // only what actually changed is recorded, the media may have been edited since
+    let mut deleted: Vec<HashTagPair> = vec![];
+    for chunk in undo.added.chunks(MAX_BINDS / 2) {
+        let mut query_builder: QueryBuilder<Sqlite> =
+            QueryBuilder::new("DELETE FROM HashTagPair WHERE (hash, tag_name) IN (VALUES ");
+        let mut separated = query_builder.separated(", ");
+        for pair in chunk {
+            separated.push("(");
+            separated.push_bind_unseparated(pair.hash.clone());
+            separated.push_unseparated(", ");
+            separated.push_bind_unseparated(pair.tag_name.clone());
+            separated.push_unseparated(")");
+        }
+        query_builder.push(") RETURNING *");
+
+        deleted.extend(
+            query_builder
+                .build_query_as::<HashTagPair>()
+                .fetch_all(&mut *conn)
+                .await?,
+        );
+    }
+
+    insert_missing_tags(conn).await?;
+
+    let mut restored: Vec<HashTagPair> = vec![];
+    for chunk in undo.removed.chunks((MAX_BINDS - 1) / 4) {
+        let mut query_builder: QueryBuilder<Sqlite> =
+            QueryBuilder::new("INSERT INTO HashTagPair(hash, tag_name, source, source_type) ");
+        query_builder.push_values(chunk, |mut builder, pair| {
+            builder
+                .push_bind(pair.hash.clone())
+                .push_bind(pair.tag_name.clone())
+                .push_bind(pair.source.clone())
+                .push_bind(pair.source_type.clone());
+        });
+        query_builder.push(
+            " ON CONFLICT(hash, tag_name) DO UPDATE SET source = excluded.source, \
+            source_type = excluded.source_type WHERE HashTagPair.source = ",
+        );
+        query_builder.push_bind(IMPLICATION_SOURCE);
+        query_builder.push(" RETURNING *");
+
+        restored.extend(
+            query_builder
+                .build_query_as::<HashTagPair>()
+                .fetch_all(&mut *conn)
+                .await?,
+        );
+    }
+
+    let batch = record_tag_changes(&restored, &deleted, conn).await?;
+
+    finish_edit(&deleted, conn).await?;
+
+    Ok(batch)
+}
+
+#[sqlx::test]
+async fn test_bulk_edit_tags(pool: Pool<Sqlite>) {
+    use sqlx::{migrate, query_scalar};
+
+    use super::{implications::add_implication_impl, insert_tags};
+    use crate::test_util::db_utils::insert_image;
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    for hash in ["1", "2", "3"] {
+        insert_image(&pool, hash).await;
+    }
+
+    let tags = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect_vec();
+
+    insert_tags(tags(&["cat", "sky"]), &pool, Some("1".to_string()), None).await;
+    insert_tags(tags(&["cat"]), &pool, Some("2".to_string()), None).await;
+    insert_tags(tags(&["dog"]), &pool, Some("3".to_string()), None).await;
+    add_implication_impl("cat", "animal", &pool).await.unwrap();
+    add_implication_impl("dog", "animal", &pool).await.unwrap();
+
+    let all_tags = async || -> Vec<(String, String)> {
+        query_as("SELECT hash, tag_name FROM HashTagPair ORDER BY hash, tag_name")
+            .fetch_all(&pool)
+            .await
+            .unwrap()
+    };
+    let pairs = |pairs: &[(&str, &str)]| {
+        pairs
+            .iter()
+            .map(|(hash, tag)| (hash.to_string(), tag.to_string()))
+            .collect_vec()
+    };
+    let before = all_tags().await;
+
+    let search = SearchCriteria::parse_from_str("cat").unwrap();
+    let undo = bulk_edit_tags_impl(
+        BulkTarget::Search(search),
+        BulkTagOperation::Add(tags(&["cute", "animal"])),
+        Some("bulk".to_string()),
+        &pool,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        all_tags().await,
+        pairs(&[
+            ("1", "animal"),
+            ("1", "cat"),
+            ("1", "cute"),
+            ("1", "sky"),
+            ("2", "animal"),
+            ("2", "cat"),
+            ("2", "cute"),
+            ("3", "animal"),
+            ("3", "dog"),
+        ])
+    );
+    // the implied `animal`s became regular tags
+    assert_eq!(undo.added.len(), 4);
+    assert_eq!(undo.removed.len(), 2);
+
+    undo_bulk_tag_edit_impl(undo, &pool).await.unwrap();
+    assert_eq!(all_tags().await, before);
+
+    // the implied tags go with the tags implying them, `cute` is cleaned up as nothing uses it anymore while the
+    // tags of the implications are kept
+    let undo = bulk_edit_tags_impl(
+        BulkTarget::Hashes(tags(&["1", "2", "3"])),
+        BulkTagOperation::Replace(tags(&["sky"])),
+        None,
+        &pool,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        all_tags().await,
+        pairs(&[("1", "sky"), ("2", "sky"), ("3", "sky")])
+    );
+
+    let tag_names: Vec<String> = query_scalar("SELECT name FROM Tag ORDER BY name")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(tag_names, vec!["animal", "cat", "dog", "sky"]);
+
+    undo_bulk_tag_edit_impl(undo, &pool).await.unwrap();
+    assert_eq!(all_tags().await, before);
+
+    bulk_edit_tags_impl(
+        BulkTarget::Hashes(tags(&["1", "3"])),
+        BulkTagOperation::Remove(tags(&["cat", "dog", "animal"])),
+        None,
+        &pool,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        all_tags().await,
+        pairs(&[("1", "sky"), ("2", "animal"), ("2", "cat")])
+    );
+}
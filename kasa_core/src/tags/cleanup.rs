@@ -0,0 +1,191 @@
+//! Removes tags that no media uses anymore, so they stop showing up in autocomplete
+//!
+//! Tags with `TagDetail.delete_on_no_references_left` turned off are kept, as well as tags that are the target
+//! of an alias or part of an implication, which are still referenced even without media.
+
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, QueryBuilder, Sqlite, SqliteConnection, prelude::FromRow, query};
+
+use crate::db::MAX_BINDS;
+
+/// Tags that are in `Tag` or `TagDetail` without any `HashTagPair`, alias or implication
+const ORPHANED_TAGS_SQL: &str = "SELECT n.name, \
+    COALESCE((SELECT MIN(d.delete_on_no_references_left) FROM TagDetail d WHERE d.name = n.name), 1) \
+    AS delete_on_no_references_left, \
+    EXISTS (SELECT 1 FROM Tag t WHERE t.name = n.name) AS is_in_tag \
+    FROM (SELECT name FROM Tag UNION SELECT name FROM TagDetail) n \
+    WHERE NOT EXISTS (SELECT 1 FROM HashTagPair htp WHERE htp.tag_name = n.name) \
+    AND NOT EXISTS (SELECT 1 FROM TagAlias ta WHERE ta.canonical = n.name) \
+    AND NOT EXISTS (SELECT 1 FROM TagImplication ti WHERE ti.tag = n.name OR ti.implied = n.name)";
+
+#[derive(FromRow)]
+struct OrphanedTag {
+    name: String,
+    delete_on_no_references_left: bool,
+    is_in_tag: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub struct TagCleanupReport {
+    /// Tags without media that are (or would be) removed from `Tag` and `TagDetail`
+    pub removed: Vec<String>,
+    /// Tags without media kept because `delete_on_no_references_left` is off
+    pub kept: Vec<String>,
+}
+
+/// Pushes `IN (...)` for the names
+fn push_names(query_builder: &mut QueryBuilder<Sqlite>, names: &[String]) {
+    query_builder.push(" IN (");
+    let mut separated = query_builder.separated(", ");
+    for name in names {
+        separated.push_bind(name.clone());
+    }
+    query_builder.push(")");
+}
+
+/// Removes the orphaned tags, only checking `tags` if it is `Some`
+///
+/// Kept tags that lost their `Tag` row are added back to it, they were removed before the flag was honored.
+pub(super) async fn remove_orphaned_tags(
+    tags: Option<&[String]>,
+    dry_run: bool,
+    conn: &mut SqliteConnection,
+) -> sqlx::Result<TagCleanupReport> {
+    let mut orphaned: Vec<OrphanedTag> = vec![];
+
+    match tags {
+        Some(tags) => {
+            for chunk in tags.chunks(MAX_BINDS) {
+                let mut query_builder: QueryBuilder<Sqlite> =
+                    QueryBuilder::new(format!("{ORPHANED_TAGS_SQL} AND n.name"));
+                push_names(&mut query_builder, chunk);
+
+                orphaned.extend(
+                    query_builder
+                        .build_query_as::<OrphanedTag>()
+                        .fetch_all(&mut *conn)
+                        .await?,
+                );
+            }
+        }
+        None => {
+            let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(ORPHANED_TAGS_SQL);
+            orphaned = query_builder.build_query_as().fetch_all(&mut *conn).await?;
+        }
+    }
+
+    let (removed, kept): (Vec<OrphanedTag>, Vec<OrphanedTag>) = orphaned
+        .into_iter()
+        .partition(|tag| tag.delete_on_no_references_left);
+
+    let mut report = TagCleanupReport {
+        removed: removed.iter().map(|tag| tag.name.clone()).collect(),
+        kept: kept.iter().map(|tag| tag.name.clone()).collect(),
+    };
+    report.removed.sort();
+    report.kept.sort();
+
+    if dry_run {
+        return Ok(report);
+    }
+
+    for chunk in report.removed.chunks(MAX_BINDS) {
+        for table in ["Tag", "TagDetail"] {
+            let mut query_builder: QueryBuilder<Sqlite> =
+                QueryBuilder::new(format!("DELETE FROM {table} WHERE name"));
+            push_names(&mut query_builder, chunk);
+            query_builder.build().execute(&mut *conn).await?;
+        }
+    }
+
+    for tag in kept.iter().filter(|tag| !tag.is_in_tag) {
+        query("INSERT INTO Tag(name) VALUES (?)")
+            .bind(&tag.name)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    Ok(report)
+}
+
+/// Removes every tag without media that has `delete_on_no_references_left` set, with `dry_run` only reports
+/// what would be removed
+pub async fn cleanup_orphaned_tags_impl(
+    dry_run: bool,
+    pool: &Pool<Sqlite>,
+) -> sqlx::Result<TagCleanupReport> {
+    let mut tx = pool.begin().await?;
+    let report = remove_orphaned_tags(None, dry_run, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(report)
+}
+
+#[sqlx::test]
+async fn test_cleanup_orphaned_tags(pool: Pool<Sqlite>) {
+    use sqlx::{migrate, query_scalar};
+
+    use super::{aliases::add_alias_impl, insert_tags, remove_tags};
+    use crate::test_util::db_utils::insert_image;
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    insert_image(&pool, "1").await;
+
+    let tags = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+
+    insert_tags(
+        tags(&["cat", "dog", "bird", "fish"]),
+        &pool,
+        Some("1".to_string()),
+        None,
+    )
+    .await;
+    // unused but the target of an alias
+    insert_tags(tags(&["kitten"]), &pool, None, None).await;
+    add_alias_impl("kitty", "kitten", &pool).await.unwrap();
+
+    query("UPDATE TagDetail SET delete_on_no_references_left = false WHERE name = 'dog'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    // removing the tags from the only media cleans them up right away
+    remove_tags(tags(&["cat", "dog"]), &pool, Some("1".to_string())).await;
+
+    let tag_names = async || -> Vec<String> {
+        query_scalar("SELECT name FROM Tag ORDER BY name")
+            .fetch_all(&pool)
+            .await
+            .unwrap()
+    };
+    assert_eq!(tag_names().await, vec!["bird", "dog", "fish", "kitten"]);
+
+    // media removed without going through `remove_tags` leaves orphans behind
+    query("DELETE FROM HashTagPair WHERE tag_name IN ('bird', 'fish')")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let report = cleanup_orphaned_tags_impl(true, &pool).await.unwrap();
+    assert_eq!(
+        report,
+        TagCleanupReport {
+            removed: tags(&["bird", "fish"]),
+            kept: tags(&["dog"]),
+        }
+    );
+    assert_eq!(tag_names().await, vec!["bird", "dog", "fish", "kitten"]);
+
+    assert_eq!(
+        cleanup_orphaned_tags_impl(false, &pool).await.unwrap(),
+        report
+    );
+    assert_eq!(tag_names().await, vec!["dog", "kitten"]);
+
+    let detail_names: Vec<String> = query_scalar("SELECT name FROM TagDetail ORDER BY name")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(detail_names, vec!["dog", "kitten"]);
+}
@@ -0,0 +1,342 @@
+//! Named groups of tags with a color, like `character` or `artist`
+//!
+//! Tags reference their group by name in `TagDetail._group`. A tag is shown with the color of its group,
+//! unless it has `override_group_color` set or the group has no color, then its own color is used.
+
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, QueryBuilder, Sqlite, query, query_as};
+use thiserror::Error;
+
+use crate::db::{MAX_BINDS, impl_from_sqlx_error, schema::TagGroup};
+
+use super::{
+    aliases::{resolve_alias, resolve_aliases},
+    validate_name,
+};
+
+/// Joined to queries selecting from `TagDetail` to resolve the group and color of the tags
+pub(crate) const GROUP_JOIN_SQL: &str = " LEFT JOIN TagGroup ON TagGroup.name = TagDetail._group ";
+
+/// Color a tag from `TagDetail` is shown with, needs `GROUP_JOIN_SQL`
+pub(crate) const RESOLVED_COLOR_SQL: &str = "CASE WHEN TagDetail.override_group_color \
+    OR TagGroup.color IS NULL THEN TagDetail.color ELSE TagGroup.color END";
+
+#[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub enum GroupError {
+    #[error("Group names can't be empty")]
+    EmptyName,
+    #[error("There is no group named `{0}`")]
+    NotFound(String),
+    #[error("A group named `{0}` already exists")]
+    NameTaken(String),
+    #[error("Database error: {0}")]
+    Database(String),
+}
+
+impl_from_sqlx_error!(GroupError);
+
+/// Empty colors are no color
+fn normalize_color(color: Option<String>) -> Option<String> {
+    color
+        .map(|color| color.trim().to_string())
+        .filter(|color| !color.is_empty())
+}
+
+fn map_unique_violation(error: sqlx::Error, name: &str) -> GroupError {
+    match error {
+        sqlx::Error::Database(e) if e.is_unique_violation() => {
+            GroupError::NameTaken(name.to_string())
+        }
+        e => e.into(),
+    }
+}
+
+pub async fn create_group_impl(
+    name: &str,
+    color: Option<String>,
+    pool: &Pool<Sqlite>,
+) -> Result<(), GroupError> {
+    let name = validate_name(name, GroupError::EmptyName)?;
+
+    query("INSERT INTO TagGroup(name, color) VALUES (?, ?)")
+        .bind(name)
+        .bind(normalize_color(color))
+        .execute(pool)
+        .await
+        .map_err(|e| map_unique_violation(e, name))?;
+
+    Ok(())
+}
+
+/// Renames the group, its tags stay in it
+pub async fn rename_group_impl(
+    old_name: &str,
+    new_name: &str,
+    pool: &Pool<Sqlite>,
+) -> Result<(), GroupError> {
+    let new_name = validate_name(new_name, GroupError::EmptyName)?;
+
+    let mut tx = pool.begin().await?;
+
+    let result = query("UPDATE TagGroup SET name = ? WHERE name = ?")
+        .bind(new_name)
+        .bind(old_name)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| map_unique_violation(e, new_name))?;
+
+    if result.rows_affected() == 0 {
+        return Err(GroupError::NotFound(old_name.to_string()));
+    }
+
+    query("UPDATE TagDetail SET _group = ? WHERE _group = ?")
+        .bind(new_name)
+        .bind(old_name)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Sets the color of the group, `None` removes it
+pub async fn set_group_color_impl(
+    name: &str,
+    color: Option<String>,
+    pool: &Pool<Sqlite>,
+) -> Result<(), GroupError> {
+    let result = query("UPDATE TagGroup SET color = ? WHERE name = ?")
+        .bind(normalize_color(color))
+        .bind(name)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(GroupError::NotFound(name.to_string()));
+    }
+
+    Ok(())
+}
+
+/// Deletes the group, its tags are left without a group
+pub async fn delete_group_impl(name: &str, pool: &Pool<Sqlite>) -> Result<(), GroupError> {
+    let mut tx = pool.begin().await?;
+
+    let result = query("DELETE FROM TagGroup WHERE name = ?")
+        .bind(name)
+        .execute(&mut *tx)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(GroupError::NotFound(name.to_string()));
+    }
+
+    query("UPDATE TagDetail SET _group = NULL WHERE _group = ?")
+        .bind(name)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow, Serialize, Deserialize, specta::Type)]
+pub struct TagGroupWithCount {
+    pub name: String,
+    pub color: Option<String>,
+    /// Number of tags in the group
+    pub tag_count: i64,
+}
+
+/// Every group ordered by name
+pub async fn get_groups_impl(pool: &Pool<Sqlite>) -> Result<Vec<TagGroupWithCount>, GroupError> {
+    let groups = query_as(
+        "SELECT TagGroup.name, TagGroup.color, COUNT(TagDetail.name) AS tag_count FROM TagGroup \
+        LEFT JOIN TagDetail ON TagDetail._group = TagGroup.name \
+        GROUP BY TagGroup.name ORDER BY TagGroup.name COLLATE NOCASE",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(groups)
+}
+
+pub async fn get_group_impl(name: &str, pool: &Pool<Sqlite>) -> Result<TagGroup, GroupError> {
+    let group: Option<TagGroup> = query_as("SELECT name, color FROM TagGroup WHERE name = ?")
+        .bind(name)
+        .fetch_optional(pool)
+        .await?;
+
+    group.ok_or_else(|| GroupError::NotFound(name.to_string()))
+}
+
+/// Moves the tags to the group, or out of their groups if `group` is `None`
+///
+/// Aliases are resolved to their canonical tags, tags that don't exist are ignored.
+pub async fn assign_tags_to_group_impl(
+    tags: Vec<String>,
+    group: Option<&str>,
+    pool: &Pool<Sqlite>,
+) -> Result<(), GroupError> {
+    if let Some(group) = group {
+        get_group_impl(group, pool).await?;
+    }
+
+    let tags = resolve_aliases(tags, pool).await?;
+
+    let mut tx = pool.begin().await?;
+
+    for chunk in tags.chunks(MAX_BINDS - 1) {
+        let mut query_builder: QueryBuilder<Sqlite> =
+            QueryBuilder::new("UPDATE TagDetail SET _group = ");
+        query_builder.push_bind(group.map(str::to_string));
+        query_builder.push(" WHERE name IN (");
+        let mut separated = query_builder.separated(", ");
+        for tag in chunk {
+            separated.push_bind(tag.clone());
+        }
+        query_builder.push(")");
+
+        query_builder.build().execute(&mut *tx).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Sets the own color of the tag, with `override_group_color` it is used even if its group has a color
+pub async fn set_tag_color_impl(
+    tag: &str,
+    color: Option<String>,
+    override_group_color: bool,
+    pool: &Pool<Sqlite>,
+) -> Result<(), GroupError> {
+    let tag = resolve_alias(tag, pool).await?;
+
+    query("UPDATE TagDetail SET color = ?, override_group_color = ? WHERE name = ?")
+        .bind(normalize_color(color))
+        .bind(override_group_color)
+        .bind(tag)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Color the tag is shown with
+pub async fn get_tag_color_impl(
+    tag: &str,
+    pool: &Pool<Sqlite>,
+) -> Result<Option<String>, GroupError> {
+    let tag = resolve_alias(tag, pool).await?;
+
+    let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+        "SELECT {RESOLVED_COLOR_SQL} FROM TagDetail{GROUP_JOIN_SQL}WHERE TagDetail.name = "
+    ));
+    query_builder.push_bind(tag);
+
+    let color: Option<Option<String>> = query_builder
+        .build_query_scalar()
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(color.flatten())
+}
+
+#[sqlx::test]
+async fn test_tag_groups(pool: Pool<Sqlite>) {
+    use sqlx::migrate;
+
+    use super::{AllTagsOrderingCriteria, get_list_of_all_tags_with_details_impl, insert_tags};
+    use crate::test_util::db_utils::insert_image;
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    insert_image(&pool, "1").await;
+
+    let tags = vec!["alice".to_string(), "bob".to_string(), "forest".to_string()];
+    insert_tags(tags, &pool, Some("1".to_string()), None).await;
+
+    create_group_impl("character", Some("#ff0000".to_string()), &pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        create_group_impl(" character ", None, &pool).await,
+        Err(GroupError::NameTaken("character".to_string()))
+    );
+
+    assign_tags_to_group_impl(
+        vec!["alice".to_string(), "bob".to_string()],
+        Some("character"),
+        &pool,
+    )
+    .await
+    .unwrap();
+    set_tag_color_impl("bob", Some("#0000ff".to_string()), true, &pool)
+        .await
+        .unwrap();
+    set_tag_color_impl("forest", Some("#00ff00".to_string()), false, &pool)
+        .await
+        .unwrap();
+
+    let resolved = async || -> Vec<(String, Option<String>, Option<String>)> {
+        get_list_of_all_tags_with_details_impl(&pool, AllTagsOrderingCriteria::Alphabetic)
+            .await
+            .into_iter()
+            .map(|t| (t.tag_name, t.resolved_group, t.resolved_color))
+            .collect()
+    };
+    let entry = |name: &str, group: Option<&str>, color: Option<&str>| {
+        (
+            name.to_string(),
+            group.map(str::to_string),
+            color.map(str::to_string),
+        )
+    };
+
+    // the group color wins unless the tag overrides it, tags without a group use their own
+    assert_eq!(
+        resolved().await,
+        vec![
+            entry("alice", Some("character"), Some("#ff0000")),
+            entry("bob", Some("character"), Some("#0000ff")),
+            entry("forest", None, Some("#00ff00")),
+        ]
+    );
+
+    rename_group_impl("character", "person", &pool)
+        .await
+        .unwrap();
+    set_group_color_impl("person", None, &pool).await.unwrap();
+    assert_eq!(get_tag_color_impl("alice", &pool).await.unwrap(), None);
+    assert_eq!(
+        get_groups_impl(&pool).await.unwrap(),
+        vec![TagGroupWithCount {
+            name: "person".to_string(),
+            color: None,
+            tag_count: 2
+        }]
+    );
+
+    assert_eq!(
+        assign_tags_to_group_impl(vec!["forest".to_string()], Some("place"), &pool).await,
+        Err(GroupError::NotFound("place".to_string()))
+    );
+
+    delete_group_impl("person", &pool).await.unwrap();
+    assert_eq!(
+        resolved().await,
+        vec![
+            entry("alice", None, None),
+            entry("bob", None, Some("#0000ff")),
+            entry("forest", None, Some("#00ff00")),
+        ]
+    );
+    assert_eq!(
+        delete_group_impl("person", &pool).await,
+        Err(GroupError::NotFound("person".to_string()))
+    );
+}
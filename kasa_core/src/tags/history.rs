@@ -0,0 +1,220 @@
+//! Journal of the tags added to and removed from media by hand
+//!
+//! Edits from the tag field, removed tags and bulk edits are appended to `TagHistory`, every edit is a batch
+//! that can be reverted as a whole. Reverting is an edit too, so it is recorded as a new batch instead of
+//! removing the old one. Tags added by indexers and importers, and implied tags, aren't recorded.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    Pool, QueryBuilder, Sqlite, SqliteConnection, prelude::FromRow, query_as, query_scalar,
+};
+use thiserror::Error;
+
+use crate::db::{MAX_BINDS, impl_from_sqlx_error, schema::HashTagPair};
+
+use super::{IMPLICATION_SOURCE, bulk::BulkTagUndo, bulk::revert_changes};
+
+#[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub enum HistoryError {
+    #[error("There is no tag history batch {0}")]
+    BatchNotFound(i64),
+    #[error("Database error: {0}")]
+    Database(String),
+}
+
+impl_from_sqlx_error!(HistoryError);
+
+/// A tag added to or removed from a media
+#[derive(Debug, Clone, PartialEq, FromRow, Serialize, Deserialize, specta::Type)]
+pub struct TagChange {
+    /// Changes made by the same edit share the batch
+    pub batch: i64,
+    pub hash: String,
+    pub tag_name: String,
+    /// `false` if the tag was removed
+    pub added: bool,
+    pub source: Option<String>,
+    pub source_type: Option<String>,
+    /// Unix timestamp in milliseconds
+    pub time: i64,
+}
+
+impl From<TagChange> for HashTagPair {
+    fn from(change: TagChange) -> Self {
+        HashTagPair {
+            hash: change.hash,
+            tag_name: change.tag_name,
+            source: change.source,
+            source_type: change.source_type,
+        }
+    }
+}
+
+/// Appends the changes as a new batch, returns the batch or `None` if nothing changed
+///
+/// Implied pairs are skipped, they follow the other tags of the media.
+pub(super) async fn record_tag_changes(
+    added: &[HashTagPair],
+    removed: &[HashTagPair],
+    conn: &mut SqliteConnection,
+) -> sqlx::Result<Option<i64>> {
+    let changes: Vec<(&HashTagPair, bool)> = added
+        .iter()
+        .map(|pair| (pair, true))
+        .chain(removed.iter().map(|pair| (pair, false)))
+        .filter(|(pair, _)| pair.source.as_deref() != Some(IMPLICATION_SOURCE))
+        .collect();
+
+    if changes.is_empty() {
+        return Ok(None);
+    }
+
+    let batch: i64 = query_scalar("SELECT COALESCE(MAX(batch), 0) + 1 FROM TagHistory")
+        .fetch_one(&mut *conn)
+        .await?;
+    let time = Utc::now().timestamp_millis();
+
+    for chunk in changes.chunks(MAX_BINDS / 7) {
+        let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(
+            "INSERT INTO TagHistory(batch, hash, tag_name, added, source, source_type, time) ",
+        );
+        query_builder.push_values(chunk, |mut builder, (pair, added)| {
+            builder
+                .push_bind(batch)
+                .push_bind(pair.hash.clone())
+                .push_bind(pair.tag_name.clone())
+                .push_bind(*added)
+                .push_bind(pair.source.clone())
+                .push_bind(pair.source_type.clone())
+                .push_bind(time);
+        });
+
+        query_builder.build().execute(&mut *conn).await?;
+    }
+
+    Ok(Some(batch))
+}
+
+/// Every recorded change of the media, newest first
+pub async fn get_tag_history_impl(
+    hash: &str,
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<TagChange>, HistoryError> {
+    let changes =
+        query_as("SELECT * FROM TagHistory WHERE hash = ? ORDER BY batch DESC, added, tag_name")
+            .bind(hash)
+            .fetch_all(pool)
+            .await?;
+
+    Ok(changes)
+}
+
+/// Changes made by the batch, which can span many media
+pub async fn get_tag_history_batch_impl(
+    batch: i64,
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<TagChange>, HistoryError> {
+    let changes: Vec<TagChange> =
+        query_as("SELECT * FROM TagHistory WHERE batch = ? ORDER BY hash, added, tag_name")
+            .bind(batch)
+            .fetch_all(pool)
+            .await?;
+
+    if changes.is_empty() {
+        return Err(HistoryError::BatchNotFound(batch));
+    }
+
+    Ok(changes)
+}
+
+/// Removes the tags the batch added and restores the ones it removed, returns the batch of the revert
+///
+/// Returns `None` if the media already has the tags it had before the batch.
+pub async fn revert_tag_batch_impl(
+    batch: i64,
+    pool: &Pool<Sqlite>,
+) -> Result<Option<i64>, HistoryError> {
+    let changes = get_tag_history_batch_impl(batch, pool).await?;
+
+    let (added, removed): (Vec<TagChange>, Vec<TagChange>) =
+        changes.into_iter().partition(|change| change.added);
+    let undo = BulkTagUndo {
+        added: added.into_iter().map(HashTagPair::from).collect(),
+        removed: removed.into_iter().map(HashTagPair::from).collect(),
+    };
+
+    let mut tx = pool.begin().await?;
+    let revert_batch = revert_changes(&undo, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(revert_batch)
+}
+
+#[sqlx::test]
+async fn test_tag_history(pool: Pool<Sqlite>) {
+    use sqlx::migrate;
+
+    use super::{remove_tags, update_tags_impl};
+    use crate::test_util::db_utils::insert_image;
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    insert_image(&pool, "1").await;
+
+    let media_tags = async || -> Vec<String> {
+        query_scalar("SELECT tag_name FROM HashTagPair WHERE hash = '1' ORDER BY tag_name")
+            .fetch_all(&pool)
+            .await
+            .unwrap()
+    };
+    let history = async || -> Vec<(i64, String, bool)> {
+        get_tag_history_impl("1", &pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|change| (change.batch, change.tag_name, change.added))
+            .collect()
+    };
+    let change = |batch: i64, tag: &str, added: bool| (batch, tag.to_string(), added);
+
+    update_tags_impl("cat, sky", "1".to_string(), &pool).await;
+    // nothing changed, no batch is recorded
+    update_tags_impl("sky, cat", "1".to_string(), &pool).await;
+    // an accidental edit through the text box
+    update_tags_impl("dog", "1".to_string(), &pool).await;
+    remove_tags(vec!["dog".to_string()], &pool, Some("1".to_string())).await;
+
+    assert_eq!(
+        history().await,
+        vec![
+            change(3, "dog", false),
+            change(2, "cat", false),
+            change(2, "sky", false),
+            change(2, "dog", true),
+            change(1, "cat", true),
+            change(1, "sky", true),
+        ]
+    );
+    assert_eq!(media_tags().await, Vec::<String>::new());
+
+    assert_eq!(revert_tag_batch_impl(2, &pool).await.unwrap(), Some(4));
+    assert_eq!(media_tags().await, vec!["cat", "sky"]);
+
+    // the revert is a batch of its own
+    assert_eq!(
+        get_tag_history_batch_impl(4, &pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|change| (change.tag_name, change.added))
+            .collect::<Vec<_>>(),
+        vec![("cat".to_string(), true), ("sky".to_string(), true)]
+    );
+    assert_eq!(revert_tag_batch_impl(2, &pool).await.unwrap(), None);
+
+    assert_eq!(
+        revert_tag_batch_impl(10, &pool).await,
+        Err(HistoryError::BatchNotFound(10))
+    );
+}
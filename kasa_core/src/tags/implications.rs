@@ -0,0 +1,490 @@
+//! Tags implied by other tags, like `cat_ears` implying `animal_ears`
+//!
+//! Implications are transitive and can't form cycles. Searches always match the tags that imply the searched
+//! tag, `ImplicationMode` decides if the implied tags are also stored in `HashTagPair`, which makes them show
+//! up in the tags of the media. Stored implied tags use `IMPLICATION_SOURCE` as their source so they can be
+//! recomputed when implications change, tags added by hand are never removed by this.
+
+use std::collections::{HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    Pool, QueryBuilder, Sqlite, SqliteConnection, prelude::FromRow, query, query_as, query_scalar,
+};
+use thiserror::Error;
+
+use crate::db::impl_from_sqlx_error;
+
+use super::{IMPLICATION_SOURCE, aliases::resolve_alias, insert_tags, validate_name};
+
+const IMPLICATION_MODE_KEY: &str = "implication_mode";
+
+/// Every `(tag, implied)` pair of the transitive closure of `TagImplication`
+const CLOSURE_CTE: &str = "WITH RECURSIVE closure(tag, implied) AS ( \
+    SELECT tag, implied FROM TagImplication \
+    UNION SELECT c.tag, ti.implied FROM closure c JOIN TagImplication ti ON ti.tag = c.implied) ";
+
+#[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub enum ImplicationError {
+    #[error("Tag names can't be empty")]
+    EmptyName,
+    #[error("`{0}` can't imply itself")]
+    ImpliesItself(String),
+    #[error("Implication would create the cycle {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+    #[error("`{0}` doesn't imply `{1}`")]
+    NotFound(String, String),
+    #[error("Database error: {0}")]
+    Database(String),
+}
+
+impl_from_sqlx_error!(ImplicationError);
+
+#[derive(Debug, Clone, PartialEq, FromRow, Serialize, Deserialize, specta::Type)]
+pub struct TagImplication {
+    pub tag: String,
+    pub implied: String,
+}
+
+/// How implied tags are applied, stored per database
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, specta::Type)]
+pub enum ImplicationMode {
+    /// Implied tags are stored in `HashTagPair` and show up in the tags of the media
+    #[default]
+    Materialize,
+    /// Implied tags are only matched by searches
+    QueryTime,
+}
+
+async fn read_implication_mode(conn: &mut SqliteConnection) -> sqlx::Result<ImplicationMode> {
+    let value: Option<String> = query_scalar("SELECT value FROM Setting WHERE key = ?")
+        .bind(IMPLICATION_MODE_KEY)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    Ok(value
+        .and_then(|value| serde_json::from_str(&value).ok())
+        .unwrap_or_default())
+}
+
+pub async fn get_implication_mode(pool: &Pool<Sqlite>) -> sqlx::Result<ImplicationMode> {
+    read_implication_mode(&mut *pool.acquire().await?).await
+}
+
+/// Changes the mode, stores the implied tags of every media or removes the stored ones
+pub async fn set_implication_mode_impl(
+    mode: ImplicationMode,
+    pool: &Pool<Sqlite>,
+) -> Result<(), ImplicationError> {
+    let mut tx = pool.begin().await?;
+
+    query("INSERT INTO Setting(key, value) VALUES (?, ?) ON CONFLICT(key) DO UPDATE SET value = excluded.value")
+        .bind(IMPLICATION_MODE_KEY)
+        .bind(serde_json::to_string(&mode).unwrap())
+        .execute(&mut *tx)
+        .await?;
+
+    clear_materialized(&MediaScope::All, &mut tx).await?;
+    if mode == ImplicationMode::Materialize {
+        materialize(&MediaScope::All, &mut tx).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Media whose stored implied tags are updated
+pub(super) enum MediaScope<'a> {
+    All,
+    Hash(&'a str),
+    Hashes(&'a [String]),
+    /// Media whose hash is selected by the subquery
+    Subquery(&'static str),
+    /// Media with the tag or a tag implying it, the only ones affected when the implications of the tag change
+    ImplyingTag(&'a str),
+}
+
+impl MediaScope<'_> {
+    fn push_condition(&self, query_builder: &mut QueryBuilder<Sqlite>, column: &str) {
+        match self {
+            MediaScope::All => {
+                query_builder.push("1 = 1");
+            }
+            MediaScope::Hash(hash) => {
+                query_builder.push(format!("{column} = "));
+                query_builder.push_bind(hash.to_string());
+            }
+            MediaScope::Hashes(hashes) => {
+                query_builder.push(format!("{column} IN ("));
+                let mut separated = query_builder.separated(", ");
+                for hash in hashes.iter() {
+                    separated.push_bind(hash.clone());
+                }
+                separated.push_unseparated(")");
+            }
+            MediaScope::Subquery(subquery) => {
+                query_builder.push(format!("{column} IN ({subquery})"));
+            }
+            MediaScope::ImplyingTag(tag) => {
+                query_builder.push(format!(
+                    "{column} IN (WITH RECURSIVE implying(tag) AS (SELECT "
+                ));
+                query_builder.push_bind(tag.to_string());
+                query_builder.push(
+                    " UNION SELECT ti.tag FROM TagImplication ti JOIN implying i ON ti.implied = i.tag) \
+                    SELECT hash FROM HashTagPair WHERE tag_name IN (SELECT tag FROM implying))",
+                );
+            }
+        }
+    }
+}
+
+/// Removes the stored implied tags of the media
+async fn clear_materialized(
+    scope: &MediaScope<'_>,
+    conn: &mut SqliteConnection,
+) -> sqlx::Result<()> {
+    let mut query_builder: QueryBuilder<Sqlite> =
+        QueryBuilder::new("DELETE FROM HashTagPair WHERE source = ");
+    query_builder.push_bind(IMPLICATION_SOURCE);
+    query_builder.push(" AND ");
+    scope.push_condition(&mut query_builder, "hash");
+
+    query_builder.build().execute(&mut *conn).await?;
+
+    Ok(())
+}
+
+/// Stores the tags implied by the tags of the media
+///
+/// The closure only starts from the tags of the media, so refreshing a few media doesn't walk every implication.
+async fn materialize(scope: &MediaScope<'_>, conn: &mut SqliteConnection) -> sqlx::Result<()> {
+    let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(
+        "WITH RECURSIVE closure(tag, implied) AS ( \
+        SELECT tag, implied FROM TagImplication WHERE tag IN (SELECT tag_name FROM HashTagPair WHERE ",
+    );
+    scope.push_condition(&mut query_builder, "hash");
+    query_builder.push(
+        ") UNION SELECT c.tag, ti.implied FROM closure c JOIN TagImplication ti ON ti.tag = c.implied) \
+        INSERT OR IGNORE INTO HashTagPair(hash, tag_name, source) SELECT htp.hash, c.implied, ",
+    );
+    query_builder.push_bind(IMPLICATION_SOURCE);
+    query_builder.push(" FROM HashTagPair htp JOIN closure c ON c.tag = htp.tag_name WHERE ");
+    scope.push_condition(&mut query_builder, "htp.hash");
+
+    query_builder.build().execute(&mut *conn).await?;
+
+    Ok(())
+}
+
+/// Recomputes the stored implied tags of the media after their tags changed
+pub(super) async fn refresh_implied_tags_of(
+    scope: &MediaScope<'_>,
+    conn: &mut SqliteConnection,
+) -> sqlx::Result<()> {
+    let mode = read_implication_mode(conn).await?;
+
+    clear_materialized(scope, conn).await?;
+    if mode == ImplicationMode::Materialize {
+        materialize(scope, conn).await?;
+    }
+
+    Ok(())
+}
+
+/// Recomputes the stored implied tags of the media after its tags changed
+pub async fn refresh_implied_tags(hash: &str, pool: &Pool<Sqlite>) -> sqlx::Result<()> {
+    refresh_implied_tags_of(&MediaScope::Hash(hash), &mut *pool.acquire().await?).await
+}
+
+/// Recomputes the stored implied tags of the media affected by a change to the implications of `tag`
+pub(super) async fn refresh_implied_tags_around(
+    tag: &str,
+    conn: &mut SqliteConnection,
+) -> sqlx::Result<()> {
+    refresh_implied_tags_of(&MediaScope::ImplyingTag(tag), conn).await
+}
+
+/// Path of implications from `from` to `to`, if there is one
+fn find_path(
+    from: &str,
+    to: &str,
+    implications: &HashMap<String, Vec<String>>,
+) -> Option<Vec<String>> {
+    let mut previous: HashMap<&str, &str> = HashMap::new();
+    let mut queue = VecDeque::from([from]);
+
+    while let Some(tag) = queue.pop_front() {
+        if tag == to {
+            let mut path = vec![to.to_string()];
+            let mut current = to;
+            while let Some(&tag) = previous.get(current) {
+                path.push(tag.to_string());
+                current = tag;
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        for implied in implications.get(tag).into_iter().flatten() {
+            if implied != from && !previous.contains_key(implied.as_str()) {
+                previous.insert(implied, tag);
+                queue.push_back(implied);
+            }
+        }
+    }
+
+    None
+}
+
+/// Implied tags of every tag that implies something
+fn implication_graph(implications: Vec<TagImplication>) -> HashMap<String, Vec<String>> {
+    let mut graph: HashMap<String, Vec<String>> = HashMap::new();
+    for implication in implications {
+        graph
+            .entry(implication.tag)
+            .or_default()
+            .push(implication.implied);
+    }
+
+    graph
+}
+
+/// A cycle of implications starting and ending with the same tag, if there is one
+///
+/// Adding an implication can't create a cycle, but merging tags can join both ends of a path.
+pub(super) async fn find_implication_cycle(
+    conn: &mut SqliteConnection,
+) -> sqlx::Result<Option<Vec<String>>> {
+    let implications = read_implications(conn).await?;
+    let graph = implication_graph(implications.clone());
+
+    Ok(implications
+        .into_iter()
+        .find_map(|TagImplication { tag, implied }| {
+            let mut cycle = find_path(&implied, &tag, &graph)?;
+            cycle.insert(0, tag);
+            Some(cycle)
+        }))
+}
+
+/// Makes `tag` imply `implied`, media tagged with `tag` gets `implied` if the mode is `Materialize`
+///
+/// Aliases are resolved to their canonical tags.
+pub async fn add_implication_impl(
+    tag: &str,
+    implied: &str,
+    pool: &Pool<Sqlite>,
+) -> Result<(), ImplicationError> {
+    let tag = validate_name(tag, ImplicationError::EmptyName)?;
+    let implied = validate_name(implied, ImplicationError::EmptyName)?;
+
+    let tag = resolve_alias(tag, pool).await?;
+    let implied = resolve_alias(implied, pool).await?;
+
+    if tag == implied {
+        return Err(ImplicationError::ImpliesItself(tag));
+    }
+
+    let implications = implication_graph(get_implications_impl(pool).await?);
+
+    if let Some(mut cycle) = find_path(&implied, &tag, &implications) {
+        cycle.insert(0, tag);
+        return Err(ImplicationError::Cycle(cycle));
+    }
+
+    query("INSERT OR IGNORE INTO TagImplication(tag, implied) VALUES (?, ?)")
+        .bind(&tag)
+        .bind(&implied)
+        .execute(pool)
+        .await?;
+
+    // so the implied tag can be searched and picked even before any media has it
+    insert_tags(vec![tag.clone(), implied], pool, None, None).await;
+
+    let mut tx = pool.begin().await?;
+    refresh_implied_tags_around(&tag, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Removes the implication, stored implied tags that aren't implied anymore are removed
+pub async fn remove_implication_impl(
+    tag: &str,
+    implied: &str,
+    pool: &Pool<Sqlite>,
+) -> Result<(), ImplicationError> {
+    let result = query("DELETE FROM TagImplication WHERE tag = ? AND implied = ?")
+        .bind(tag)
+        .bind(implied)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(ImplicationError::NotFound(
+            tag.to_string(),
+            implied.to_string(),
+        ));
+    }
+
+    let mut tx = pool.begin().await?;
+    refresh_implied_tags_around(tag, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+async fn read_implications(conn: &mut SqliteConnection) -> sqlx::Result<Vec<TagImplication>> {
+    query_as("SELECT tag, implied FROM TagImplication ORDER BY tag, implied")
+        .fetch_all(&mut *conn)
+        .await
+}
+
+/// Every direct implication, ordered by the implying tag
+pub async fn get_implications_impl(
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<TagImplication>, ImplicationError> {
+    Ok(read_implications(&mut *pool.acquire().await?).await?)
+}
+
+/// Every tag implied by `tag`, directly or through other tags
+pub async fn get_implied_tags_impl(
+    tag: &str,
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<String>, ImplicationError> {
+    let tag = resolve_alias(tag, pool).await?;
+
+    let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+        "{CLOSURE_CTE} SELECT implied FROM closure WHERE tag = "
+    ));
+    query_builder.push_bind(tag);
+    query_builder.push(" ORDER BY implied");
+
+    let implied = query_builder.build_query_scalar().fetch_all(pool).await?;
+
+    Ok(implied)
+}
+
+#[test]
+fn test_find_path() {
+    let implications = HashMap::from([
+        ("a".to_string(), vec!["b".to_string()]),
+        ("b".to_string(), vec!["c".to_string(), "d".to_string()]),
+        ("d".to_string(), vec!["a".to_string()]),
+    ]);
+
+    assert_eq!(
+        find_path("b", "a", &implications),
+        Some(vec!["b".to_string(), "d".to_string(), "a".to_string()])
+    );
+    assert_eq!(find_path("c", "a", &implications), None);
+}
+
+#[sqlx::test]
+async fn test_implications(pool: Pool<Sqlite>) {
+    use itertools::Itertools;
+    use sqlx::migrate;
+
+    use super::{remove_tags, update_tags_impl};
+    use crate::test_util::db_utils::{insert_image, search_hashes};
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    for hash in ["1", "2", "3"] {
+        insert_image(&pool, hash).await;
+    }
+
+    let tags = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect_vec();
+
+    insert_tags(tags(&["cat_ears"]), &pool, Some("1".to_string()), None).await;
+    insert_tags(tags(&["fox_ears"]), &pool, Some("2".to_string()), None).await;
+
+    add_implication_impl("cat_ears", "animal_ears", &pool)
+        .await
+        .unwrap();
+    add_implication_impl("animal_ears", "ears", &pool)
+        .await
+        .unwrap();
+
+    let media_tags = async |hash: &str| -> Vec<String> {
+        query_scalar("SELECT tag_name FROM HashTagPair WHERE hash = ? ORDER BY tag_name")
+            .bind(hash)
+            .fetch_all(&pool)
+            .await
+            .unwrap()
+    };
+
+    // existing media gets the implied tags transitively
+    assert_eq!(
+        media_tags("1").await,
+        vec!["animal_ears", "cat_ears", "ears"]
+    );
+
+    // and so does media tagged afterwards
+    add_implication_impl("fox_ears", "animal_ears", &pool)
+        .await
+        .unwrap();
+    insert_tags(tags(&["cat_ears"]), &pool, Some("3".to_string()), None).await;
+    assert_eq!(
+        media_tags("2").await,
+        vec!["animal_ears", "ears", "fox_ears"]
+    );
+    assert_eq!(
+        media_tags("3").await,
+        vec!["animal_ears", "cat_ears", "ears"]
+    );
+
+    assert_eq!(
+        add_implication_impl("ears", "cat_ears", &pool).await,
+        Err(ImplicationError::Cycle(tags(&[
+            "ears",
+            "cat_ears",
+            "animal_ears",
+            "ears"
+        ])))
+    );
+    assert_eq!(
+        get_implied_tags_impl("cat_ears", &pool).await.unwrap(),
+        vec!["animal_ears", "ears"]
+    );
+
+    // editing the tags of a media keeps the implied ones in sync
+    update_tags_impl("fox_ears, whiskers", "2".to_string(), &pool).await;
+    assert_eq!(
+        media_tags("2").await,
+        vec!["animal_ears", "ears", "fox_ears", "whiskers"]
+    );
+    remove_tags(tags(&["cat_ears"]), &pool, Some("3".to_string())).await;
+    assert_eq!(media_tags("3").await, Vec::<String>::new());
+
+    // searches match implied tags without them being stored
+    set_implication_mode_impl(ImplicationMode::QueryTime, &pool)
+        .await
+        .unwrap();
+    assert_eq!(media_tags("1").await, vec!["cat_ears"]);
+    assert_eq!(search_hashes("ears", &pool).await, vec!["1", "2"]);
+    assert_eq!(
+        search_hashes("animal_ears, -fox_ears", &pool).await,
+        vec!["1"]
+    );
+
+    set_implication_mode_impl(ImplicationMode::Materialize, &pool)
+        .await
+        .unwrap();
+    assert_eq!(search_hashes("ears", &pool).await, vec!["1", "2"]);
+
+    remove_implication_impl("animal_ears", "ears", &pool)
+        .await
+        .unwrap();
+    assert_eq!(media_tags("1").await, vec!["animal_ears", "cat_ears"]);
+    assert_eq!(search_hashes("ears", &pool).await, Vec::<String>::new());
+    assert_eq!(
+        remove_implication_impl("animal_ears", "ears", &pool).await,
+        Err(ImplicationError::NotFound(
+            "animal_ears".to_string(),
+            "ears".to_string()
+        ))
+    );
+}
@@ -1,5 +1,15 @@
+pub mod aliases;
+pub mod bulk;
+pub mod cleanup;
+pub mod groups;
+pub mod history;
+pub mod implications;
 pub mod presets;
+pub mod rename;
 pub mod search;
+pub mod sidecar;
+pub mod writeback;
+pub mod xmp;
 
 use std::collections::HashSet;
 
@@ -7,9 +17,40 @@ use itertools::Itertools;
 use kasa_python::ExtractedTag;
 use log::trace;
 use serde::{Deserialize, Serialize};
-use sqlx::{Pool, QueryBuilder, Sqlite, prelude::FromRow, query, query_scalar};
-
-use crate::db::schema::TagDetail;
+use sqlx::{Pool, QueryBuilder, Sqlite, SqliteConnection, prelude::FromRow, query, query_scalar};
+
+use crate::{
+    db::{
+        MAX_BINDS,
+        schema::{HashTagPair, TagDetail},
+    },
+    tags::{
+        aliases::{resolve_aliases, resolve_extracted_tags},
+        cleanup::remove_orphaned_tags,
+        groups::{GROUP_JOIN_SQL, RESOLVED_COLOR_SQL},
+        history::record_tag_changes,
+        implications::{MediaScope, refresh_implied_tags_of},
+    },
+};
+
+/// `HashTagPair.source` of tags imported from gallery-dl metadata
+pub const GALLERY_DL_SOURCE: &str = "gallery_dl";
+/// `HashTagPair.source` of tags parsed from the prompts of AI generated images
+pub const AI_PROMPT_SOURCE: &str = "ai_prompt_import";
+/// `HashTagPair.source` of tags predicted by the image tagger model
+pub const AI_TAGGER_SOURCE: &str = "AI Tagger";
+/// `HashTagPair.source` of tags read from the metadata embedded in the media files
+pub const EMBEDDED_SOURCE: &str = "embedded";
+/// `HashTagPair.source` of tags implied by other tags of the media
+pub const IMPLICATION_SOURCE: &str = "implication";
+
+/// The name without its surrounding whitespace, `empty` if nothing is left of it
+pub(crate) fn validate_name<E>(name: &str, empty: E) -> Result<&str, E> {
+    match name.trim() {
+        "" => Err(empty),
+        name => Ok(name),
+    }
+}
 
 // Utilities to keep `Tag` and `TagFTS` tables in sync
 
@@ -17,55 +58,26 @@ use crate::db::schema::TagDetail;
 ///
 /// If the media_hash is `Some(_)` it creates the tags into `Tag` table and creates the `HashTagPairs` from provided tags
 /// and hash, if it is `None` it only creates the tags in the `Tag` table.
+///
+/// Aliases are inserted as their canonical tag, and the tags implied by the tags of the media are updated.
 pub async fn insert_tags(
     tags: Vec<String>,
     pool: &Pool<Sqlite>,
     media_hash: Option<String>,
     source: Option<String>,
 ) {
-    // This runs a query for every tag, might be possible to optimize this somehow?
+    let tags = resolve_aliases(tags, pool).await.unwrap();
+    let tags: Vec<(String, Option<String>)> = tags.into_iter().map(|tag| (tag, None)).collect();
 
-    // Insert tag pairs into HashTagPair
-    // Check if tags exist on Tag and TagFTS
-    // If it exists on Tag bump the count by one
     let mut tx = pool.begin().await.unwrap();
-
-    if let Some(media_hash) = media_hash {
-        for tag in &tags {
-            query("INSERT INTO HashTagPair(hash, tag_name, source) VALUES (?, ?, ?) ON CONFLICT DO NOTHING")
-                .bind(&media_hash)
-                .bind(tag)
-                .bind(&source)
-                .execute(&mut *tx)
-                .await
-                .unwrap();
-        }
-    }
-
-    for tag in &tags {
-        let does_tag_exist: Option<i64> = query_scalar("SELECT 1 FROM Tag WHERE name = ? ")
-            .bind(tag)
-            .fetch_optional(pool)
+    insert_tag_rows(&tags, media_hash.as_deref(), source.as_deref(), &mut tx)
+        .await
+        .unwrap();
+    if let Some(media_hash) = &media_hash {
+        refresh_implied_tags_of(&MediaScope::Hash(media_hash), &mut tx)
             .await
             .unwrap();
-
-        let does_tag_exist = does_tag_exist.is_some();
-
-        if !does_tag_exist {
-            query("INSERT INTO Tag(name) VALUES (?)")
-                .bind(tag)
-                .execute(&mut *tx)
-                .await
-                .unwrap();
-
-            query("INSERT INTO TagDetail(name) VALUES (?)")
-                .bind(tag)
-                .execute(&mut *tx)
-                .await
-                .unwrap();
-        }
     }
-
     tx.commit().await.unwrap();
 }
 
@@ -75,114 +87,175 @@ pub async fn insert_tags_with_source_types(
     media_hash: Option<String>,
     source: Option<String>,
 ) {
-    // This runs a query for every tag, might be possible to optimize this somehow?
+    let tags = resolve_extracted_tags(tags, pool).await.unwrap();
     trace!("Inserting gallery_dl tags: {:#?}", &tags);
-    // Insert tag pairs into HashTagPair
-    // Check if tags exist on Tag and TagFTS
-    // If it exists on Tag bump the count by one
+    let tags: Vec<(String, Option<String>)> = tags
+        .into_iter()
+        .map(|tag| (tag.name, Some(tag._type)))
+        .collect();
+
     let mut tx = pool.begin().await.unwrap();
+    insert_tag_rows(&tags, media_hash.as_deref(), source.as_deref(), &mut tx)
+        .await
+        .unwrap();
+    if let Some(media_hash) = &media_hash {
+        refresh_implied_tags_of(&MediaScope::Hash(media_hash), &mut tx)
+            .await
+            .unwrap();
+    }
+    tx.commit().await.unwrap();
+}
 
-    if let Some(media_hash) = media_hash {
-        for tag in &tags {
-            query("INSERT INTO HashTagPair(hash, tag_name, source, source_type) VALUES (?, ?, ?, ?) ON CONFLICT DO NOTHING")
-                .bind(&media_hash)
-                .bind(&tag.name)
-                .bind(&source)
-                .bind(&tag._type)
-                .execute(&mut *tx)
-                .await
-                .unwrap();
-        }
+/// `insert_tags_with_source_types` for many media in one transaction, the tags of each media are keyed by its
+/// hash
+///
+/// The implied tags of the media are refreshed once for the whole batch instead of after every media.
+pub async fn insert_media_tags_with_source_types(
+    media_tags: Vec<(String, Vec<ExtractedTag>)>,
+    source: &str,
+    pool: &Pool<Sqlite>,
+) -> sqlx::Result<()> {
+    let mut resolved = vec![];
+    for (hash, tags) in media_tags {
+        let tags: Vec<(String, Option<String>)> = resolve_extracted_tags(tags, pool)
+            .await?
+            .into_iter()
+            .map(|tag| (tag.name, Some(tag._type)))
+            .collect();
+        resolved.push((hash, tags));
     }
+    trace!("Inserting the tags of {} media", resolved.len());
 
-    for tag in &tags {
-        let does_tag_exist: Option<i64> = query_scalar("SELECT 1 FROM Tag WHERE name = ? ")
-            .bind(&tag.name)
-            .fetch_optional(pool)
-            .await
-            .unwrap();
+    let mut tx = pool.begin().await?;
+    for (hash, tags) in &resolved {
+        insert_tag_rows(tags, Some(hash), Some(source), &mut tx).await?;
+    }
 
-        let does_tag_exist = does_tag_exist.is_some();
+    let hashes = resolved.into_iter().map(|(hash, _)| hash).collect_vec();
+    // the hashes are bound twice by the refresh
+    for chunk in hashes.chunks(MAX_BINDS / 2 - 1) {
+        refresh_implied_tags_of(&MediaScope::Hashes(chunk), &mut tx).await?;
+    }
 
-        if !does_tag_exist {
-            query("INSERT INTO Tag(name) VALUES (?)")
-                .bind(&tag.name)
-                .execute(&mut *tx)
-                .await
-                .unwrap();
+    tx.commit().await
+}
+
+/// Inserts the `(name, source_type)` tags with a few multi-row statements instead of a query per tag
+///
+/// Pairs that were only implied take the new source and source type, other existing pairs are kept as they are.
+/// `Tag` and `TagDetail` rows are only created for the tags that don't have them yet. The implied tags of the media
+/// have to be refreshed afterwards.
+async fn insert_tag_rows(
+    tags: &[(String, Option<String>)],
+    media_hash: Option<&str>,
+    source: Option<&str>,
+    conn: &mut SqliteConnection,
+) -> sqlx::Result<()> {
+    let tags = tags.iter().unique_by(|(name, _)| name).collect_vec();
 
-            query("INSERT INTO TagDetail(name) VALUES (?)")
-                .bind(&tag.name)
-                .execute(&mut *tx)
-                .await
-                .unwrap();
+    if let Some(media_hash) = media_hash {
+        // 4 binds per tag and one for the `WHERE`
+        for chunk in tags.chunks((MAX_BINDS - 1) / 4) {
+            let mut query_builder: QueryBuilder<Sqlite> =
+                QueryBuilder::new("INSERT INTO HashTagPair(hash, tag_name, source, source_type) ");
+            query_builder.push_values(chunk, |mut builder, (name, source_type)| {
+                builder
+                    .push_bind(media_hash.to_string())
+                    .push_bind(name.clone())
+                    .push_bind(source.map(str::to_string))
+                    .push_bind(source_type.clone());
+            });
+            // tags that were only implied become regular tags
+            query_builder.push(
+                " ON CONFLICT(hash, tag_name) DO UPDATE SET source = excluded.source, \
+                source_type = excluded.source_type WHERE HashTagPair.source = ",
+            );
+            query_builder.push_bind(IMPLICATION_SOURCE);
+
+            query_builder.build().execute(&mut *conn).await?;
         }
     }
 
-    tx.commit().await.unwrap();
+    for chunk in tags.chunks(MAX_BINDS) {
+        for table in ["Tag", "TagDetail"] {
+            let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+                "INSERT INTO {table}(name) SELECT column1 FROM (VALUES "
+            ));
+            let mut separated = query_builder.separated(", ");
+            for (name, _) in chunk {
+                separated.push("(");
+                separated.push_bind_unseparated(name.clone());
+                separated.push_unseparated(")");
+            }
+            query_builder.push(format!(") WHERE column1 NOT IN (SELECT name FROM {table})"));
+
+            query_builder.build().execute(&mut *conn).await?;
+        }
+    }
+
+    Ok(())
 }
 
 /// Removes tags from the database
 ///
 /// If the media_hash is Some(_) it removes any `HashTagPairs` along with `Tag`s
-/// if it is none it only removes `Tag`s
+/// if it is none it only removes `Tag`s, either way only tags left without media are removed
+///
+/// Removing an alias removes its canonical tag, tags that are still implied by other tags of the media stay.
+/// Tags removed from the media are recorded in the tag history.
 pub async fn remove_tags(tags: Vec<String>, pool: &Pool<Sqlite>, media_hash: Option<String>) {
+    let tags = resolve_aliases(tags, pool).await.unwrap();
+
     let mut tx = pool.begin().await.unwrap();
 
-    if let Some(media_hash) = media_hash {
-        for tag in &tags {
-            query("DELETE FROM HashTagPair WHERE hash = ? AND tag_name = ?")
-                .bind(&media_hash)
-                .bind(tag)
-                .execute(&mut *tx)
-                .await
-                .unwrap();
-        }
+    let removed = remove_tag_rows(&tags, media_hash.as_deref(), &mut tx)
+        .await
+        .unwrap();
+    if let Some(media_hash) = &media_hash {
+        refresh_implied_tags_of(&MediaScope::Hash(media_hash), &mut tx)
+            .await
+            .unwrap();
     }
+    record_tag_changes(&[], &removed, &mut tx).await.unwrap();
+
+    tx.commit().await.unwrap();
+}
+
+/// Removes the tags from the media, then the tags that aren't used anymore, returns the removed pairs
+///
+/// The implied tags of the media have to be refreshed afterwards.
+async fn remove_tag_rows(
+    tags: &[String],
+    media_hash: Option<&str>,
+    conn: &mut SqliteConnection,
+) -> sqlx::Result<Vec<HashTagPair>> {
+    let mut removed = vec![];
 
-    for tag in tags {
-        let tag_reference_count: i64 =
-            query_scalar("SELECT COUNT(*) FROM HashTagPair WHERE tag_name = ?")
-                .bind(&tag)
-                .fetch_one(pool)
-                .await
-                .unwrap();
-
-        // Delete the row if there is only one reference to the tag, `tag_reference_count` query should execute
-        // before this one, so we check if there is one reference left
-        if tag_reference_count == 1 {
-            // check if the we want to remove the tag if there are no references left
-            // the default behavior should remove the tags if no references are left
-            // but the user might want to change that on a per tag basis or using a global config option
-
-            let should_cleanup_tags_per_tag = true;
-
-            /*
-                query("SELECT should_cleanup_tag FROM TagDetails WHERE tag_name = ?")
-                    .bind(tag)
-                    .fetch_optional(pool)
-                    .await
-                    .unwrap(); // TODO Table not implemented
-
-            let should_cleanup_tags_per_tag = match should_cleanup_tags_per_tag {
-                Some(_) => true,
-                None => false,
-            };
-            */
-
-            let should_cleanup_tags_global_config = true; // TODO Global config
-
-            if should_cleanup_tags_global_config || should_cleanup_tags_per_tag {
-                query("DELETE From Tag WHERE name = ?")
-                    .bind(&tag)
-                    .execute(&mut *tx)
-                    .await
-                    .unwrap();
+    if let Some(media_hash) = media_hash {
+        for chunk in tags.chunks(MAX_BINDS - 1) {
+            let mut query_builder: QueryBuilder<Sqlite> =
+                QueryBuilder::new("DELETE FROM HashTagPair WHERE hash = ");
+            query_builder.push_bind(media_hash.to_string());
+            query_builder.push(" AND tag_name IN (");
+            let mut separated = query_builder.separated(", ");
+            for tag in chunk {
+                separated.push_bind(tag.clone());
             }
+            query_builder.push(") RETURNING *");
+
+            removed.extend(
+                query_builder
+                    .build_query_as::<HashTagPair>()
+                    .fetch_all(&mut *conn)
+                    .await?,
+            );
         }
     }
 
-    tx.commit().await.unwrap();
+    // tags that aren't used anymore are removed unless their details say otherwise
+    remove_orphaned_tags(Some(tags), false, conn).await?;
+
+    Ok(removed)
 }
 
 pub fn parse_tags(input: &str) -> Vec<&str> {
@@ -202,8 +275,9 @@ pub fn parse_tags(input: &str) -> Vec<&str> {
 /// Any non existing tags are then added/removed Tags` and `TagsFTS` tables. They should be kept in sync.
 ///  
 ///
+/// Aliases are replaced with their canonical tag, the changes are recorded as one batch in the tag history.
+///
 /// TODOS
-/// - Implicit tags
 /// - Make sure there to deduplicate the HashTagPairs
 pub async fn update_tags_impl(raw_input: &str, hash: String, pool: &Pool<Sqlite>) {
     // TODO RawTagsField removal
@@ -217,18 +291,25 @@ pub async fn update_tags_impl(raw_input: &str, hash: String, pool: &Pool<Sqlite>
     .await
     .unwrap();
 
-    let tags = parse_tags(raw_input);
+    // aliases are resolved before diffing, otherwise typing an alias of an existing tag would remove it
+    let tags = parse_tags(raw_input)
+        .into_iter()
+        .map(|tag| tag.to_string())
+        .collect();
+    let tags = resolve_aliases(tags, pool).await.unwrap();
 
+    // implied tags aren't typed by the user, they are recomputed by `insert_tags` and `remove_tags`
     let previous_tags: Vec<String> =
-        query_scalar("SELECT tag_name FROM HashTagPair WHERE hash = ?")
+        query_scalar("SELECT tag_name FROM HashTagPair WHERE hash = ? AND source IS NOT ?")
             .bind(&hash)
+            .bind(IMPLICATION_SOURCE)
             .fetch_all(pool)
             .await
             .unwrap();
 
     let previous_tags_hs: HashSet<&str> =
         HashSet::from_iter(previous_tags.iter().map(|tag| tag.as_str()));
-    let tags_hs: HashSet<&str> = HashSet::from_iter(tags);
+    let tags_hs: HashSet<&str> = HashSet::from_iter(tags.iter().map(|tag| tag.as_str()));
 
     let to_add: Vec<String> = tags_hs
         .difference(&previous_tags_hs)
@@ -249,17 +330,45 @@ pub async fn update_tags_impl(raw_input: &str, hash: String, pool: &Pool<Sqlite>
     trace!("Removing tags from db: {:?}", to_remove);
     trace!("---------------------");
 
-    insert_tags(to_add, pool, Some(hash.clone()), None).await;
-    remove_tags(to_remove, pool, Some(hash)).await;
-}
+    // the whole edit is a single batch in the tag history
+    let mut tx = pool.begin().await.unwrap();
 
-pub async fn get_tags_as_text_impl(hash: &str, pool: &Pool<Sqlite>) -> String {
-    let tags: Vec<String> = query_scalar("SELECT tag_name FROM HashTagPair WHERE hash = ?")
-        .bind(hash)
-        .fetch_all(pool)
+    let removed = remove_tag_rows(&to_remove, Some(&hash), &mut tx)
         .await
         .unwrap();
 
+    let added: Vec<HashTagPair> = to_add
+        .iter()
+        .map(|tag| HashTagPair {
+            hash: hash.clone(),
+            tag_name: tag.clone(),
+            source: None,
+            source_type: None,
+        })
+        .collect();
+    let to_add: Vec<(String, Option<String>)> = to_add.into_iter().map(|tag| (tag, None)).collect();
+    insert_tag_rows(&to_add, Some(&hash), None, &mut tx)
+        .await
+        .unwrap();
+    refresh_implied_tags_of(&MediaScope::Hash(&hash), &mut tx)
+        .await
+        .unwrap();
+
+    record_tag_changes(&added, &removed, &mut tx).await.unwrap();
+
+    tx.commit().await.unwrap();
+}
+
+/// The tags typed for the media, implied tags are left out as saving the text would turn them into regular tags
+pub async fn get_tags_as_text_impl(hash: &str, pool: &Pool<Sqlite>) -> String {
+    let tags: Vec<String> =
+        query_scalar("SELECT tag_name FROM HashTagPair WHERE hash = ? AND source IS NOT ?")
+            .bind(hash)
+            .bind(IMPLICATION_SOURCE)
+            .fetch_all(pool)
+            .await
+            .unwrap();
+
     tags.iter().join(", ")
 }
 
@@ -269,6 +378,10 @@ pub struct TagWithCount {
     pub count: u32,
     #[sqlx(flatten)]
     pub details: TagDetail,
+    /// Group of the tag, `None` if it has none or it doesn't exist anymore
+    pub resolved_group: Option<String>,
+    /// Color the tag is shown with, see `groups`
+    pub resolved_color: Option<String>,
 }
 
 #[derive(Debug, specta::Type, Deserialize, Serialize)]
@@ -283,9 +396,9 @@ pub async fn get_list_of_all_tags_with_details_impl(
     pool: &Pool<Sqlite>,
     ordering_criteria: AllTagsOrderingCriteria,
 ) -> Vec<TagWithCount> {
-    let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(
-        "SELECT tag_name, COUNT(tag_name) AS count, TagDetail.* FROM HashTagPair, TagDetail WHERE HashTagPair.tag_name = TagDetail.name GROUP BY HashTagPair.tag_name ",
-    );
+    let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+        "SELECT tag_name, COUNT(tag_name) AS count, TagDetail.*, TagGroup.name AS resolved_group, {RESOLVED_COLOR_SQL} AS resolved_color FROM HashTagPair JOIN TagDetail ON HashTagPair.tag_name = TagDetail.name{GROUP_JOIN_SQL}GROUP BY HashTagPair.tag_name ",
+    ));
 
     match ordering_criteria {
         AllTagsOrderingCriteria::Alphabetic => query_builder.push("ORDER BY tag_name ASC"),
@@ -306,3 +419,144 @@ pub async fn get_list_of_all_tags_with_details_impl(
     //    .await
     //    .unwrap()
 }
+
+#[sqlx::test]
+async fn test_insert_tags(pool: Pool<Sqlite>) {
+    use sqlx::{migrate, query_as};
+
+    use crate::{
+        db::schema::HashTagPair, tags::implications::add_implication_impl,
+        test_util::db_utils::insert_image,
+    };
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    insert_image(&pool, "1").await;
+
+    insert_tags(vec!["cat".to_string()], &pool, Some("1".to_string()), None).await;
+    add_implication_impl("cat", "animal", &pool).await.unwrap();
+
+    let extracted = |name: &str, _type: &str| ExtractedTag {
+        _type: _type.to_string(),
+        name: name.to_string(),
+    };
+
+    // duplicates are inserted once, the implied `animal` takes the new source, `cat` keeps its own
+    insert_tags_with_source_types(
+        vec![
+            extracted("cat", "General"),
+            extracted("animal", "General"),
+            extracted("alice", "Character"),
+            extracted("alice", "Character"),
+        ],
+        &pool,
+        Some("1".to_string()),
+        Some(GALLERY_DL_SOURCE.to_string()),
+    )
+    .await;
+
+    let pairs: Vec<HashTagPair> = query_as("SELECT * FROM HashTagPair ORDER BY tag_name")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    let pair = |name: &str, source: Option<&str>, source_type: Option<&str>| HashTagPair {
+        hash: "1".to_string(),
+        tag_name: name.to_string(),
+        source: source.map(str::to_string),
+        source_type: source_type.map(str::to_string),
+    };
+    assert_eq!(
+        pairs,
+        vec![
+            pair("alice", Some(GALLERY_DL_SOURCE), Some("Character")),
+            pair("animal", Some(GALLERY_DL_SOURCE), Some("General")),
+            pair("cat", None, None),
+        ]
+    );
+
+    let tag_names: Vec<String> = query_scalar("SELECT name FROM Tag ORDER BY name")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(tag_names, vec!["alice", "animal", "cat"]);
+
+    let detail_names: Vec<String> = query_scalar("SELECT name FROM TagDetail ORDER BY name")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(detail_names, vec!["alice", "animal", "cat"]);
+}
+
+#[sqlx::test]
+async fn test_tags_text_round_trip(pool: Pool<Sqlite>) {
+    use sqlx::migrate;
+
+    use crate::{tags::implications::add_implication_impl, test_util::db_utils::insert_image};
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    insert_image(&pool, "1").await;
+    add_implication_impl("cat", "animal", &pool).await.unwrap();
+    update_tags_impl("cat, whiskers", "1".to_string(), &pool).await;
+
+    // saving the text unchanged keeps `animal` implied
+    let text = get_tags_as_text_impl("1", &pool).await;
+    assert_eq!(text, "cat, whiskers");
+    update_tags_impl(&text, "1".to_string(), &pool).await;
+
+    update_tags_impl("whiskers", "1".to_string(), &pool).await;
+    let tags: Vec<String> = query_scalar("SELECT tag_name FROM HashTagPair WHERE hash = ?")
+        .bind("1")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(tags, vec!["whiskers"]);
+}
+
+#[sqlx::test]
+async fn test_insert_media_tags(pool: Pool<Sqlite>) {
+    use sqlx::{migrate, query_as};
+
+    use crate::{tags::implications::add_implication_impl, test_util::db_utils::insert_image};
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    for hash in ["1", "2"] {
+        insert_image(&pool, hash).await;
+    }
+    add_implication_impl("cat", "animal", &pool).await.unwrap();
+
+    let extracted = |name: &str| ExtractedTag {
+        _type: "General".to_string(),
+        name: name.to_string(),
+    };
+    insert_media_tags_with_source_types(
+        vec![
+            ("1".to_string(), vec![extracted("cat")]),
+            ("2".to_string(), vec![extracted("cat"), extracted("smile")]),
+        ],
+        AI_TAGGER_SOURCE,
+        &pool,
+    )
+    .await
+    .unwrap();
+
+    let pairs: Vec<(String, String, String)> =
+        query_as("SELECT hash, tag_name, source FROM HashTagPair ORDER BY hash, tag_name")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    let pair = |hash: &str, tag: &str, source: &str| {
+        (hash.to_string(), tag.to_string(), source.to_string())
+    };
+    assert_eq!(
+        pairs,
+        vec![
+            pair("1", "animal", IMPLICATION_SOURCE),
+            pair("1", "cat", AI_TAGGER_SOURCE),
+            pair("2", "animal", IMPLICATION_SOURCE),
+            pair("2", "cat", AI_TAGGER_SOURCE),
+            pair("2", "smile", AI_TAGGER_SOURCE),
+        ]
+    );
+}
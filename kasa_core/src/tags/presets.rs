@@ -1,70 +1,243 @@
-use std::u32;
+//! Named saved searches, stored as the JSON of their `SearchCriteria` in `SearchPreset`
+//!
+//! Wildcards, fuzzy tags and relative times are stored as they were typed, so they are expanded and resolved
+//! again every time the preset is used.
 
-use anyhow::{Ok, Result};
 use serde::{Deserialize, Serialize};
-use sqlx::{Pool, Sqlite, query, query_as, query_scalar};
+use sqlx::{Pool, Sqlite, query, query_as};
+use thiserror::Error;
 
-use crate::db::schema::TagPreset;
+use crate::{
+    db::{impl_from_sqlx_error, schema::TagPreset},
+    tags::{
+        search::{SearchCriteria, SearchParseError},
+        validate_name,
+    },
+};
 
-#[derive(Deserialize, Debug, Serialize, Clone, specta::Type)]
-pub struct TagPresetData {
-    included_tags: Vec<String>,
-    excluded_tags: Vec<String>,
+#[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub enum PresetError {
+    #[error("Preset names can't be empty")]
+    EmptyName,
+    #[error("There is no preset named `{0}`")]
+    NotFound(String),
+    #[error("A preset named `{0}` already exists")]
+    NameTaken(String),
+    #[error("Invalid search: {0}")]
+    InvalidSearch(SearchParseError),
+    #[error("Database error: {0}")]
+    Database(String),
 }
 
+impl_from_sqlx_error!(PresetError);
+
+impl From<SearchParseError> for PresetError {
+    fn from(error: SearchParseError) -> Self {
+        PresetError::InvalidSearch(error)
+    }
+}
+
+/// Saves the search as `name`, replacing the preset with the same name if there is one
 pub async fn new_or_update_preset_impl(
-    includes: Vec<String>,
-    excludes: Vec<String>,
     name: &str,
+    criteria: &SearchCriteria,
     pool: &Pool<Sqlite>,
-) -> Result<()> {
-    let data = TagPresetData {
-        included_tags: includes,
-        excluded_tags: excludes,
-    };
+) -> Result<(), PresetError> {
+    let name = validate_name(name, PresetError::EmptyName)?;
 
-    query("INSERT OR REPLACE INTO SearchPreset(name, preset) VALUES ? ?")
+    query("INSERT INTO SearchPreset(name, preset) VALUES (?, ?) ON CONFLICT(name) DO UPDATE SET preset = excluded.preset")
         .bind(name)
-        .bind(serde_json::to_string(&data).unwrap())
+        .bind(serde_json::to_string(criteria).unwrap())
         .execute(pool)
         .await?;
 
     Ok(())
 }
 
-pub async fn get_preset_impl(name: &str, pool: Pool<Sqlite>) -> Result<TagPresetData> {
-    let preset_json: String = query_scalar("SELECT preset FROM SearchPreset WHERE name = ?")
-        .bind(name)
-        .fetch_one(&pool)
-        .await?;
-
-    let preset: TagPresetData = serde_json::from_str(&preset_json)?;
+pub async fn get_preset_impl(
+    name: &str,
+    pool: &Pool<Sqlite>,
+) -> Result<SearchCriteria, PresetError> {
+    let preset: Option<TagPreset> =
+        query_as("SELECT name, preset FROM SearchPreset WHERE name = ?")
+            .bind(name)
+            .fetch_optional(pool)
+            .await?;
 
-    Ok(preset)
+    match preset {
+        Some(preset) => Ok(preset.preset.0),
+        None => Err(PresetError::NotFound(name.to_string())),
+    }
 }
 
-#[derive(Deserialize, Debug, Serialize, Clone, specta::Type)]
+#[derive(Deserialize, Debug, Serialize, Clone, PartialEq, specta::Type)]
 pub struct PresetListEntry {
     pub name: String,
-    include_count: u32,
-    exclude_count: u32,
+    /// Number of media the preset currently matches
+    pub media_count: i64,
 }
 
-pub async fn get_preset_list_impl(pool: &Pool<Sqlite>) -> Result<Vec<PresetListEntry>> {
-    let entries: Vec<TagPreset> = query_as("SELECT name, preset FROM SearchPreset")
-        .fetch_all(pool)
-        .await?;
+/// Every preset ordered by name
+pub async fn get_preset_list_impl(
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<PresetListEntry>, PresetError> {
+    let presets: Vec<TagPreset> =
+        query_as("SELECT name, preset FROM SearchPreset ORDER BY name COLLATE NOCASE")
+            .fetch_all(pool)
+            .await?;
 
     let mut result = vec![];
 
-    let preset_list: Vec<PresetListEntry> = entries
-        .iter()
-        .map(|e| PresetListEntry {
-            name: e.name.clone(),
-            include_count: e.preset.included_tags.len() as u32,
-            exclude_count: e.preset.excluded_tags.len() as u32,
-        })
-        .collect();
+    for preset in presets {
+        let mut criteria = preset.preset.0;
+        criteria.expand_tags(pool).await?;
+
+        let media_count: i64 = criteria
+            .to_count_query()
+            .build_query_scalar()
+            .fetch_one(pool)
+            .await?;
+
+        result.push(PresetListEntry {
+            name: preset.name,
+            media_count,
+        });
+    }
 
     Ok(result)
 }
+
+pub async fn rename_preset_impl(
+    old_name: &str,
+    new_name: &str,
+    pool: &Pool<Sqlite>,
+) -> Result<(), PresetError> {
+    let new_name = validate_name(new_name, PresetError::EmptyName)?;
+
+    if old_name == new_name {
+        // still fails if the preset doesn't exist
+        get_preset_impl(old_name, pool).await?;
+        return Ok(());
+    }
+
+    let result = query("UPDATE SearchPreset SET name = ? WHERE name = ?")
+        .bind(new_name)
+        .bind(old_name)
+        .execute(pool)
+        .await;
+
+    match result {
+        Ok(result) if result.rows_affected() == 0 => {
+            Err(PresetError::NotFound(old_name.to_string()))
+        }
+        Ok(_) => Ok(()),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            Err(PresetError::NameTaken(new_name.to_string()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn delete_preset_impl(name: &str, pool: &Pool<Sqlite>) -> Result<(), PresetError> {
+    let result = query("DELETE FROM SearchPreset WHERE name = ?")
+        .bind(name)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(PresetError::NotFound(name.to_string()));
+    }
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_preset_round_trip(pool: Pool<Sqlite>) {
+    use sqlx::migrate;
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    // tag picker lists come from the frontend
+    let picker: SearchCriteria = serde_json::from_value(serde_json::json!({
+        "contains_tags": ["cat"],
+        "contains_tags_or_group": [["red", "blue"]],
+        "excludes_tags": ["dog"],
+        "order_by": "NewestFirst",
+        "date_range": { "start": 0, "end": 1000 },
+    }))
+    .unwrap();
+
+    let mut criteria =
+        SearchCriteria::parse_from_str("artist:*, imported last 3 days, order by size").unwrap();
+    criteria.merge(&picker);
+
+    new_or_update_preset_impl("cats", &criteria, &pool)
+        .await
+        .unwrap();
+    assert_eq!(get_preset_impl("cats", &pool).await.unwrap(), criteria);
+
+    // saving again replaces it
+    let other = SearchCriteria::parse_from_str("dog").unwrap();
+    new_or_update_preset_impl("cats", &other, &pool)
+        .await
+        .unwrap();
+    assert_eq!(get_preset_impl("cats", &pool).await.unwrap(), other);
+
+    assert_eq!(
+        get_preset_impl("nothing", &pool).await,
+        Err(PresetError::NotFound("nothing".to_string()))
+    );
+    assert_eq!(
+        new_or_update_preset_impl(" ", &other, &pool).await,
+        Err(PresetError::EmptyName)
+    );
+}
+
+#[sqlx::test]
+async fn test_preset_rename_delete_and_list(pool: Pool<Sqlite>) {
+    use crate::{tags::insert_tags, test_util::db_utils::insert_image};
+    use sqlx::migrate;
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    for (hash, tag) in [("1", "cat"), ("2", "cat"), ("3", "dog")] {
+        insert_image(&pool, hash).await;
+        insert_tags(vec![tag.to_string()], &pool, Some(hash.to_string()), None).await;
+    }
+
+    for (name, input) in [("cats", "cat"), ("dogs", "dog"), ("animals", "c* or d*")] {
+        let criteria = SearchCriteria::parse_from_str(input).unwrap();
+        new_or_update_preset_impl(name, &criteria, &pool)
+            .await
+            .unwrap();
+    }
+
+    let entry = |name: &str, media_count| PresetListEntry {
+        name: name.to_string(),
+        media_count,
+    };
+
+    assert_eq!(
+        get_preset_list_impl(&pool).await.unwrap(),
+        vec![entry("animals", 3), entry("cats", 2), entry("dogs", 1)]
+    );
+
+    assert_eq!(
+        rename_preset_impl("cats", "dogs", &pool).await,
+        Err(PresetError::NameTaken("dogs".to_string()))
+    );
+    assert_eq!(
+        rename_preset_impl("birds", "parrots", &pool).await,
+        Err(PresetError::NotFound("birds".to_string()))
+    );
+    rename_preset_impl("cats", "kittens", &pool).await.unwrap();
+
+    delete_preset_impl("dogs", &pool).await.unwrap();
+    assert_eq!(
+        delete_preset_impl("dogs", &pool).await,
+        Err(PresetError::NotFound("dogs".to_string()))
+    );
+
+    assert_eq!(
+        get_preset_list_impl(&pool).await.unwrap(),
+        vec![entry("animals", 3), entry("kittens", 2)]
+    );
+}
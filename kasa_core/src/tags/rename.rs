@@ -0,0 +1,406 @@
+//! Renaming and merging tags
+//!
+//! Every reference to the tag is rewritten in one transaction: `HashTagPair`, `Tag`, `TagDetail`,
+//! `RawTagsField`, aliases, implications, the tag history and the tags saved in search presets. Media that already has the
+//! target tag keeps its own `HashTagPair`, unless it was only implied.
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite, SqliteConnection, query, query_as, query_scalar};
+use thiserror::Error;
+
+use crate::db::{impl_from_sqlx_error, schema::TagPreset};
+
+use super::{
+    IMPLICATION_SOURCE,
+    aliases::resolve_alias,
+    implications::{find_implication_cycle, refresh_implied_tags_around},
+    parse_tags, validate_name,
+};
+
+#[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub enum RenameError {
+    #[error("Tag names can't be empty")]
+    EmptyName,
+    #[error("There is no tag named `{0}`")]
+    NotFound(String),
+    #[error("A tag named `{0}` already exists, merge the tags instead")]
+    TargetExists(String),
+    #[error("`{0}` can't be merged into itself")]
+    SameTag(String),
+    #[error("Merging would create the implication cycle {}", .0.join(" -> "))]
+    ImplicationCycle(Vec<String>),
+    #[error("Database error: {0}")]
+    Database(String),
+}
+
+impl_from_sqlx_error!(RenameError);
+
+async fn tag_exists(tag: &str, conn: &mut SqliteConnection) -> sqlx::Result<bool> {
+    let exists: Option<i64> = query_scalar(
+        "SELECT 1 FROM Tag WHERE name = ? UNION SELECT 1 FROM HashTagPair WHERE tag_name = ?",
+    )
+    .bind(tag)
+    .bind(tag)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(exists.is_some())
+}
+
+/// Renames `old` to `new`, fails if `new` already exists
+pub async fn rename_tag_impl(old: &str, new: &str, pool: &Pool<Sqlite>) -> Result<(), RenameError> {
+    let new = validate_name(new, RenameError::EmptyName)?;
+    let old = resolve_alias(old, pool).await?;
+    let new = resolve_alias(new, pool).await?;
+
+    if old == new {
+        return Ok(());
+    }
+
+    let mut tx = pool.begin().await?;
+
+    if !tag_exists(&old, &mut tx).await? {
+        return Err(RenameError::NotFound(old));
+    }
+    if tag_exists(&new, &mut tx).await? {
+        return Err(RenameError::TargetExists(new));
+    }
+
+    rewrite_tag(&old, &new, &mut tx).await?;
+    refresh_implied_tags_around(&new, &mut tx).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Merges every tag of `sources` into `target`, which is created if it doesn't exist
+///
+/// `target` keeps its own details, group and color if it has them. Nothing is merged if the implications of the
+/// tags would form a cycle afterwards.
+pub async fn merge_tags_impl(
+    sources: Vec<String>,
+    target: &str,
+    pool: &Pool<Sqlite>,
+) -> Result<(), RenameError> {
+    let target = resolve_alias(validate_name(target, RenameError::EmptyName)?, pool).await?;
+
+    let mut resolved_sources = vec![];
+    for source in sources {
+        let source = resolve_alias(&source, pool).await?;
+        if source == target {
+            return Err(RenameError::SameTag(source));
+        }
+        resolved_sources.push(source);
+    }
+
+    let mut tx = pool.begin().await?;
+
+    for source in resolved_sources.iter().unique() {
+        if !tag_exists(source, &mut tx).await? {
+            return Err(RenameError::NotFound(source.clone()));
+        }
+
+        rewrite_tag(source, &target, &mut tx).await?;
+    }
+
+    if let Some(cycle) = find_implication_cycle(&mut tx).await? {
+        return Err(RenameError::ImplicationCycle(cycle));
+    }
+
+    refresh_implied_tags_around(&target, &mut tx).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Replaces every reference to `old` with `new`, merging them if `new` already exists
+pub(super) async fn rewrite_tag(
+    old: &str,
+    new: &str,
+    conn: &mut SqliteConnection,
+) -> sqlx::Result<()> {
+    // media with both keeps the pair of `new`, with the source of `old` if `new` was only implied
+    query(
+        "UPDATE HashTagPair SET source = o.source, source_type = o.source_type \
+        FROM (SELECT hash, source, source_type FROM HashTagPair WHERE tag_name = ?) o \
+        WHERE HashTagPair.hash = o.hash AND HashTagPair.tag_name = ? AND HashTagPair.source = ?",
+    )
+    .bind(old)
+    .bind(new)
+    .bind(IMPLICATION_SOURCE)
+    .execute(&mut *conn)
+    .await?;
+
+    query("UPDATE OR IGNORE HashTagPair SET tag_name = ? WHERE tag_name = ?")
+        .bind(new)
+        .bind(old)
+        .execute(&mut *conn)
+        .await?;
+
+    query("DELETE FROM HashTagPair WHERE tag_name = ?")
+        .bind(old)
+        .execute(&mut *conn)
+        .await?;
+
+    // reverting a batch recorded before the rename shouldn't bring `old` back
+    query("UPDATE TagHistory SET tag_name = ? WHERE tag_name = ?")
+        .bind(new)
+        .bind(old)
+        .execute(&mut *conn)
+        .await?;
+
+    let does_new_exist: Option<i64> = query_scalar("SELECT 1 FROM Tag WHERE name = ?")
+        .bind(new)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    query("DELETE FROM Tag WHERE name = ?")
+        .bind(old)
+        .execute(&mut *conn)
+        .await?;
+
+    if does_new_exist.is_none() {
+        query("INSERT INTO Tag(name) VALUES (?)")
+            .bind(new)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    let does_new_have_details: Option<i64> = query_scalar("SELECT 1 FROM TagDetail WHERE name = ?")
+        .bind(new)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    if does_new_have_details.is_some() {
+        query("DELETE FROM TagDetail WHERE name = ?")
+            .bind(old)
+            .execute(&mut *conn)
+            .await?;
+    } else {
+        query("UPDATE TagDetail SET name = ? WHERE name = ?")
+            .bind(new)
+            .bind(old)
+            .execute(&mut *conn)
+            .await?;
+
+        query("INSERT INTO TagDetail(name) SELECT ? WHERE NOT EXISTS (SELECT 1 FROM TagDetail WHERE name = ?)")
+            .bind(new)
+            .bind(new)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    query("UPDATE TagAlias SET canonical = ? WHERE canonical = ?")
+        .bind(new)
+        .bind(old)
+        .execute(&mut *conn)
+        .await?;
+
+    query("UPDATE OR IGNORE TagImplication SET tag = ? WHERE tag = ?")
+        .bind(new)
+        .bind(old)
+        .execute(&mut *conn)
+        .await?;
+
+    query("UPDATE OR IGNORE TagImplication SET implied = ? WHERE implied = ?")
+        .bind(new)
+        .bind(old)
+        .execute(&mut *conn)
+        .await?;
+
+    query("DELETE FROM TagImplication WHERE tag = ? OR implied = ? OR tag = implied")
+        .bind(old)
+        .bind(old)
+        .execute(&mut *conn)
+        .await?;
+
+    rewrite_raw_tags_fields(old, new, conn).await?;
+    rewrite_presets(old, new, conn).await?;
+
+    Ok(())
+}
+
+async fn rewrite_raw_tags_fields(
+    old: &str,
+    new: &str,
+    conn: &mut SqliteConnection,
+) -> sqlx::Result<()> {
+    // `LIKE` only narrows the rows down, `_` in tag names matches any character
+    let fields: Vec<(String, String)> =
+        query_as("SELECT hash, _text FROM RawTagsField WHERE _text LIKE ?")
+            .bind(format!("%{old}%"))
+            .fetch_all(&mut *conn)
+            .await?;
+
+    for (hash, text) in fields {
+        let tags = parse_tags(&text);
+        if !tags.contains(&old) {
+            continue;
+        }
+
+        let text = tags
+            .into_iter()
+            .map(|tag| if tag == old { new } else { tag })
+            .unique()
+            .join(", ");
+
+        query("UPDATE RawTagsField SET _text = ? WHERE hash = ?")
+            .bind(text)
+            .bind(hash)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn rewrite_presets(old: &str, new: &str, conn: &mut SqliteConnection) -> sqlx::Result<()> {
+    let presets: Vec<TagPreset> = query_as("SELECT name, preset FROM SearchPreset")
+        .fetch_all(&mut *conn)
+        .await?;
+
+    for mut preset in presets {
+        if !preset.preset.0.rename_tag(old, new) {
+            continue;
+        }
+
+        query("UPDATE SearchPreset SET preset = ? WHERE name = ?")
+            .bind(serde_json::to_string(&preset.preset.0).unwrap())
+            .bind(preset.name)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn test_rename_and_merge(pool: Pool<Sqlite>) {
+    use sqlx::migrate;
+
+    use super::{
+        implications::add_implication_impl,
+        insert_tags,
+        presets::{get_preset_impl, new_or_update_preset_impl},
+        search::SearchCriteria,
+        update_tags_impl,
+    };
+    use crate::test_util::db_utils::insert_image;
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    for hash in ["1", "2"] {
+        insert_image(&pool, hash).await;
+    }
+
+    update_tags_impl("blue_hair, bleu_hair, smile", "1".to_string(), &pool).await;
+    update_tags_impl("bleu_hair", "2".to_string(), &pool).await;
+    insert_tags(vec!["hair".to_string()], &pool, None, None).await;
+    add_implication_impl("bleu_hair", "hair", &pool)
+        .await
+        .unwrap();
+
+    let criteria = SearchCriteria::parse_from_str("bleu_hair or smile, -bleu*").unwrap();
+    new_or_update_preset_impl("typo", &criteria, &pool)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        rename_tag_impl("bleu_hair", "smile", &pool).await,
+        Err(RenameError::TargetExists("smile".to_string()))
+    );
+    assert_eq!(
+        rename_tag_impl("red_hair", "crimson_hair", &pool).await,
+        Err(RenameError::NotFound("red_hair".to_string()))
+    );
+
+    rename_tag_impl("smile", "smiling", &pool).await.unwrap();
+
+    // media 1 has both, it keeps a single `blue_hair`
+    merge_tags_impl(vec!["bleu_hair".to_string()], "blue_hair", &pool)
+        .await
+        .unwrap();
+
+    let media_tags = async |hash: &str| -> Vec<String> {
+        query_scalar("SELECT tag_name FROM HashTagPair WHERE hash = ? ORDER BY tag_name")
+            .bind(hash)
+            .fetch_all(&pool)
+            .await
+            .unwrap()
+    };
+    assert_eq!(media_tags("1").await, vec!["blue_hair", "hair", "smiling"]);
+    assert_eq!(media_tags("2").await, vec!["blue_hair", "hair"]);
+
+    let raw_text: String = query_scalar("SELECT _text FROM RawTagsField WHERE hash = '1'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(raw_text, "blue_hair, smiling");
+
+    let tag_names: Vec<String> = query_scalar("SELECT name FROM Tag ORDER BY name")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(tag_names, vec!["blue_hair", "hair", "smiling"]);
+
+    let detail_names: Vec<String> = query_scalar("SELECT name FROM TagDetail ORDER BY name")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(detail_names, vec!["blue_hair", "hair", "smiling"]);
+
+    let history_names: Vec<String> =
+        query_scalar("SELECT DISTINCT tag_name FROM TagHistory ORDER BY tag_name")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(history_names, vec!["blue_hair", "smiling"]);
+
+    let implications: Vec<(String, String)> = query_as("SELECT tag, implied FROM TagImplication")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        implications,
+        vec![("blue_hair".to_string(), "hair".to_string())]
+    );
+
+    // patterns are kept as typed
+    assert_eq!(
+        get_preset_impl("typo", &pool).await.unwrap(),
+        SearchCriteria::parse_from_str("blue_hair or smiling, -bleu*").unwrap()
+    );
+
+    assert_eq!(
+        merge_tags_impl(vec!["blue_hair".to_string()], "blue_hair", &pool).await,
+        Err(RenameError::SameTag("blue_hair".to_string()))
+    );
+
+    // `blue_hair -> hair -> long_hair` would become `blue_hair -> hair -> blue_hair`, nothing is merged
+    add_implication_impl("hair", "long_hair", &pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        merge_tags_impl(vec!["long_hair".to_string()], "blue_hair", &pool).await,
+        Err(RenameError::ImplicationCycle(vec![
+            "blue_hair".to_string(),
+            "hair".to_string(),
+            "blue_hair".to_string()
+        ]))
+    );
+    let implications: Vec<(String, String)> =
+        query_as("SELECT tag, implied FROM TagImplication ORDER BY tag")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        implications,
+        vec![
+            ("blue_hair".to_string(), "hair".to_string()),
+            ("hair".to_string(), "long_hair".to_string())
+        ]
+    );
+}
@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::db::impl_from_sqlx_error;
+
+/// Byte offsets of the offending part of the search input, `end` is exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, specta::Type)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+#[error("{kind} at {}..{}", .span.start, .span.end)]
+pub struct SearchParseError {
+    pub kind: SearchParseErrorKind,
+    pub span: Span,
+}
+
+/// Error of running a search typed by the user
+#[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub enum SearchError {
+    #[error("Invalid search: {0}")]
+    InvalidSearch(SearchParseError),
+    #[error("Database error: {0}")]
+    Database(String),
+}
+
+impl_from_sqlx_error!(SearchError);
+
+impl From<SearchParseError> for SearchError {
+    fn from(error: SearchParseError) -> Self {
+        SearchError::InvalidSearch(error)
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub enum SearchParseErrorKind {
+    #[error("Expected a tag or an expression")]
+    ExpectedExpression,
+    #[error("Parenthesis is never closed")]
+    UnclosedParenthesis,
+    #[error("Closing parenthesis doesn't have a matching opening one")]
+    UnmatchedClosingParenthesis,
+    #[error("Quote is never closed")]
+    UnclosedQuote,
+    #[error("Unexpected input, expected ',' or the end of the search")]
+    UnexpectedInput,
+    #[error("Expected a time like `today`, `3 days ago` or `2024-05-13`")]
+    ExpectedTime,
+    #[error("Invalid time: {0}")]
+    InvalidTime(String),
+    #[error("Invalid value `{value}` for `{field}`")]
+    InvalidPredicateValue { field: String, value: String },
+    #[error("`{0}` can only be compared with `:`, `=` or `!=`")]
+    InvalidComparison(String),
+    #[error("Invalid order criteria: {0}")]
+    InvalidOrderCriteria(String),
+}
@@ -0,0 +1,208 @@
+//! Expands wildcard and fuzzy tags into the tags they match before the media query runs
+//!
+//! Candidates are looked up from the `Tag` FTS5 table using the literal words of the pattern, then
+//! filtered exactly, the FTS tokenizer splits tags on non alphanumeric characters so `blue_hair` is
+//! indexed as `blue` and `hair`.
+
+use itertools::Itertools;
+use sqlx::{Pool, QueryBuilder, Result, Sqlite, query_as};
+
+use super::SearchExpr;
+
+/// Patterns matching more tags than this aren't expanded and use a `GLOB` over `HashTagPair` instead
+const MAX_EXPANDED_TAGS: usize = 1000;
+
+/// Converts a pattern where `*` is the only wildcard to a lowercase SQLite `GLOB` pattern
+pub(super) fn to_glob(pattern: &str) -> String {
+    let mut glob = String::with_capacity(pattern.len());
+
+    for c in pattern.to_lowercase().chars() {
+        match c {
+            '?' => glob.push_str("[?]"),
+            '[' => glob.push_str("[[]"),
+            c => glob.push(c),
+        }
+    }
+
+    glob
+}
+
+/// FTS5 terms for the words of the pattern, words directly after a `*` can't be used as they might be
+/// the end of a longer word, words directly before a `*` are prefix queries
+fn fts_terms(pattern: &str) -> Vec<String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut terms = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        if !chars[i].is_alphanumeric() {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < chars.len() && chars[i].is_alphanumeric() {
+            i += 1;
+        }
+
+        if start > 0 && chars[start - 1] == '*' {
+            continue;
+        }
+
+        let word: String = chars[start..i].iter().collect();
+
+        if chars.get(i) == Some(&'*') {
+            terms.push(format!("\"{word}\"*"));
+        } else {
+            terms.push(format!("\"{word}\""));
+        }
+    }
+
+    terms
+}
+
+/// Tags matching the pattern and canonical tags of aliases matching it, `None` if there are too many of them
+async fn expand_pattern(pattern: &str, pool: &Pool<Sqlite>) -> Result<Option<Vec<String>>> {
+    let terms = fts_terms(pattern);
+
+    let mut query_builder: QueryBuilder<Sqlite> =
+        QueryBuilder::new("SELECT name FROM Tag WHERE lower(name) GLOB ");
+    query_builder.push_bind(to_glob(pattern));
+
+    if !terms.is_empty() {
+        query_builder.push(" AND Tag MATCH ");
+        query_builder.push_bind(terms.join(" AND "));
+    }
+
+    query_builder.push(" UNION SELECT canonical FROM TagAlias WHERE lower(alias) GLOB ");
+    query_builder.push_bind(to_glob(pattern));
+
+    query_builder.push(" LIMIT ");
+    query_builder.push_bind((MAX_EXPANDED_TAGS + 1) as i64);
+
+    let tags: Vec<String> = query_builder.build_query_scalar().fetch_all(pool).await?;
+
+    if tags.len() > MAX_EXPANDED_TAGS {
+        return Ok(None);
+    }
+
+    Ok(Some(tags))
+}
+
+fn max_typos(name: &str) -> usize {
+    (name.chars().count() / 4).clamp(1, 3)
+}
+
+/// Tags that are a few typos away from `name`, or have an alias that is
+///
+/// Candidates are the tags with a word starting with the first two characters of any word of `name`,
+/// so typos in the first two characters of every word can't be found.
+async fn expand_fuzzy(name: &str, pool: &Pool<Sqlite>) -> Result<Vec<String>> {
+    let terms: Vec<String> = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"*", word.chars().take(2).collect::<String>()))
+        .collect();
+
+    let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT name FROM Tag");
+
+    if !terms.is_empty() {
+        query_builder.push(" WHERE Tag MATCH ");
+        query_builder.push_bind(terms.join(" OR "));
+    }
+
+    let mut candidates: Vec<(String, String)> = query_builder
+        .build_query_scalar()
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|name: String| (name.clone(), name))
+        .collect();
+
+    // aliases aren't in `Tag`, there should be few enough of them to check all
+    let aliases: Vec<(String, String)> = query_as("SELECT alias, canonical FROM TagAlias")
+        .fetch_all(pool)
+        .await?;
+    candidates.extend(aliases);
+
+    let name = name.to_lowercase();
+    let max_typos = max_typos(&name);
+
+    Ok(candidates
+        .into_iter()
+        .filter(|(candidate, _)| levenshtein(&candidate.to_lowercase(), &name) <= max_typos)
+        .map(|(_, canonical)| canonical)
+        .unique()
+        .collect())
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+fn collect_expandable<'a>(expr: &'a mut SearchExpr, output: &mut Vec<&'a mut SearchExpr>) {
+    match expr {
+        SearchExpr::TagPattern(_) | SearchExpr::FuzzyTag(_) => output.push(expr),
+        SearchExpr::And(operands) | SearchExpr::Or(operands) => {
+            for operand in operands {
+                collect_expandable(operand, output);
+            }
+        }
+        SearchExpr::Not(inner) => collect_expandable(inner, output),
+        SearchExpr::Tag(_) | SearchExpr::AnyTag(_) | SearchExpr::Predicate(_) => {}
+    }
+}
+
+/// Replaces every `TagPattern` and `FuzzyTag` in the expression with the tags they match
+pub(super) async fn expand_expr(expr: &mut SearchExpr, pool: &Pool<Sqlite>) -> Result<()> {
+    let mut expandable = vec![];
+    collect_expandable(expr, &mut expandable);
+
+    for expr in expandable {
+        match expr {
+            SearchExpr::TagPattern(pattern) => {
+                if let Some(tags) = expand_pattern(pattern, pool).await? {
+                    *expr = SearchExpr::AnyTag(tags);
+                }
+            }
+            SearchExpr::FuzzyTag(name) => {
+                let tags = expand_fuzzy(name, pool).await?;
+                *expr = SearchExpr::AnyTag(tags);
+            }
+            _ => unreachable!("only patterns are collected"),
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_fts_terms() {
+    assert_eq!(fts_terms("artist:*"), vec![r#""artist""#]);
+    assert_eq!(fts_terms("*_hair"), vec![r#""hair""#]);
+    assert_eq!(fts_terms("blue*"), vec![r#""blue"*"#]);
+    assert!(fts_terms("*hair").is_empty());
+}
+
+#[test]
+fn test_levenshtein() {
+    assert_eq!(levenshtein("blue_hair", "blue_hair"), 0);
+    assert_eq!(levenshtein("blue_hiar", "blue_hair"), 2);
+    assert_eq!(levenshtein("blue_har", "blue_hair"), 1);
+    assert_eq!(levenshtein("", "abc"), 3);
+}
@@ -0,0 +1,792 @@
+mod errors;
+mod expand;
+mod order;
+mod page;
+mod parser;
+mod predicates;
+mod time;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, QueryBuilder, Sqlite, query_as};
+
+#[allow(unused)]
+use crate::{
+    db::schema::Media,
+    test_util::db_utils::{_insert_media_row, insert_hash_tag_pair_row},
+};
+
+pub use errors::{SearchError, SearchParseError, SearchParseErrorKind, Span};
+pub use order::{OrderCriteria, SortDirection};
+pub use page::{SearchCursor, SearchPage};
+use parser::Clause;
+pub use predicates::{Comparison, NumericField, Orientation, Predicate};
+pub use time::{CalendarUnit, TimePoint, TimeRange, TimeUnit};
+
+use super::parse_tags;
+
+// Tags separated by commas
+// `tag1, tag2`
+//
+// OR queries
+// `tag1 or tag2, tag3, tag4`
+//
+// NOT queries,
+// `tag1, tag2, not tag3` or `tag1, tag2, -tag3`
+//
+// Wildcards and fuzzy matching
+// `artist:*, *_hair, ~blue_hiar`
+//
+// Tag aliases match their canonical tag, `blue hair` finds media tagged `blue_hair` if it is an alias of it
+//
+// Tags match the tags implying them, `animal_ears` finds media tagged `cat_ears` if it implies `animal_ears`
+//
+// Grouping and quoting
+// `(tag1 and tag2) or not (tag3 | "tag, with comma")`
+//
+// ORDERING
+// `tag1, tag2, order by date reverse`
+// `tag1, order by size`, `tag1, order by name desc`, `tag1, order by random(42)`
+//
+// TIME queries
+// `tag1, imported between 3 days ago and now`
+// `tag1, imported yesterday`
+// `tag1, added after 2024-05-01`
+// `tag1, modified last week` for the modification time of the files
+//
+// METADATA queries
+// `width>=1920, ratio:landscape`
+// `size<2MB, type:video, mime:image/webp`
+// `camera:x100v, lens:35mm, taken:2023`
+//
+// See `parser.rs` for the grammar
+
+/// Unix timestamps in milliseconds, compared against `Media.time_added`
+#[derive(Debug, PartialEq, Default, Clone, specta::Type, Serialize, Deserialize)]
+pub struct DateRange {
+    start: u64,
+    end: u64,
+}
+
+/// Boolean expression tree of a search query
+#[derive(Debug, PartialEq, Clone, specta::Type, Serialize, Deserialize)]
+pub enum SearchExpr {
+    Tag(String),
+    /// Tag with `*` wildcards, expanded to `AnyTag` by `SearchCriteria::expand_tags`
+    TagPattern(String),
+    /// Tag that might have typos, expanded to `AnyTag` by `SearchCriteria::expand_tags`
+    FuzzyTag(String),
+    /// Media has at least one of the tags
+    AnyTag(Vec<String>),
+    And(Vec<SearchExpr>),
+    Or(Vec<SearchExpr>),
+    Not(Box<SearchExpr>),
+    Predicate(Predicate),
+}
+
+impl SearchExpr {
+    /// `And` of the operands, without wrapping single operands
+    fn and(mut operands: Vec<SearchExpr>) -> Self {
+        if operands.len() == 1 {
+            operands.pop().unwrap()
+        } else {
+            SearchExpr::And(operands)
+        }
+    }
+
+    /// `Or` of the operands, without wrapping single operands
+    fn or(mut operands: Vec<SearchExpr>) -> Self {
+        if operands.len() == 1 {
+            operands.pop().unwrap()
+        } else {
+            SearchExpr::Or(operands)
+        }
+    }
+
+    /// Replaces the tag `old` with `new`, returns if anything changed
+    fn rename_tag(&mut self, old: &str, new: &str) -> bool {
+        match self {
+            SearchExpr::Tag(tag) => rename_in(std::slice::from_mut(tag), old, new),
+            SearchExpr::AnyTag(tags) => rename_in(tags, old, new),
+            SearchExpr::And(operands) | SearchExpr::Or(operands) => {
+                let mut changed = false;
+                for operand in operands {
+                    changed |= operand.rename_tag(old, new);
+                }
+                changed
+            }
+            SearchExpr::Not(inner) => inner.rename_tag(old, new),
+            SearchExpr::TagPattern(_) | SearchExpr::FuzzyTag(_) | SearchExpr::Predicate(_) => false,
+        }
+    }
+
+    /// Pushes the expression as a condition on `Media m`
+    fn push_sql(&self, query_builder: &mut QueryBuilder<Sqlite>, now: DateTime<Local>) {
+        match self {
+            // aliases are never stored in `HashTagPair`, so they are searched as their canonical tag
+            SearchExpr::Tag(tag) => {
+                push_implying_tags_sql(query_builder, |query_builder| {
+                    query_builder.push(
+                        "SELECT COALESCE((SELECT ta.canonical FROM TagAlias ta WHERE ta.alias = ",
+                    );
+                    query_builder.push_bind(tag.clone());
+                    query_builder.push("), ");
+                    query_builder.push_bind(tag.clone());
+                    query_builder.push(")");
+                });
+            }
+            // only used when the pattern matches too many tags to expand
+            SearchExpr::TagPattern(pattern) => {
+                query_builder.push(
+                    "m.hash IN (SELECT htp.hash FROM HashTagPair htp WHERE lower(htp.tag_name) GLOB ",
+                );
+                query_builder.push_bind(expand::to_glob(pattern));
+                query_builder.push(")");
+            }
+            // not expanded, only matches the exact tag
+            SearchExpr::FuzzyTag(tag) => SearchExpr::Tag(tag.clone()).push_sql(query_builder, now),
+            SearchExpr::AnyTag(tags) if tags.is_empty() => {
+                query_builder.push("1 = 0");
+            }
+            SearchExpr::AnyTag(tags) => {
+                push_implying_tags_sql(query_builder, |query_builder| {
+                    query_builder.push("SELECT column1 FROM (VALUES ");
+                    let mut separated = query_builder.separated(", ");
+                    for tag in tags {
+                        separated.push("(");
+                        separated.push_bind_unseparated(tag.clone());
+                        separated.push_unseparated(")");
+                    }
+                    query_builder.push(")");
+                });
+            }
+            SearchExpr::And(operands) => {
+                push_joined(query_builder, operands, " AND ", "1 = 1", now)
+            }
+            SearchExpr::Or(operands) => push_joined(query_builder, operands, " OR ", "1 = 0", now),
+            SearchExpr::Not(inner) => {
+                query_builder.push("NOT (");
+                inner.push_sql(query_builder, now);
+                query_builder.push(")");
+            }
+            SearchExpr::Predicate(predicate) => predicate.push_sql(query_builder, now),
+        }
+    }
+}
+
+/// Replaces every `old` in `tags` with `new`, returns whether any tag was replaced
+fn rename_in(tags: &mut [String], old: &str, new: &str) -> bool {
+    let mut changed = false;
+
+    for tag in tags.iter_mut().filter(|tag| *tag == old) {
+        *tag = new.to_string();
+        changed = true;
+    }
+
+    changed
+}
+
+/// Pushes a condition matching media tagged with one of the tags selected by `push_tags`, or with a tag that
+/// implies one of them, so implied tags are found even if they aren't stored in `HashTagPair`
+fn push_implying_tags_sql(
+    query_builder: &mut QueryBuilder<Sqlite>,
+    push_tags: impl FnOnce(&mut QueryBuilder<Sqlite>),
+) {
+    query_builder.push(
+        "m.hash IN (SELECT htp.hash FROM HashTagPair htp WHERE htp.tag_name IN \
+        (WITH RECURSIVE implying(name) AS (",
+    );
+    push_tags(query_builder);
+    query_builder.push(
+        " UNION SELECT ti.tag FROM TagImplication ti JOIN implying i ON ti.implied = i.name) \
+        SELECT name FROM implying))",
+    );
+}
+
+/// Pushes `(op1 <separator> op2 ...)`, or `empty` if there are no operands
+fn push_joined(
+    query_builder: &mut QueryBuilder<Sqlite>,
+    operands: &[SearchExpr],
+    separator: &str,
+    empty: &str,
+    now: DateTime<Local>,
+) {
+    if operands.is_empty() {
+        query_builder.push(empty);
+        return;
+    }
+
+    query_builder.push("(");
+    for (i, operand) in operands.iter().enumerate() {
+        if i != 0 {
+            query_builder.push(separator);
+        }
+        operand.push_sql(query_builder, now);
+    }
+    query_builder.push(")");
+}
+
+#[derive(Debug, PartialEq, Default, Clone, specta::Type, Serialize, Deserialize)]
+pub struct SearchCriteria {
+    contains_tags: Vec<String>,
+    contains_tags_or_group: Vec<Vec<String>>,
+    excludes_tags: Vec<String>,
+    order_by: OrderCriteria,
+    date_range: Option<DateRange>,
+    /// Parsed search bar input, the tag lists above are set by the tag picker
+    #[serde(default)]
+    expr: Option<SearchExpr>,
+}
+
+impl SearchCriteria {
+    pub fn parse_from_str(input: &str) -> Result<Self, SearchParseError> {
+        let mut operands = vec![];
+        let mut order_by_criteria: Option<OrderCriteria> = None;
+
+        for clause in parser::parse_query(input)? {
+            match clause {
+                Clause::Expr(expr) => operands.push(expr),
+                Clause::OrderBy(criteria) => order_by_criteria = Some(criteria),
+            }
+        }
+
+        let expr = if operands.is_empty() {
+            None
+        } else {
+            Some(SearchExpr::and(operands))
+        };
+
+        Ok(SearchCriteria {
+            order_by: order_by_criteria.unwrap_or(OrderCriteria::OldestFirst),
+            expr,
+            ..Default::default()
+        })
+    }
+
+    /// Every condition of the search as a single expression
+    fn to_expr(&self) -> SearchExpr {
+        let tag = |name: &String| SearchExpr::Tag(name.clone());
+
+        let mut operands: Vec<SearchExpr> = self.contains_tags.iter().map(tag).collect();
+
+        operands.extend(
+            self.contains_tags_or_group
+                .iter()
+                .map(|group| SearchExpr::Or(group.iter().map(tag).collect())),
+        );
+
+        operands.extend(
+            self.excludes_tags
+                .iter()
+                .map(|name| SearchExpr::Not(Box::new(tag(name)))),
+        );
+
+        if let Some(date_range) = &self.date_range {
+            operands.push(SearchExpr::Predicate(Predicate::Imported(TimeRange {
+                start: Some(TimePoint::Absolute(date_range.start as i64)),
+                end: Some(TimePoint::Absolute(date_range.end as i64)),
+            })));
+        }
+
+        operands.extend(self.expr.clone());
+
+        SearchExpr::And(operands)
+    }
+
+    /// Expands wildcard and fuzzy tags into the tags they match, should be called before `to_query`
+    pub async fn expand_tags(&mut self, pool: &Pool<Sqlite>) -> sqlx::Result<()> {
+        if let Some(expr) = &mut self.expr {
+            expand::expand_expr(expr, pool).await?;
+        }
+
+        Ok(())
+    }
+
+    /*
+
+    -- Example for 1boy, 1girl, general OR sensitive
+
+    SELECT m.*
+    FROM Media m
+    WHERE (m.hash IN (SELECT htp.hash FROM HashTagPair htp WHERE htp.tag_name = '1boy')
+    AND m.hash IN (SELECT htp.hash FROM HashTagPair htp WHERE htp.tag_name = '1girl')
+    AND (m.hash IN (SELECT htp.hash FROM HashTagPair htp WHERE htp.tag_name = 'general')
+    OR m.hash IN (SELECT htp.hash FROM HashTagPair htp WHERE htp.tag_name = 'sensitive')))
+
+         */
+
+    pub fn to_query(&self) -> QueryBuilder<Sqlite> {
+        let mut query_builder: QueryBuilder<Sqlite> =
+            QueryBuilder::new("SELECT m.* FROM Media m WHERE ");
+
+        // relative times are resolved against the same `now` for the whole query
+        self.to_expr().push_sql(&mut query_builder, Local::now());
+
+        self.order_by.push_sql(&mut query_builder);
+
+        query_builder
+    }
+
+    /// Pushes `SELECT m.hash` of every media matching the search, unordered
+    pub(crate) fn push_hash_query(&self, query_builder: &mut QueryBuilder<Sqlite>) {
+        query_builder.push("SELECT m.hash FROM Media m WHERE ");
+        self.to_expr().push_sql(query_builder, Local::now());
+    }
+
+    /// Replaces the tag `old` with `new` in the tag lists and the expression, returns if anything changed
+    ///
+    /// Patterns and fuzzy tags are left as they were typed.
+    pub(crate) fn rename_tag(&mut self, old: &str, new: &str) -> bool {
+        let mut changed = rename_in(&mut self.contains_tags, old, new);
+        changed |= rename_in(&mut self.excludes_tags, old, new);
+
+        for group in &mut self.contains_tags_or_group {
+            changed |= rename_in(group, old, new);
+        }
+
+        if let Some(expr) = &mut self.expr {
+            changed |= expr.rename_tag(old, new);
+        }
+
+        changed
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.contains_tags.append(&mut other.contains_tags.clone());
+        self.contains_tags_or_group
+            .append(&mut other.contains_tags_or_group.clone());
+        self.excludes_tags.append(&mut other.excludes_tags.clone());
+
+        self.expr = match (self.expr.take(), other.expr.clone()) {
+            (Some(left), Some(right)) => Some(SearchExpr::And(vec![left, right])),
+            (left, right) => left.or(right),
+        };
+
+        // ordering is not merged as it is a single value and should always prioritize the searchbar value
+    }
+}
+
+#[sqlx::test]
+async fn test_sql_query_gen(pool: Pool<Sqlite>) {
+    use sqlx::migrate;
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    let q = SearchCriteria::parse_from_str("foo, bar, python OR javascript, -csharp").unwrap();
+    let mut q = q.to_query();
+
+    let media1 = Media {
+        hash: "123".to_string(),
+        media_type: "Image".to_string(),
+        thumb_path: Some("nowhere".to_string()),
+        thumbnail_x: 123,
+        thumbnail_y: 123,
+        filesize: 9999,
+        mime: None,
+        time_added: 0,
+        has_file_ref: true,
+        hide: false,
+        is_valid: true,
+    };
+
+    let media2 = Media {
+        hash: "124".to_string(),
+        media_type: "Image".to_string(),
+        thumb_path: Some("nowhere".to_string()),
+        thumbnail_x: 123,
+        thumbnail_y: 123,
+        filesize: 9999,
+        mime: None,
+        time_added: 0,
+        has_file_ref: true,
+        hide: false,
+        is_valid: true,
+    };
+
+    let media3 = Media {
+        hash: "125".to_string(),
+        media_type: "Image".to_string(),
+        thumb_path: Some("nowhere".to_string()),
+        thumbnail_x: 123,
+        thumbnail_y: 123,
+        filesize: 9999,
+        mime: None,
+        time_added: 0,
+        has_file_ref: true,
+        hide: false,
+        is_valid: true,
+    };
+
+    let media4 = Media {
+        hash: "126".to_string(),
+        media_type: "Image".to_string(),
+        thumb_path: Some("nowhere".to_string()),
+        thumbnail_x: 123,
+        thumbnail_y: 123,
+        filesize: 9999,
+        mime: None,
+        time_added: 0,
+        has_file_ref: true,
+        hide: false,
+        is_valid: true,
+    };
+
+    _insert_media_row(&pool, &media1).await;
+    _insert_media_row(&pool, &media2).await;
+    _insert_media_row(&pool, &media3).await;
+    _insert_media_row(&pool, &media4).await;
+
+    insert_hash_tag_pair_row("123", "foo", &pool).await;
+    insert_hash_tag_pair_row("123", "bar", &pool).await;
+    insert_hash_tag_pair_row("123", "python", &pool).await;
+
+    insert_hash_tag_pair_row("124", "foo", &pool).await;
+    insert_hash_tag_pair_row("124", "bar", &pool).await;
+    insert_hash_tag_pair_row("124", "javascript", &pool).await;
+
+    insert_hash_tag_pair_row("125", "foo", &pool).await;
+    insert_hash_tag_pair_row("125", "bar", &pool).await;
+    insert_hash_tag_pair_row("125", "rust", &pool).await;
+
+    insert_hash_tag_pair_row("126", "foo", &pool).await;
+    insert_hash_tag_pair_row("126", "bar", &pool).await;
+    insert_hash_tag_pair_row("126", "python", &pool).await;
+    insert_hash_tag_pair_row("126", "csharp", &pool).await;
+
+    let queried_media: Vec<Media> = q.build_query_as().fetch_all(&pool).await.unwrap();
+
+    assert!(queried_media.contains(&media1));
+    assert!(queried_media.contains(&media2));
+    assert!(!queried_media.contains(&media3));
+    assert!(!queried_media.contains(&media4))
+}
+
+#[sqlx::test]
+async fn test_imported_time_query(pool: Pool<Sqlite>) {
+    use crate::test_util::db_utils::{insert_image_added_at, search_hashes};
+    use sqlx::migrate;
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    let now = Local::now().timestamp_millis();
+    let day = 24 * 60 * 60 * 1000;
+
+    for (hash, time_added) in [("1", now - day), ("2", now - 10 * day), ("3", 0)] {
+        insert_image_added_at(&pool, hash, time_added).await;
+    }
+
+    assert_eq!(
+        search_hashes("imported last 3 days", &pool).await,
+        vec!["1"]
+    );
+    assert_eq!(
+        search_hashes("imported between 2 weeks ago and 2 days ago", &pool).await,
+        vec!["2"]
+    );
+    assert_eq!(
+        search_hashes("added before 2000, order by date", &pool).await,
+        vec!["3"]
+    );
+    assert_eq!(
+        search_hashes("imported after 1999-12-31 or imported 1970", &pool).await,
+        vec!["3", "2", "1"]
+    );
+}
+
+#[sqlx::test]
+async fn test_metadata_query(pool: Pool<Sqlite>) {
+    use crate::test_util::db_utils::{insert_image_row, insert_media_row, search_hashes};
+    use sqlx::migrate;
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    let mb = 1024 * 1024;
+
+    insert_media_row(
+        &pool,
+        "1",
+        "",
+        "Image",
+        mb,
+        "image/png",
+        0,
+        0,
+        1,
+        true,
+        false,
+    )
+    .await;
+    insert_media_row(
+        &pool,
+        "2",
+        "",
+        "Image",
+        3 * mb,
+        "image/webp",
+        0,
+        0,
+        2,
+        true,
+        false,
+    )
+    .await;
+    insert_media_row(
+        &pool,
+        "3",
+        "",
+        "Video",
+        9 * mb,
+        "video/mp4",
+        0,
+        0,
+        3,
+        true,
+        false,
+    )
+    .await;
+
+    insert_image_row(&pool, "1", 1920, 1080).await;
+    insert_image_row(&pool, "2", 800, 1200).await;
+
+    assert_eq!(search_hashes("width>=1920", &pool).await, vec!["1"]);
+    assert_eq!(search_hashes("ratio:portrait", &pool).await, vec!["2"]);
+    assert_eq!(search_hashes("ratio=16:9", &pool).await, vec!["1"]);
+    assert_eq!(search_hashes("size<2MB", &pool).await, vec!["1"]);
+    assert_eq!(
+        search_hashes("size>=2mb, type:image", &pool).await,
+        vec!["2"]
+    );
+    assert_eq!(
+        search_hashes("type:video or mime:image/webp", &pool).await,
+        vec!["2", "3"]
+    );
+    assert_eq!(
+        search_hashes("mime:image/*, -ratio:landscape", &pool).await,
+        vec!["2"]
+    );
+}
+
+#[sqlx::test]
+async fn test_tag_pattern_query(pool: Pool<Sqlite>) {
+    use crate::test_util::db_utils::{insert_image, search_hashes};
+    use sqlx::migrate;
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    let tags_of_media = [
+        ("1", vec!["artist:someone", "blue_hair"]),
+        ("2", vec!["artist:someone_else", "red_hair"]),
+        ("3", vec!["bluesky", "long_hair"]),
+    ];
+
+    for (hash, tags) in tags_of_media {
+        insert_image(&pool, hash).await;
+        let tags = tags.into_iter().map(|t| t.to_string()).collect();
+        super::insert_tags(tags, &pool, Some(hash.to_string()), None).await;
+    }
+
+    assert_eq!(search_hashes("artist:*", &pool).await, vec!["1", "2"]);
+    assert_eq!(search_hashes("*_hair, -red*", &pool).await, vec!["1", "3"]);
+    assert_eq!(search_hashes("blue*", &pool).await, vec!["1", "3"]);
+    assert_eq!(search_hashes("~blue_hiar", &pool).await, vec!["1"]);
+    assert_eq!(
+        search_hashes("~lnog_hair or nothing*", &pool).await,
+        vec!["3"]
+    );
+    assert!(search_hashes("~purple", &pool).await.is_empty());
+
+    // unexpanded patterns still work
+    let criteria = SearchCriteria::parse_from_str("*hair, artist:*").unwrap();
+    let media: Vec<Media> = criteria
+        .to_query()
+        .build_query_as()
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(media.len(), 2);
+}
+
+#[sqlx::test]
+async fn test_tag_source_query(pool: Pool<Sqlite>) {
+    use crate::test_util::db_utils::{insert_image, search_hashes};
+    use kasa_python::ExtractedTag;
+    use sqlx::migrate;
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    for hash in ["1", "2", "3", "4"] {
+        insert_image(&pool, hash).await;
+    }
+
+    super::insert_tags_with_source_types(
+        vec![
+            ExtractedTag::new("artist", "someone"),
+            ExtractedTag::new("general", "blue_hair"),
+        ],
+        &pool,
+        Some("1".to_string()),
+        Some(super::GALLERY_DL_SOURCE.to_string()),
+    )
+    .await;
+    super::insert_tags_with_source_types(
+        vec![ExtractedTag::new("Rating", "general")],
+        &pool,
+        Some("2".to_string()),
+        Some(super::AI_TAGGER_SOURCE.to_string()),
+    )
+    .await;
+    super::insert_tags(
+        vec!["red_hair".to_string()],
+        &pool,
+        Some("3".to_string()),
+        Some(super::AI_PROMPT_SOURCE.to_string()),
+    )
+    .await;
+    super::insert_tags(
+        vec!["long_hair".to_string()],
+        &pool,
+        Some("3".to_string()),
+        None,
+    )
+    .await;
+
+    assert_eq!(search_hashes("tagcount:0", &pool).await, vec!["4"]);
+    assert_eq!(search_hashes("tagcount>=2", &pool).await, vec!["1", "3"]);
+    assert_eq!(search_hashes("source:gallery_dl", &pool).await, vec!["1"]);
+    assert_eq!(search_hashes("source:ai", &pool).await, vec!["2", "3"]);
+    assert_eq!(
+        search_hashes("source:ai, -source:user", &pool).await,
+        vec!["2"]
+    );
+    assert_eq!(search_hashes("source:user", &pool).await, vec!["3"]);
+    assert_eq!(search_hashes("category:artist", &pool).await, vec!["1"]);
+    assert_eq!(
+        search_hashes("category!=rating", &pool).await,
+        vec!["1", "3", "4"]
+    );
+}
+
+#[sqlx::test]
+async fn test_exif_query(pool: Pool<Sqlite>) {
+    use chrono::TimeZone;
+    use sqlx::{migrate, query};
+
+    use crate::test_util::db_utils::{insert_image, search_hashes};
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    let taken_at = |year| {
+        Local
+            .with_ymd_and_hms(year, 5, 14, 12, 0, 0)
+            .unwrap()
+            .timestamp_millis()
+    };
+
+    for (hash, make, model, lens, year) in [
+        ("1", "FUJIFILM", "X100V", None, 2023),
+        ("2", "Canon", "Canon EOS R5", Some("RF35mm F1.8"), 2021),
+    ] {
+        insert_image(&pool, hash).await;
+        query("INSERT INTO Exif(hash, make, model, lens, taken_at) VALUES (?, ?, ?, ?, ?)")
+            .bind(hash)
+            .bind(make)
+            .bind(model)
+            .bind(lens)
+            .bind(taken_at(year))
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+    // without EXIF data
+    insert_image(&pool, "3").await;
+
+    assert_eq!(search_hashes("camera:X100V", &pool).await, vec!["1"]);
+    assert_eq!(search_hashes("camera:canon", &pool).await, vec!["2"]);
+    assert_eq!(
+        search_hashes("camera!=fujifilm", &pool).await,
+        vec!["2", "3"]
+    );
+    assert_eq!(search_hashes("lens:35mm", &pool).await, vec!["2"]);
+    assert_eq!(search_hashes("taken:2023", &pool).await, vec!["1"]);
+    assert_eq!(search_hashes("taken before 2023", &pool).await, vec!["2"]);
+    assert_eq!(
+        search_hashes("taken:2022", &pool).await,
+        Vec::<String>::new()
+    );
+}
+
+#[sqlx::test]
+async fn test_modified_time_query(pool: Pool<Sqlite>) {
+    use crate::test_util::db_utils::{insert_image, insert_path_row, search_hashes};
+    use sqlx::{migrate, query};
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    let now = Local::now().timestamp_millis();
+    let day = 24 * 60 * 60 * 1000;
+
+    for hash in ["1", "2", "3"] {
+        insert_image(&pool, hash).await;
+    }
+    // `Path.mtime` is in nanoseconds
+    for (hash, path, mtime) in [
+        ("1", "/a/1.png", Some(now - day)),
+        ("2", "/a/2.png", Some(now - 10 * day)),
+        ("2", "/b/2.png", Some(now - 20 * day)),
+        ("3", "/a/3.png", None),
+    ] {
+        insert_path_row(&pool, hash, path, "/a").await;
+        query("UPDATE Path SET mtime = ? WHERE path = ?")
+            .bind(mtime.map(|millis| millis * 1_000_000))
+            .bind(path)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(
+        search_hashes("modified last 3 days", &pool).await,
+        vec!["1"]
+    );
+    // any of the paths of the media
+    assert_eq!(
+        search_hashes("modified:between 3 weeks ago and 2 weeks ago", &pool).await,
+        vec!["2"]
+    );
+    assert_eq!(
+        search_hashes("not modified last 2 weeks", &pool).await,
+        vec!["3"]
+    );
+}
+
+pub async fn search_impl() {
+    // http://web.archive.org/web/20150813211028/http://tagging.pui.ch/post/37027745720/tags-database-schemas
+}
+
+pub async fn search_simple_impl(raw_input: &str, pool: &Pool<Sqlite>) -> Vec<Media> {
+    let tags = parse_tags(raw_input);
+
+    // show all Media on empty search
+    if tags.is_empty() {
+        return query_as("SELECT * FROM Media")
+            .fetch_all(pool)
+            .await
+            .unwrap();
+    }
+
+    let mut query_builder: QueryBuilder<Sqlite> =
+        QueryBuilder::new("SELECT m.* FROM HashTagPair htp, Media m WHERE (htp.tag_name) IN (");
+
+    let mut separated = query_builder.separated(", ");
+
+    for tag in &tags {
+        separated.push_bind(tag);
+    }
+
+    separated.push_unseparated(") ");
+
+    query_builder.push("AND m.hash = htp.hash GROUP BY m.hash");
+
+    query_builder.push("HAVING COUNT (m.hash) = ");
+    query_builder.push_bind(tags.len() as i32);
+
+    query_builder.push("AND m.has_file_ref = true");
+
+    let query = query_builder.build_query_as::<Media>();
+
+    query.fetch_all(pool).await.unwrap()
+}
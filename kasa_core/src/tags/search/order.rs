@@ -0,0 +1,261 @@
+//! Sort orders of search results
+//!
+//! ```text
+//! criteria  = ("date" | "time" | "added") ["reverse" | "descending"]
+//!           | "random" ["(" seed ")"]
+//!           | key [direction]
+//! key       = "size" | "filesize" | "resolution" | "pixels" | "tags" | "tagcount" | "name" | "filename"
+//!           | "viewed" | "last viewed"
+//! direction = "asc" | "ascending" | "desc" | "descending" | "reverse"
+//! ```
+//!
+//! Keys without a direction sort the largest, the most tagged and the most recently viewed first and names
+//! alphabetically, `reverse` flips that. `random` without a seed picks one when the search is parsed.
+//!
+//! Every order is broken by `m.hash` so it is the same across queries, which keyset pagination relies on.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use nom::bytes::complete::take_while;
+use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, Sqlite};
+
+use super::{errors::SearchParseErrorKind, parser::PResult, parser::failure};
+
+const PIXEL_COUNT_SQL: &str = "COALESCE((SELECT i.resolution_x * i.resolution_y \
+    FROM Image i WHERE i.hash = m.hash), 0)";
+
+/// Everything after the last `/` of the path, `rtrim` strips the characters of the path without its `/`s
+/// from its end, which leaves the directory
+const FILE_NAME_SQL: &str = "COALESCE((SELECT MIN(lower(replace(p.path, \
+    rtrim(p.path, replace(p.path, '/', '')), ''))) FROM Path p WHERE p.hash = m.hash), '')";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, specta::Type, Serialize, Deserialize)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+impl SortDirection {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            SortDirection::Ascending => "ASC",
+            SortDirection::Descending => "DESC",
+        }
+    }
+
+    fn reversed(self) -> Self {
+        match self {
+            SortDirection::Ascending => SortDirection::Descending,
+            SortDirection::Descending => SortDirection::Ascending,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Default, Clone, specta::Type, Serialize, Deserialize)]
+pub enum OrderCriteria {
+    #[default]
+    NewestFirst,
+    OldestFirst,
+    /// Unordered, paginated queries use `OldestFirst`
+    None,
+    /// Shuffled, the same seed gives the same order as long as the database isn't vacuumed
+    Random(u32),
+    /// `Media.filesize`
+    FileSize(SortDirection),
+    /// Width times height of images, other media count as 0
+    PixelCount(SortDirection),
+    /// Number of tags of the media
+    TagCount(SortDirection),
+    /// File name of the media, without its directories
+    FileName(SortDirection),
+    /// Last time the media was opened, media that was never opened counts as the oldest
+    LastViewed(SortDirection),
+}
+
+impl OrderCriteria {
+    /// SQL expression on `Media m` that is sorted on, never `NULL`
+    pub(super) fn key_sql(&self) -> String {
+        match self {
+            OrderCriteria::NewestFirst | OrderCriteria::OldestFirst | OrderCriteria::None => {
+                "COALESCE(m.time_added, 0)".to_string()
+            }
+            OrderCriteria::Random(seed) => shuffle_sql(*seed),
+            OrderCriteria::FileSize(_) => "COALESCE(m.filesize, 0)".to_string(),
+            OrderCriteria::PixelCount(_) => PIXEL_COUNT_SQL.to_string(),
+            OrderCriteria::TagCount(_) => {
+                "(SELECT COUNT(*) FROM HashTagPair htp WHERE htp.hash = m.hash)".to_string()
+            }
+            OrderCriteria::FileName(_) => FILE_NAME_SQL.to_string(),
+            OrderCriteria::LastViewed(_) => {
+                "COALESCE((SELECT v.last_viewed FROM MediaView v WHERE v.hash = m.hash), 0)"
+                    .to_string()
+            }
+        }
+    }
+
+    /// The key is a string instead of an integer
+    pub(super) fn is_text_key(&self) -> bool {
+        matches!(self, OrderCriteria::FileName(_))
+    }
+
+    pub(super) fn direction(&self) -> SortDirection {
+        match self {
+            OrderCriteria::NewestFirst => SortDirection::Descending,
+            OrderCriteria::OldestFirst | OrderCriteria::None | OrderCriteria::Random(_) => {
+                SortDirection::Ascending
+            }
+            OrderCriteria::FileSize(direction)
+            | OrderCriteria::PixelCount(direction)
+            | OrderCriteria::TagCount(direction)
+            | OrderCriteria::FileName(direction)
+            | OrderCriteria::LastViewed(direction) => *direction,
+        }
+    }
+
+    /// Pushes ` ORDER BY ...`, nothing for `OrderCriteria::None`
+    pub(super) fn push_sql(&self, query_builder: &mut QueryBuilder<Sqlite>) {
+        if *self == OrderCriteria::None {
+            return;
+        }
+
+        let direction = self.direction().as_sql();
+        query_builder.push(format!(
+            " ORDER BY {} {direction}, m.hash {direction}",
+            self.key_sql()
+        ));
+    }
+}
+
+fn xor_sql(a: &str, b: &str) -> String {
+    format!("(({a}) | ({b})) - (({a}) & ({b}))")
+}
+
+/// Integer hash of `m.rowid` and the seed, kept under 2^31 so the multiplications can't overflow
+fn shuffle_sql(seed: u32) -> String {
+    let mixed = format!(
+        "(({}) & 2147483647) * 1103515245 % 2147483648",
+        xor_sql("m.rowid", &seed.to_string())
+    );
+    let shifted = format!("({mixed}) >> 13");
+
+    format!("({}) * 1103515245 % 2147483648", xor_sql(&mixed, &shifted))
+}
+
+fn random_seed() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.subsec_nanos())
+}
+
+fn parse_direction(word: &str, default: SortDirection) -> Option<SortDirection> {
+    let direction = match word {
+        "" => default,
+        "asc" | "ascending" => SortDirection::Ascending,
+        "desc" | "descending" => SortDirection::Descending,
+        "reverse" => default.reversed(),
+        _ => return None,
+    };
+
+    Some(direction)
+}
+
+fn criteria_from_str(criteria: &str) -> Option<OrderCriteria> {
+    let criteria = match criteria {
+        // sort by date in order
+        "date" | "time" | "added" => OrderCriteria::NewestFirst,
+
+        // sort by date in reverse order
+        "date descending" | "date reverse" | "time descending" | "time reverse"
+        | "added reverse" | "added descending" => OrderCriteria::OldestFirst,
+
+        "random" => OrderCriteria::Random(random_seed()),
+
+        _ => {
+            if let Some(seed) = criteria
+                .strip_prefix("random")
+                .and_then(|rest| rest.trim_start().strip_prefix('('))
+                .and_then(|rest| rest.strip_suffix(')'))
+            {
+                return seed.trim().parse().ok().map(OrderCriteria::Random);
+            }
+
+            let (key, direction) = match criteria.strip_prefix("last viewed") {
+                Some(direction) => ("viewed", direction.trim_start()),
+                None => criteria.split_once(' ').unwrap_or((criteria, "")),
+            };
+
+            let (criteria, default): (fn(SortDirection) -> OrderCriteria, _) = match key {
+                "size" | "filesize" => (OrderCriteria::FileSize, SortDirection::Descending),
+                "resolution" | "pixels" => (OrderCriteria::PixelCount, SortDirection::Descending),
+                "tags" | "tagcount" => (OrderCriteria::TagCount, SortDirection::Descending),
+                "name" | "filename" => (OrderCriteria::FileName, SortDirection::Ascending),
+                "viewed" => (OrderCriteria::LastViewed, SortDirection::Descending),
+                _ => return None,
+            };
+
+            criteria(parse_direction(direction, default)?)
+        }
+    };
+
+    Some(criteria)
+}
+
+/// Parses everything up to the next `,` as the sort order
+pub(super) fn parse_order_criteria(input: &str) -> PResult<'_, OrderCriteria> {
+    let (rest, raw) = take_while(|c: char| c != ',')(input)?;
+    let raw = raw.trim_end();
+
+    let normalized = raw
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase();
+
+    match criteria_from_str(&normalized) {
+        Some(criteria) => Ok((rest, criteria)),
+        None => failure(
+            input,
+            raw.len(),
+            SearchParseErrorKind::InvalidOrderCriteria(raw.to_string()),
+        ),
+    }
+}
+
+#[test]
+fn test_parse_order_criteria() {
+    let parse = |input| parse_order_criteria(input).unwrap().1;
+
+    assert_eq!(parse("date"), OrderCriteria::NewestFirst);
+    assert_eq!(parse("Date Reverse"), OrderCriteria::OldestFirst);
+    assert_eq!(parse("random(42)"), OrderCriteria::Random(42));
+    assert_eq!(parse("random (42)"), OrderCriteria::Random(42));
+    assert!(matches!(parse("random"), OrderCriteria::Random(_)));
+    assert_eq!(
+        parse("size"),
+        OrderCriteria::FileSize(SortDirection::Descending)
+    );
+    assert_eq!(
+        parse("resolution asc"),
+        OrderCriteria::PixelCount(SortDirection::Ascending)
+    );
+    assert_eq!(
+        parse("tags reverse"),
+        OrderCriteria::TagCount(SortDirection::Ascending)
+    );
+    assert_eq!(
+        parse("name"),
+        OrderCriteria::FileName(SortDirection::Ascending)
+    );
+    assert_eq!(
+        parse("last viewed"),
+        OrderCriteria::LastViewed(SortDirection::Descending)
+    );
+
+    for invalid in ["colour", "size sideways", "random(seed)"] {
+        assert!(matches!(
+            parse_order_criteria(invalid),
+            Err(nom::Err::Failure(_))
+        ));
+    }
+}
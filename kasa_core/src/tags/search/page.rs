@@ -0,0 +1,234 @@
+//! Keyset pagination over search results
+//!
+//! Pages are ordered by the sort key of the search and `hash`, and continue from the last media of the
+//! previous page, unlike `OFFSET` this doesn't skip or repeat media when media before the cursor is added or
+//! removed, and doesn't get slower the further the page is.
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, QueryBuilder, Row, Sqlite, sqlite::SqliteRow};
+
+use crate::db::schema::Media;
+
+use super::{SearchCriteria, SortDirection};
+
+/// Value of `OrderCriteria::key_sql` for a media
+#[derive(Debug, PartialEq, Eq, Clone, specta::Type, Serialize, Deserialize)]
+pub enum SortKey {
+    Integer(i64),
+    Text(String),
+}
+
+/// Position of the last media of a page
+#[derive(Debug, PartialEq, Eq, Clone, specta::Type, Serialize, Deserialize)]
+pub struct SearchCursor {
+    pub key: SortKey,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, specta::Type, Serialize, Deserialize)]
+pub struct SearchPage {
+    pub media: Vec<Media>,
+    /// Cursor for the next page, `None` if this is the last one
+    pub next_cursor: Option<SearchCursor>,
+    /// Number of media matching the search, only counted for the first page
+    pub total: Option<i64>,
+}
+
+impl SearchCriteria {
+    pub fn to_count_query(&self) -> QueryBuilder<Sqlite> {
+        let mut query_builder: QueryBuilder<Sqlite> =
+            QueryBuilder::new("SELECT COUNT(*) FROM Media m WHERE ");
+
+        self.to_expr().push_sql(&mut query_builder, Local::now());
+
+        query_builder
+    }
+
+    /// Up to `limit` media after `cursor` with their sort key as `sort_key`, `OrderCriteria::None` is ordered
+    /// like `OldestFirst`
+    pub fn to_page_query(&self, cursor: Option<&SearchCursor>, limit: u32) -> QueryBuilder<Sqlite> {
+        let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+            "SELECT * FROM (SELECT m.*, {} AS sort_key FROM Media m WHERE ",
+            self.order_by.key_sql()
+        ));
+
+        self.to_expr().push_sql(&mut query_builder, Local::now());
+        query_builder.push(") m");
+
+        let direction = self.order_by.direction();
+        let (comparison, direction) = match direction {
+            SortDirection::Ascending => (">", "ASC"),
+            SortDirection::Descending => ("<", "DESC"),
+        };
+
+        if let Some(cursor) = cursor {
+            query_builder.push(format!(" WHERE (m.sort_key, m.hash) {comparison} ("));
+            match &cursor.key {
+                SortKey::Integer(key) => query_builder.push_bind(*key),
+                SortKey::Text(key) => query_builder.push_bind(key.clone()),
+            };
+            query_builder.push(", ");
+            query_builder.push_bind(cursor.hash.clone());
+            query_builder.push(")");
+        }
+
+        query_builder.push(format!(
+            " ORDER BY m.sort_key {direction}, m.hash {direction} LIMIT "
+        ));
+        query_builder.push_bind(limit as i64);
+
+        query_builder
+    }
+
+    fn cursor_from_row(&self, row: &SqliteRow) -> sqlx::Result<SearchCursor> {
+        let key = if self.order_by.is_text_key() {
+            SortKey::Text(row.try_get("sort_key")?)
+        } else {
+            SortKey::Integer(row.try_get("sort_key")?)
+        };
+
+        Ok(SearchCursor {
+            key,
+            hash: row.try_get("hash")?,
+        })
+    }
+
+    /// Fetches the page after `cursor`, or the first page if it is `None`
+    ///
+    /// `expand_tags` should be called before fetching the first page.
+    pub async fn fetch_page(
+        &self,
+        cursor: Option<&SearchCursor>,
+        limit: u32,
+        pool: &Pool<Sqlite>,
+    ) -> sqlx::Result<SearchPage> {
+        // one extra row to know if there is a next page
+        let mut rows = self
+            .to_page_query(cursor, limit.saturating_add(1))
+            .build()
+            .fetch_all(pool)
+            .await?;
+
+        let next_cursor = if rows.len() > limit as usize {
+            rows.truncate(limit as usize);
+            rows.last()
+                .map(|row| self.cursor_from_row(row))
+                .transpose()?
+        } else {
+            None
+        };
+
+        let media = rows
+            .iter()
+            .map(Media::from_row)
+            .collect::<sqlx::Result<Vec<Media>>>()?;
+
+        let total = match cursor {
+            Some(_) => None,
+            None => Some(
+                self.to_count_query()
+                    .build_query_scalar()
+                    .fetch_one(pool)
+                    .await?,
+            ),
+        };
+
+        Ok(SearchPage {
+            media,
+            next_cursor,
+            total,
+        })
+    }
+}
+
+#[sqlx::test]
+async fn test_search_pages(pool: Pool<Sqlite>) {
+    use crate::test_util::db_utils::{
+        insert_hash_tag_pair_row, insert_image_added_at, insert_path_row,
+    };
+    use sqlx::migrate;
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    // two media share a time to check the hash tiebreaker
+    for (hash, time_added) in [("a", 1), ("b", 2), ("c", 2), ("d", 3), ("e", 4)] {
+        insert_image_added_at(&pool, hash, time_added).await;
+    }
+    insert_hash_tag_pair_row("e", "hidden", &pool).await;
+    insert_path_row(&pool, "a", "/b/zebra.png", "/b").await;
+    insert_path_row(&pool, "b", "/a/Apple.png", "/a").await;
+
+    let collect_pages = async |input: &str| -> (Vec<Vec<String>>, Option<i64>) {
+        let criteria = SearchCriteria::parse_from_str(input).unwrap();
+        let hashes = |page: &SearchPage| page.media.iter().map(|m| m.hash.clone()).collect();
+
+        let mut page = criteria.fetch_page(None, 2, &pool).await.unwrap();
+        let total = page.total;
+        let mut pages = vec![hashes(&page)];
+
+        while let Some(cursor) = page.next_cursor.take() {
+            page = criteria.fetch_page(Some(&cursor), 2, &pool).await.unwrap();
+            assert_eq!(page.total, None);
+            pages.push(hashes(&page));
+        }
+
+        (pages, total)
+    };
+
+    let (pages, total) = collect_pages("").await;
+    assert_eq!(pages, vec![vec!["a", "b"], vec!["c", "d"], vec!["e"]]);
+    assert_eq!(total, Some(5));
+
+    let (pages, _) = collect_pages("order by date").await;
+    assert_eq!(pages, vec![vec!["e", "d"], vec!["c", "b"], vec!["a"]]);
+
+    let (pages, total) = collect_pages("-hidden").await;
+    assert_eq!(pages, vec![vec!["a", "b"], vec!["c", "d"]]);
+    assert_eq!(total, Some(4));
+
+    // only `e` has a tag, the hash breaks the ties in the same direction
+    let (pages, _) = collect_pages("order by tags").await;
+    assert_eq!(pages, vec![vec!["e", "d"], vec!["c", "b"], vec!["a"]]);
+
+    let (shuffled, _) = collect_pages("order by random(7)").await;
+    assert_eq!(shuffled, collect_pages("order by random(7)").await.0);
+    assert_eq!(shuffled.concat().len(), 5);
+
+    // media without a path sorts as an empty name
+    let (pages, _) = collect_pages("order by name").await;
+    assert_eq!(pages, vec![vec!["c", "d"], vec!["e", "b"], vec!["a"]]);
+}
+
+#[sqlx::test]
+async fn test_search_pages_null_keys(pool: Pool<Sqlite>) {
+    use crate::test_util::db_utils::insert_image_added_at;
+    use sqlx::{migrate, query};
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    for (hash, time_added) in [("a", 1), ("b", 2), ("c", 3)] {
+        insert_image_added_at(&pool, hash, time_added).await;
+    }
+    query("UPDATE Media SET time_added = NULL, filesize = NULL WHERE hash = 'b'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    // missing keys sort as 0 instead of ending the pages early
+    for (input, expected) in [("", ["b", "a", "c"]), ("order by size", ["c", "b", "a"])] {
+        let criteria = SearchCriteria::parse_from_str(input).unwrap();
+        let mut hashes = vec![];
+        let mut cursor = None;
+        loop {
+            let page = criteria
+                .fetch_page(cursor.as_ref(), 1, &pool)
+                .await
+                .unwrap();
+            hashes.extend(page.media.into_iter().map(|m| m.hash));
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(hashes, expected);
+    }
+}
@@ -0,0 +1,436 @@
+//! Parser for the search bar query language
+//!
+//! ```text
+//! query   = clause ("," clause)*
+//! clause  = "order by" criteria | or
+//! or      = and (("or" | "|" | "||") and)*
+//! and     = unary (("and" | "&" | "&&") unary)*
+//! unary   = ("not" | "-" | "!") unary | "(" or ("," or)* ")" | atom
+//! atom    = predicate | '"' quoted tag '"' | "~" bare tag | bare tag
+//! ```
+//!
+//! Commas are the loosest binding AND, so `a or b, c` is `(a or b) and c`. Bare tags can contain
+//! spaces, they end at a delimiter or at an `or`/`and` keyword, quote the tag to use those inside of it.
+//! Bare tags can use `*` as a wildcard (`artist:*`, `*_hair`), `~tag` matches tags with small typos.
+//! Tags starting with a predicate like `imported` or `type:` also need to be quoted, see `predicates.rs`.
+
+use nom::{
+    IResult,
+    branch::alt,
+    bytes::complete::{tag, tag_no_case, take_while1},
+    character::complete::{char, multispace0, multispace1, satisfy},
+    combinator::{cut, not, peek},
+    error::{ErrorKind, ParseError},
+};
+
+use super::{
+    OrderCriteria, SearchExpr,
+    errors::{SearchParseError, SearchParseErrorKind, Span},
+    order::parse_order_criteria,
+    predicates::parse_predicate,
+};
+
+const KEYWORDS: [&str; 2] = ["or", "and"];
+
+/// A single top level, comma separated part of the search
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Clause {
+    Expr(SearchExpr),
+    OrderBy(OrderCriteria),
+}
+
+/// nom error that keeps the position of the failure, converted to `SearchParseError` once parsing is done
+#[derive(Debug, PartialEq)]
+pub(super) struct RawError<'a> {
+    pub(super) input: &'a str,
+    pub(super) len: usize,
+    pub(super) kind: SearchParseErrorKind,
+}
+
+impl<'a> RawError<'a> {
+    pub(super) fn new(input: &'a str, len: usize, kind: SearchParseErrorKind) -> Self {
+        RawError { input, len, kind }
+    }
+
+    fn into_search_error(self, original: &str) -> SearchParseError {
+        let start = original.len() - self.input.len();
+
+        SearchParseError {
+            kind: self.kind,
+            span: Span {
+                start,
+                end: start + self.len,
+            },
+        }
+    }
+}
+
+impl<'a> ParseError<&'a str> for RawError<'a> {
+    fn from_error_kind(input: &'a str, _kind: ErrorKind) -> Self {
+        RawError::new(
+            input,
+            next_char_len(input),
+            SearchParseErrorKind::ExpectedExpression,
+        )
+    }
+
+    fn append(_input: &'a str, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+pub(super) type PResult<'a, T> = IResult<&'a str, T, RawError<'a>>;
+
+pub(super) fn failure<T>(input: &str, len: usize, kind: SearchParseErrorKind) -> PResult<'_, T> {
+    Err(nom::Err::Failure(RawError::new(input, len, kind)))
+}
+
+pub(super) fn next_char_len(input: &str) -> usize {
+    input.chars().next().map_or(0, char::len_utf8)
+}
+
+pub(super) fn is_word_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, ',' | '(' | ')' | '|' | '&' | '"')
+}
+
+fn is_keyword(word: &str) -> bool {
+    KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(word))
+}
+
+/// Turns recoverable errors of `parser` into failures of `kind`, used after a predicate keyword was matched
+pub(super) fn expect<'a, O>(
+    kind: SearchParseErrorKind,
+    mut parser: impl FnMut(&'a str) -> PResult<'a, O>,
+) -> impl FnMut(&'a str) -> PResult<'a, O> {
+    move |input| match parser(input) {
+        Err(nom::Err::Error(e)) => Err(nom::Err::Failure(RawError {
+            kind: kind.clone(),
+            ..e
+        })),
+        result => result,
+    }
+}
+
+/// Parses the raw search input into its top level clauses
+pub(super) fn parse_query(input: &str) -> Result<Vec<Clause>, SearchParseError> {
+    match parse_clauses(input) {
+        Ok((_, clauses)) => Ok(clauses),
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => Err(e.into_search_error(input)),
+        Err(nom::Err::Incomplete(_)) => unreachable!("complete parsers don't return Incomplete"),
+    }
+}
+
+fn parse_clauses(mut input: &str) -> PResult<'_, Vec<Clause>> {
+    let mut clauses = vec![];
+
+    loop {
+        let (rest, _) = multispace0(input)?;
+        input = rest;
+
+        if input.is_empty() {
+            return Ok((input, clauses));
+        }
+
+        // empty clauses like `foo,,bar` or a trailing comma are allowed
+        if let Some(rest) = input.strip_prefix(',') {
+            input = rest;
+            continue;
+        }
+
+        let (rest, clause) = parse_clause(input)?;
+        clauses.push(clause);
+
+        let (rest, _) = multispace0(rest)?;
+        input = rest;
+
+        if input.is_empty() {
+            return Ok((input, clauses));
+        }
+
+        match input.strip_prefix(',') {
+            Some(rest) => input = rest,
+            None if input.starts_with(')') => {
+                return failure(input, 1, SearchParseErrorKind::UnmatchedClosingParenthesis);
+            }
+            None => {
+                return failure(
+                    input,
+                    next_char_len(input),
+                    SearchParseErrorKind::UnexpectedInput,
+                );
+            }
+        }
+    }
+}
+
+fn parse_clause(input: &str) -> PResult<'_, Clause> {
+    if let Ok((rest, _)) = order_by_keyword(input) {
+        let (rest, criteria) = parse_order_criteria(rest)?;
+        return Ok((rest, Clause::OrderBy(criteria)));
+    }
+
+    let (rest, expr) = parse_or(input)?;
+    Ok((rest, Clause::Expr(expr)))
+}
+
+fn order_by_keyword(input: &str) -> PResult<'_, ()> {
+    let (input, _) = tag_no_case("order")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, _) = keyword("by")(input)?;
+    let (input, _) = multispace0(input)?;
+    Ok((input, ()))
+}
+
+/// Matches a case insensitive keyword that isn't a prefix of a longer word
+pub(super) fn keyword<'a>(word: &'static str) -> impl FnMut(&'a str) -> PResult<'a, &'a str> {
+    move |input| {
+        let (rest, matched) = tag_no_case(word)(input)?;
+        let (rest, _) = peek(not(satisfy(is_word_char)))(rest)?;
+        Ok((rest, matched))
+    }
+}
+
+fn or_operator(input: &str) -> PResult<'_, ()> {
+    let (input, _) = multispace0(input)?;
+    let (input, _) = alt((tag("||"), tag("|"), keyword("or")))(input)?;
+    let (input, _) = multispace0(input)?;
+    Ok((input, ()))
+}
+
+fn and_operator(input: &str) -> PResult<'_, ()> {
+    let (input, _) = multispace0(input)?;
+    let (input, _) = alt((tag("&&"), tag("&"), keyword("and")))(input)?;
+    let (input, _) = multispace0(input)?;
+    Ok((input, ()))
+}
+
+fn not_operator(input: &str) -> PResult<'_, &str> {
+    alt((tag("-"), tag("!"), keyword("not")))(input)
+}
+
+fn parse_or(input: &str) -> PResult<'_, SearchExpr> {
+    let (mut input, first) = parse_and(input)?;
+    let mut operands = vec![first];
+
+    while let Ok((rest, _)) = or_operator(input) {
+        let (rest, operand) = cut(parse_and)(rest)?;
+        operands.push(operand);
+        input = rest;
+    }
+
+    Ok((input, SearchExpr::or(operands)))
+}
+
+fn parse_and(input: &str) -> PResult<'_, SearchExpr> {
+    let (mut input, first) = parse_unary(input)?;
+    let mut operands = vec![first];
+
+    while let Ok((rest, _)) = and_operator(input) {
+        let (rest, operand) = cut(parse_unary)(rest)?;
+        operands.push(operand);
+        input = rest;
+    }
+
+    Ok((input, SearchExpr::and(operands)))
+}
+
+fn parse_unary(input: &str) -> PResult<'_, SearchExpr> {
+    let (input, _) = multispace0(input)?;
+
+    if let Ok((rest, _)) = not_operator(input) {
+        let (rest, inner) = cut(parse_unary)(rest)?;
+        return Ok((rest, SearchExpr::Not(Box::new(inner))));
+    }
+
+    if input.starts_with('(') {
+        return parse_group(input);
+    }
+
+    parse_atom(input)
+}
+
+/// `(expr, expr or expr)`, commas inside of the parenthesis are ANDs like they are on the top level
+fn parse_group(input: &str) -> PResult<'_, SearchExpr> {
+    let open = input;
+    let (input, _) = char('(')(input)?;
+
+    let (mut input, first) = cut(parse_or)(input)?;
+    let mut operands = vec![first];
+
+    loop {
+        let (rest, _) = multispace0(input)?;
+
+        if let Some(rest) = rest.strip_prefix(')') {
+            return Ok((rest, SearchExpr::and(operands)));
+        }
+
+        if let Some(rest) = rest.strip_prefix(',') {
+            let (rest, operand) = cut(parse_or)(rest)?;
+            operands.push(operand);
+            input = rest;
+            continue;
+        }
+
+        if rest.is_empty() {
+            return failure(open, 1, SearchParseErrorKind::UnclosedParenthesis);
+        }
+
+        return failure(
+            rest,
+            next_char_len(rest),
+            SearchParseErrorKind::UnexpectedInput,
+        );
+    }
+}
+
+fn parse_atom(input: &str) -> PResult<'_, SearchExpr> {
+    match parse_predicate(input) {
+        Ok((rest, predicate)) => return Ok((rest, predicate)),
+        // not a predicate, parse it as a tag
+        Err(nom::Err::Error(_)) => {}
+        Err(e) => return Err(e),
+    }
+
+    if input.starts_with('"') {
+        let (rest, tag) = parse_quoted(input)?;
+        return Ok((rest, SearchExpr::Tag(tag)));
+    }
+
+    let (rest, tag) = parse_bare_tag(input)?;
+
+    // `~tag` and `tag*` are only special outside of quotes
+    if let Some(fuzzy) = tag.strip_prefix('~').filter(|t| !t.is_empty()) {
+        return Ok((rest, SearchExpr::FuzzyTag(fuzzy.to_string())));
+    }
+
+    if tag.contains('*') {
+        return Ok((rest, SearchExpr::TagPattern(tag.to_string())));
+    }
+
+    Ok((rest, SearchExpr::Tag(tag.to_string())))
+}
+
+/// `"tag, with commas"`, `\"` and `\\` can be used to escape quotes and backslashes
+fn parse_quoted(input: &str) -> PResult<'_, String> {
+    let (rest, _) = char('"')(input)?;
+
+    let mut output = String::new();
+    let mut chars = rest.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((&rest[i + 1..], output)),
+            '\\' => match chars.next() {
+                Some((_, escaped)) => output.push(escaped),
+                None => break,
+            },
+            c => output.push(c),
+        }
+    }
+
+    failure(input, input.len(), SearchParseErrorKind::UnclosedQuote)
+}
+
+fn next_word(input: &str) -> PResult<'_, &str> {
+    let (input, _) = multispace1(input)?;
+    take_while1(is_word_char)(input)
+}
+
+/// Words separated by whitespace, until a delimiter or a keyword
+fn parse_bare_tag(input: &str) -> PResult<'_, &str> {
+    let (mut rest, first) = take_while1(is_word_char)(input)?;
+
+    if is_keyword(first) {
+        return Err(nom::Err::Error(RawError::new(
+            input,
+            first.len(),
+            SearchParseErrorKind::ExpectedExpression,
+        )));
+    }
+
+    while let Ok((after_word, word)) = next_word(rest) {
+        if is_keyword(word) {
+            break;
+        }
+
+        rest = after_word;
+    }
+
+    Ok((rest, &input[..input.len() - rest.len()]))
+}
+
+#[cfg(test)]
+fn tag_expr(name: &str) -> SearchExpr {
+    SearchExpr::Tag(name.to_string())
+}
+
+#[test]
+fn test_parse_precedence() {
+    let clauses = parse_query("foo, bar or baz and -qux").unwrap();
+
+    let expected = vec![
+        Clause::Expr(tag_expr("foo")),
+        Clause::Expr(SearchExpr::Or(vec![
+            tag_expr("bar"),
+            SearchExpr::And(vec![
+                tag_expr("baz"),
+                SearchExpr::Not(Box::new(tag_expr("qux"))),
+            ]),
+        ])),
+    ];
+
+    assert_eq!(clauses, expected);
+}
+
+#[test]
+fn test_parse_nested_parenthesis() {
+    let clauses = parse_query("not (cat | (dog, blue hair)), order by date").unwrap();
+
+    let expected = vec![
+        Clause::Expr(SearchExpr::Not(Box::new(SearchExpr::Or(vec![
+            tag_expr("cat"),
+            SearchExpr::And(vec![tag_expr("dog"), tag_expr("blue hair")]),
+        ])))),
+        Clause::OrderBy(OrderCriteria::NewestFirst),
+    ];
+
+    assert_eq!(clauses, expected);
+}
+
+#[test]
+fn test_parse_quoted() {
+    let clauses = parse_query(r#""a, b" OR "c \"d\"",, orange"#).unwrap();
+
+    let expected = vec![
+        Clause::Expr(SearchExpr::Or(vec![tag_expr("a, b"), tag_expr(r#"c "d""#)])),
+        Clause::Expr(tag_expr("orange")),
+    ];
+
+    assert_eq!(clauses, expected);
+}
+
+#[test]
+fn test_parse_errors() {
+    let err = parse_query("foo, (bar or baz").unwrap_err();
+    assert_eq!(err.kind, SearchParseErrorKind::UnclosedParenthesis);
+    assert_eq!(err.span, Span { start: 5, end: 6 });
+
+    let err = parse_query("foo)").unwrap_err();
+    assert_eq!(err.kind, SearchParseErrorKind::UnmatchedClosingParenthesis);
+    assert_eq!(err.span, Span { start: 3, end: 4 });
+
+    let err = parse_query(r#"foo, "bar"#).unwrap_err();
+    assert_eq!(err.kind, SearchParseErrorKind::UnclosedQuote);
+    assert_eq!(err.span, Span { start: 5, end: 9 });
+
+    let err = parse_query("foo or").unwrap_err();
+    assert_eq!(err.kind, SearchParseErrorKind::ExpectedExpression);
+    assert_eq!(err.span, Span { start: 6, end: 6 });
+
+    let err = parse_query("foo, order by colour").unwrap_err();
+    assert_eq!(
+        err.kind,
+        SearchParseErrorKind::InvalidOrderCriteria("colour".to_string())
+    );
+    assert_eq!(err.span, Span { start: 14, end: 20 });
+}
@@ -0,0 +1,644 @@
+//! Search terms that match on media properties instead of tags
+//!
+//! ```text
+//! predicate  = ("imported" | "added" | "taken" | "modified") time_range
+//!            | ("taken:" | "modified:") time_range
+//!            | field comparison value
+//! field      = "width" | "height" | "size" | "filesize" | "ratio" | "aspect" | "type" | "mime"
+//!            | "tagcount" | "source" | "category" | "camera" | "lens"
+//! comparison = ":" | "=" | "!=" | "<" | "<=" | ">" | ">="
+//! ```
+//!
+//! `width>=1920`, `size<2MB`, `ratio:portrait`, `ratio>16:9`, `type:video`, `mime:image/*`, `tagcount:0`,
+//! `source:ai`, `category:artist`, `camera:x100v`, `lens:35mm`, `taken:2023`, `modified last week`
+//!
+//! See `time.rs` for `time_range`
+
+use chrono::{DateTime, Local};
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, tag_no_case, take_while1},
+    character::complete::{alpha1, multispace1},
+    combinator::value,
+    sequence::terminated,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, Sqlite};
+
+use crate::{
+    db::schema::{MediaType, media_type_to_string},
+    tags::{AI_PROMPT_SOURCE, AI_TAGGER_SOURCE, GALLERY_DL_SOURCE},
+};
+
+use super::{
+    SearchExpr,
+    errors::SearchParseErrorKind,
+    parser::{PResult, RawError, failure, is_word_char, keyword},
+    time::{TimeRange, parse_time_range},
+};
+
+/// Aspect ratios closer than this are considered equal
+const RATIO_TOLERANCE: f64 = 0.01;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, specta::Type, Serialize, Deserialize)]
+pub enum Comparison {
+    Eq,
+    NotEq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            Comparison::Eq => "=",
+            Comparison::NotEq => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, specta::Type, Serialize, Deserialize)]
+pub enum NumericField {
+    /// `Image.resolution_x`
+    Width,
+    /// `Image.resolution_y`
+    Height,
+    /// `Media.filesize` in bytes
+    FileSize,
+    /// Width divided by height
+    AspectRatio,
+    /// Number of `HashTagPair`s of the media
+    TagCount,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, specta::Type, Serialize, Deserialize)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+    Square,
+}
+
+#[derive(Debug, PartialEq, Clone, specta::Type, Serialize, Deserialize)]
+pub enum Predicate {
+    /// `Media.time_added` is in the range
+    Imported(TimeRange),
+    /// `Exif.taken_at` is in the range
+    Taken(TimeRange),
+    /// `Path.mtime` of one of the paths of the media is in the range
+    Modified(TimeRange),
+    Numeric {
+        field: NumericField,
+        comparison: Comparison,
+        value: f64,
+    },
+    Orientation(Orientation),
+    /// One of the `MediaType` names
+    MediaType(String),
+    /// Exact mime type, or a prefix of it if it ends with `/`, `image/` matches all images
+    Mime(String),
+    /// Media has a tag with this lowercase `HashTagPair.source`, `None` matches tags added by hand
+    TagSource(Option<String>),
+    /// Media has a tag with this lowercase `HashTagPair.source_type`, like `artist` for gallery-dl tags
+    TagCategory(String),
+    /// Lowercase part of the `Exif.make` and `Exif.model` of the camera, like `fujifilm` or `x100v`
+    Camera(String),
+    /// Lowercase part of `Exif.lens`
+    Lens(String),
+}
+
+impl Predicate {
+    /// Pushes the predicate as a condition on `Media m`
+    pub(super) fn push_sql(&self, query_builder: &mut QueryBuilder<Sqlite>, now: DateTime<Local>) {
+        match self {
+            Predicate::Imported(range) => range.push_sql("m.time_added", query_builder, now),
+            Predicate::Taken(range) => {
+                query_builder.push("m.hash IN (SELECT e.hash FROM Exif e WHERE ");
+                range.push_sql("e.taken_at", query_builder, now);
+                query_builder.push(")");
+            }
+            Predicate::Modified(range) => {
+                // `Path.mtime` is in nanoseconds
+                query_builder.push("m.hash IN (SELECT p.hash FROM Path p WHERE ");
+                range.push_sql("p.mtime / 1000000", query_builder, now);
+                query_builder.push(")");
+            }
+            Predicate::Numeric {
+                field: NumericField::FileSize,
+                comparison,
+                value,
+            } => {
+                query_builder.push(format!("m.filesize {} ", comparison.as_sql()));
+                query_builder.push_bind(*value);
+            }
+            Predicate::Numeric {
+                field: NumericField::TagCount,
+                comparison,
+                value,
+            } => {
+                query_builder.push(format!(
+                    "(SELECT COUNT(*) FROM HashTagPair htp WHERE htp.hash = m.hash) {} ",
+                    comparison.as_sql()
+                ));
+                query_builder.push_bind(*value);
+            }
+            Predicate::Numeric {
+                field,
+                comparison,
+                value,
+            } => {
+                query_builder.push("m.hash IN (SELECT i.hash FROM Image i WHERE ");
+
+                match field {
+                    NumericField::Width => {
+                        query_builder.push(format!("i.resolution_x {} ", comparison.as_sql()));
+                        query_builder.push_bind(*value);
+                    }
+                    NumericField::Height => {
+                        query_builder.push(format!("i.resolution_y {} ", comparison.as_sql()));
+                        query_builder.push_bind(*value);
+                    }
+                    NumericField::AspectRatio => {
+                        let ratio = "i.resolution_x * 1.0 / i.resolution_y";
+                        query_builder.push("i.resolution_y > 0 AND ");
+
+                        match comparison {
+                            Comparison::Eq => query_builder.push(format!("ABS({ratio} - ")),
+                            Comparison::NotEq => query_builder.push(format!("NOT ABS({ratio} - ")),
+                            _ => query_builder.push(format!("{ratio} {} ", comparison.as_sql())),
+                        };
+                        query_builder.push_bind(*value);
+
+                        if matches!(comparison, Comparison::Eq | Comparison::NotEq) {
+                            query_builder.push(") < ");
+                            query_builder.push_bind(RATIO_TOLERANCE);
+                        }
+                    }
+                    NumericField::FileSize | NumericField::TagCount => {
+                        unreachable!("handled above")
+                    }
+                }
+
+                query_builder.push(")");
+            }
+            Predicate::Orientation(orientation) => {
+                let condition = match orientation {
+                    Orientation::Portrait => "i.resolution_y > i.resolution_x",
+                    Orientation::Landscape => "i.resolution_x > i.resolution_y",
+                    Orientation::Square => "i.resolution_x = i.resolution_y",
+                };
+
+                query_builder.push(format!(
+                    "m.hash IN (SELECT i.hash FROM Image i WHERE {condition})"
+                ));
+            }
+            Predicate::MediaType(media_type) => {
+                query_builder.push("m.media_type = ");
+                query_builder.push_bind(media_type.clone());
+            }
+            Predicate::Mime(mime) if mime.ends_with('/') => {
+                query_builder.push("instr(lower(m.mime), ");
+                query_builder.push_bind(mime.clone());
+                query_builder.push(") = 1");
+            }
+            Predicate::Mime(mime) => {
+                query_builder.push("lower(m.mime) = ");
+                query_builder.push_bind(mime.clone());
+            }
+            Predicate::TagSource(Some(source)) => {
+                query_builder.push(
+                    "m.hash IN (SELECT htp.hash FROM HashTagPair htp WHERE lower(htp.source) = ",
+                );
+                query_builder.push_bind(source.clone());
+                query_builder.push(")");
+            }
+            Predicate::TagSource(None) => {
+                query_builder.push(
+                    "m.hash IN (SELECT htp.hash FROM HashTagPair htp WHERE htp.source IS NULL)",
+                );
+            }
+            Predicate::TagCategory(category) => {
+                query_builder.push(
+                    "m.hash IN (SELECT htp.hash FROM HashTagPair htp WHERE lower(htp.source_type) = ",
+                );
+                query_builder.push_bind(category.clone());
+                query_builder.push(")");
+            }
+            Predicate::Camera(camera) => {
+                query_builder.push(
+                    "m.hash IN (SELECT e.hash FROM Exif e WHERE \
+                    instr(lower(coalesce(e.make, '') || ' ' || coalesce(e.model, '')), ",
+                );
+                query_builder.push_bind(camera.clone());
+                query_builder.push(") > 0)");
+            }
+            Predicate::Lens(lens) => {
+                query_builder
+                    .push("m.hash IN (SELECT e.hash FROM Exif e WHERE instr(lower(e.lens), ");
+                query_builder.push_bind(lens.clone());
+                query_builder.push(") > 0)");
+            }
+        }
+    }
+}
+
+/// Fields that can be used with a comparison
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Width,
+    Height,
+    Size,
+    Ratio,
+    Type,
+    Mime,
+    TagCount,
+    Source,
+    Category,
+    Camera,
+    Lens,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        let field = match name.to_lowercase().as_str() {
+            "width" => Field::Width,
+            "height" => Field::Height,
+            "size" | "filesize" => Field::Size,
+            "ratio" | "aspect" => Field::Ratio,
+            "type" => Field::Type,
+            "mime" => Field::Mime,
+            "tagcount" => Field::TagCount,
+            "source" => Field::Source,
+            "category" => Field::Category,
+            "camera" => Field::Camera,
+            "lens" => Field::Lens,
+            _ => return None,
+        };
+
+        Some(field)
+    }
+
+    fn to_expr(
+        self,
+        name: &str,
+        comparison: Comparison,
+        value: &str,
+    ) -> Result<SearchExpr, SearchParseErrorKind> {
+        let invalid = || SearchParseErrorKind::InvalidPredicateValue {
+            field: name.to_string(),
+            value: value.to_string(),
+        };
+        let numeric = |field, value| Predicate::Numeric {
+            field,
+            comparison,
+            value,
+        };
+
+        let predicate = match self {
+            Field::Width => numeric(
+                NumericField::Width,
+                value.parse::<u32>().map_err(|_| invalid())? as f64,
+            ),
+            Field::Height => numeric(
+                NumericField::Height,
+                value.parse::<u32>().map_err(|_| invalid())? as f64,
+            ),
+            Field::TagCount => numeric(
+                NumericField::TagCount,
+                value.parse::<u32>().map_err(|_| invalid())? as f64,
+            ),
+            Field::Size => numeric(
+                NumericField::FileSize,
+                parse_file_size(value).ok_or_else(invalid)?,
+            ),
+            Field::Ratio => match parse_orientation(value) {
+                Some(orientation) => {
+                    let expr = SearchExpr::Predicate(Predicate::Orientation(orientation));
+                    return equality(name, comparison, expr);
+                }
+                None => numeric(
+                    NumericField::AspectRatio,
+                    parse_ratio(value).ok_or_else(invalid)?,
+                ),
+            },
+            Field::Type => {
+                let media_type = parse_media_type(value).ok_or_else(invalid)?;
+                let expr =
+                    SearchExpr::Predicate(Predicate::MediaType(media_type_to_string(&media_type)));
+                return equality(name, comparison, expr);
+            }
+            Field::Mime => {
+                // `image/*` and `image/` are both prefixes
+                let mime = value.strip_suffix('*').unwrap_or(value).to_lowercase();
+                return equality(
+                    name,
+                    comparison,
+                    SearchExpr::Predicate(Predicate::Mime(mime)),
+                );
+            }
+            Field::Source => return equality(name, comparison, source_expr(value)),
+            Field::Category => {
+                let expr = SearchExpr::Predicate(Predicate::TagCategory(value.to_lowercase()));
+                return equality(name, comparison, expr);
+            }
+            Field::Camera => {
+                let expr = SearchExpr::Predicate(Predicate::Camera(value.to_lowercase()));
+                return equality(name, comparison, expr);
+            }
+            Field::Lens => {
+                let expr = SearchExpr::Predicate(Predicate::Lens(value.to_lowercase()));
+                return equality(name, comparison, expr);
+            }
+        };
+
+        Ok(SearchExpr::Predicate(predicate))
+    }
+}
+
+/// Predicates that can only be equal or not equal
+fn equality(
+    name: &str,
+    comparison: Comparison,
+    expr: SearchExpr,
+) -> Result<SearchExpr, SearchParseErrorKind> {
+    match comparison {
+        Comparison::Eq => Ok(expr),
+        Comparison::NotEq => Ok(SearchExpr::Not(Box::new(expr))),
+        _ => Err(SearchParseErrorKind::InvalidComparison(name.to_string())),
+    }
+}
+
+/// `ai` matches every AI source and `user`/`manual` matches tags added by hand, anything else is compared
+/// with `HashTagPair.source` as is
+fn source_expr(value: &str) -> SearchExpr {
+    let source =
+        |source: &str| SearchExpr::Predicate(Predicate::TagSource(Some(source.to_lowercase())));
+
+    match value.to_lowercase().as_str() {
+        "ai" => SearchExpr::or(vec![source(AI_TAGGER_SOURCE), source(AI_PROMPT_SOURCE)]),
+        "user" | "manual" => SearchExpr::Predicate(Predicate::TagSource(None)),
+        "gallery_dl" | "gallery-dl" | "gallerydl" => source(GALLERY_DL_SOURCE),
+        value => source(value),
+    }
+}
+
+fn parse_orientation(value: &str) -> Option<Orientation> {
+    let orientation = match value.to_lowercase().as_str() {
+        "portrait" | "tall" => Orientation::Portrait,
+        "landscape" | "wide" => Orientation::Landscape,
+        "square" => Orientation::Square,
+        _ => return None,
+    };
+
+    Some(orientation)
+}
+
+/// `1.5`, `16:9` or `16/9`
+fn parse_ratio(value: &str) -> Option<f64> {
+    let ratio = match value.split_once([':', '/']) {
+        Some((width, height)) => {
+            let height: f64 = height.parse().ok()?;
+            if height == 0.0 {
+                return None;
+            }
+            width.parse::<f64>().ok()? / height
+        }
+        None => value.parse().ok()?,
+    };
+
+    ratio.is_finite().then_some(ratio)
+}
+
+/// `1024`, `500KB`, `1.5mb`, units are powers of 1024 like the ones shown in the UI
+fn parse_file_size(value: &str) -> Option<f64> {
+    let unit_start = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(unit_start);
+
+    let multiplier: f64 = match unit.to_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "k" | "kb" | "kib" => 1024.0,
+        "m" | "mb" | "mib" => 1024.0 * 1024.0,
+        "g" | "gb" | "gib" => 1024.0 * 1024.0 * 1024.0,
+        "t" | "tb" | "tib" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+
+    let number: f64 = number.parse().ok()?;
+    Some(number * multiplier)
+}
+
+fn parse_media_type(value: &str) -> Option<MediaType> {
+    let media_type = match value.to_lowercase().as_str() {
+        "image" => MediaType::Image,
+        "video" => MediaType::Video,
+        "game" => MediaType::Game,
+        "group" => MediaType::Group,
+        "flash" | "swf" => MediaType::Flash,
+        "unknown" => MediaType::Unknown,
+        _ => return None,
+    };
+
+    Some(media_type)
+}
+
+fn parse_comparison(input: &str) -> PResult<'_, Comparison> {
+    alt((
+        value(Comparison::Ge, tag(">=")),
+        value(Comparison::Le, tag("<=")),
+        value(Comparison::NotEq, tag("!=")),
+        value(Comparison::Gt, tag(">")),
+        value(Comparison::Lt, tag("<")),
+        value(Comparison::Eq, tag("=")),
+        value(Comparison::Eq, tag(":")),
+    ))(input)
+}
+
+/// `field<comparison>value`, returns a recoverable error if there is no known field followed by a comparison
+fn parse_field_comparison(input: &str) -> PResult<'_, SearchExpr> {
+    let (rest, name) = alpha1(input)?;
+
+    let Some(field) = Field::from_name(name) else {
+        return Err(nom::Err::Error(RawError::new(
+            input,
+            name.len(),
+            SearchParseErrorKind::ExpectedExpression,
+        )));
+    };
+
+    let (rest, comparison) = parse_comparison(rest)?;
+
+    let Ok((after_value, value)) = take_while1::<_, &str, RawError>(is_word_char)(rest) else {
+        return failure(
+            rest,
+            0,
+            SearchParseErrorKind::InvalidPredicateValue {
+                field: name.to_string(),
+                value: String::new(),
+            },
+        );
+    };
+
+    match field.to_expr(name, comparison, value) {
+        Ok(expr) => Ok((after_value, expr)),
+        Err(kind) => failure(rest, value.len(), kind),
+    }
+}
+
+/// Returns a recoverable error if the input doesn't start with a predicate, so it can be parsed as a tag,
+/// anything invalid after the predicate keyword is a failure
+pub(super) fn parse_predicate(input: &str) -> PResult<'_, SearchExpr> {
+    if let Ok((rest, _)) = alt((keyword("imported"), keyword("added")))(input) {
+        let (rest, _) = multispace1(rest)?;
+        let (rest, range) = parse_time_range(rest)?;
+        return Ok((rest, SearchExpr::Predicate(Predicate::Imported(range))));
+    }
+
+    // `taken 2023` like `imported`, or `taken:2023` like the fields
+    if let Ok((rest, _)) = alt((
+        terminated(keyword("taken"), multispace1),
+        tag_no_case("taken:"),
+    ))(input)
+    {
+        let (rest, range) = parse_time_range(rest)?;
+        return Ok((rest, SearchExpr::Predicate(Predicate::Taken(range))));
+    }
+
+    if let Ok((rest, _)) = alt((
+        terminated(keyword("modified"), multispace1),
+        tag_no_case("modified:"),
+    ))(input)
+    {
+        let (rest, range) = parse_time_range(rest)?;
+        return Ok((rest, SearchExpr::Predicate(Predicate::Modified(range))));
+    }
+
+    parse_field_comparison(input)
+}
+
+#[test]
+fn test_parse_predicates() {
+    let (_, expr) = parse_predicate("width>=1920").unwrap();
+    assert_eq!(
+        expr,
+        SearchExpr::Predicate(Predicate::Numeric {
+            field: NumericField::Width,
+            comparison: Comparison::Ge,
+            value: 1920.0
+        })
+    );
+
+    let (_, expr) = parse_predicate("size<1.5MB").unwrap();
+    assert_eq!(
+        expr,
+        SearchExpr::Predicate(Predicate::Numeric {
+            field: NumericField::FileSize,
+            comparison: Comparison::Lt,
+            value: 1.5 * 1024.0 * 1024.0
+        })
+    );
+
+    let (_, expr) = parse_predicate("type!=Video").unwrap();
+    assert_eq!(
+        expr,
+        SearchExpr::Not(Box::new(SearchExpr::Predicate(Predicate::MediaType(
+            "Video".to_string()
+        ))))
+    );
+
+    let (_, expr) = parse_predicate("ratio:16:9").unwrap();
+    assert!(matches!(
+        expr,
+        SearchExpr::Predicate(Predicate::Numeric {
+            field: NumericField::AspectRatio,
+            comparison: Comparison::Eq,
+            ..
+        })
+    ));
+
+    let (_, expr) = parse_predicate("tagcount>20").unwrap();
+    assert_eq!(
+        expr,
+        SearchExpr::Predicate(Predicate::Numeric {
+            field: NumericField::TagCount,
+            comparison: Comparison::Gt,
+            value: 20.0
+        })
+    );
+
+    let (_, expr) = parse_predicate("source:ai").unwrap();
+    assert_eq!(
+        expr,
+        SearchExpr::Or(vec![
+            SearchExpr::Predicate(Predicate::TagSource(Some("ai tagger".to_string()))),
+            SearchExpr::Predicate(Predicate::TagSource(Some("ai_prompt_import".to_string()))),
+        ])
+    );
+
+    let (_, expr) = parse_predicate("camera:X100V").unwrap();
+    assert_eq!(
+        expr,
+        SearchExpr::Predicate(Predicate::Camera("x100v".to_string()))
+    );
+
+    let (_, expr) = parse_predicate("taken:2023").unwrap();
+    assert!(matches!(
+        expr,
+        SearchExpr::Predicate(Predicate::Taken(TimeRange {
+            start: Some(_),
+            end: Some(_)
+        }))
+    ));
+    let (_, expr) = parse_predicate("taken after 2020").unwrap();
+    assert!(matches!(
+        expr,
+        SearchExpr::Predicate(Predicate::Taken(TimeRange {
+            start: Some(_),
+            end: None
+        }))
+    ));
+    let (_, expr) = parse_predicate("modified:yesterday").unwrap();
+    assert!(matches!(
+        expr,
+        SearchExpr::Predicate(Predicate::Modified(TimeRange {
+            start: Some(_),
+            end: Some(_)
+        }))
+    ));
+
+    let (_, expr) = parse_predicate("category!=Artist").unwrap();
+    assert_eq!(
+        expr,
+        SearchExpr::Not(Box::new(SearchExpr::Predicate(Predicate::TagCategory(
+            "artist".to_string()
+        ))))
+    );
+
+    // not predicates, these are tags
+    assert!(matches!(
+        parse_predicate("artist:someone"),
+        Err(nom::Err::Error(_))
+    ));
+    assert!(matches!(parse_predicate("width"), Err(nom::Err::Error(_))));
+
+    assert!(matches!(
+        parse_predicate("ratio>portrait"),
+        Err(nom::Err::Failure(RawError {
+            kind: SearchParseErrorKind::InvalidComparison(_),
+            ..
+        }))
+    ));
+    assert!(matches!(
+        parse_predicate("size<2XB"),
+        Err(nom::Err::Failure(RawError {
+            kind: SearchParseErrorKind::InvalidPredicateValue { .. },
+            ..
+        }))
+    ));
+}
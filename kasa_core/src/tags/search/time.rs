@@ -0,0 +1,520 @@
+//! Time ranges for searching by import time
+//!
+//! ```text
+//! range  = "between" period "and" period
+//!        | "from" period ("to" | "until") period
+//!        | ("after" | "since" | "before") period
+//!        | "on"? period
+//! period = "now" | "today" | "yesterday"
+//!        | "this" calendar_unit | "last" calendar_unit
+//!        | ("last" | "past") number unit
+//!        | number unit "ago"
+//!        | YYYY | YYYY-MM | YYYY-MM-DD
+//! ```
+//!
+//! Relative times are kept relative and only resolved when the query is built, so a saved
+//! `imported this week` search keeps showing the current week.
+
+use chrono::{
+    DateTime, Datelike, Local, Months, NaiveDate, NaiveTime, TimeDelta, TimeZone, Weekday,
+};
+use nom::{
+    branch::alt,
+    bytes::complete::take_while_m_n,
+    character::complete::{alpha1, char, digit1, multispace1, satisfy},
+    combinator::{not, opt, peek},
+    sequence::preceded,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, Sqlite};
+
+use super::{
+    errors::SearchParseErrorKind,
+    parser::{PResult, RawError, expect, failure, is_word_char, keyword},
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, specta::Type, Serialize, Deserialize)]
+pub enum TimeUnit {
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, specta::Type, Serialize, Deserialize)]
+pub enum CalendarUnit {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, specta::Type, Serialize, Deserialize)]
+pub enum TimePoint {
+    Now,
+    /// Unix timestamp in milliseconds, same as `Media.time_added`
+    Absolute(i64),
+    Ago {
+        amount: u32,
+        unit: TimeUnit,
+    },
+    /// Start of the current calendar unit moved by `offset` units,
+    /// `StartOf { unit: Day, offset: -1 }` is the start of yesterday
+    StartOf {
+        unit: CalendarUnit,
+        offset: i32,
+    },
+}
+
+/// `start` is inclusive, `end` is exclusive, `None` means the range is unbounded on that side
+#[derive(Debug, PartialEq, Eq, Clone, specta::Type, Serialize, Deserialize)]
+pub struct TimeRange {
+    pub start: Option<TimePoint>,
+    pub end: Option<TimePoint>,
+}
+
+/// A period of time like `today` or `2024-05`, ranges are built from the start and end of periods
+#[derive(Debug, PartialEq, Clone)]
+struct Period {
+    start: TimePoint,
+    end: TimePoint,
+}
+
+impl Period {
+    fn new(start: TimePoint, end: TimePoint) -> Self {
+        Period { start, end }
+    }
+
+    fn calendar(unit: CalendarUnit, offset: i32) -> Self {
+        Period::new(
+            TimePoint::StartOf { unit, offset },
+            TimePoint::StartOf {
+                unit,
+                offset: offset + 1,
+            },
+        )
+    }
+
+    fn is_instant(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl TimeUnit {
+    fn subtract_from(&self, now: DateTime<Local>, amount: u32) -> Option<DateTime<Local>> {
+        let amount_i64 = amount as i64;
+
+        match self {
+            TimeUnit::Second => now.checked_sub_signed(TimeDelta::try_seconds(amount_i64)?),
+            TimeUnit::Minute => now.checked_sub_signed(TimeDelta::try_minutes(amount_i64)?),
+            TimeUnit::Hour => now.checked_sub_signed(TimeDelta::try_hours(amount_i64)?),
+            TimeUnit::Day => now.checked_sub_signed(TimeDelta::try_days(amount_i64)?),
+            TimeUnit::Week => now.checked_sub_signed(TimeDelta::try_weeks(amount_i64)?),
+            TimeUnit::Month => now.checked_sub_months(Months::new(amount)),
+            TimeUnit::Year => now.checked_sub_months(Months::new(amount.checked_mul(12)?)),
+        }
+    }
+}
+
+impl CalendarUnit {
+    fn start_of(&self, today: NaiveDate, offset: i32) -> Option<NaiveDate> {
+        let add_months = |date: NaiveDate, months: i32| {
+            if months >= 0 {
+                date.checked_add_months(Months::new(months as u32))
+            } else {
+                date.checked_sub_months(Months::new(months.unsigned_abs()))
+            }
+        };
+
+        match self {
+            CalendarUnit::Day => today.checked_add_signed(TimeDelta::try_days(offset as i64)?),
+            CalendarUnit::Week => {
+                let monday = today.week(Weekday::Mon).first_day();
+                monday.checked_add_signed(TimeDelta::try_weeks(offset as i64)?)
+            }
+            CalendarUnit::Month => add_months(today.with_day(1)?, offset),
+            CalendarUnit::Year => add_months(today.with_ordinal(1)?, offset.checked_mul(12)?),
+        }
+    }
+}
+
+impl TimePoint {
+    /// Unix timestamp in milliseconds, points that can't be represented are clamped to the distant past
+    pub fn resolve(&self, now: DateTime<Local>) -> i64 {
+        let resolved = match self {
+            TimePoint::Now => Some(now.timestamp_millis()),
+            TimePoint::Absolute(millis) => Some(*millis),
+            TimePoint::Ago { amount, unit } => unit
+                .subtract_from(now, *amount)
+                .map(|time| time.timestamp_millis()),
+            TimePoint::StartOf { unit, offset } => unit
+                .start_of(now.date_naive(), *offset)
+                .map(local_midnight_millis),
+        };
+
+        resolved.unwrap_or(i64::MIN)
+    }
+}
+
+impl TimeRange {
+    fn new(start: Option<TimePoint>, end: Option<TimePoint>) -> Self {
+        TimeRange { start, end }
+    }
+
+    fn between(start: Period, end: Period) -> Self {
+        TimeRange::new(Some(start.start), Some(end.end))
+    }
+
+    /// Pushes `column` being in the range as a condition
+    pub(super) fn push_sql(
+        &self,
+        column: &str,
+        query_builder: &mut QueryBuilder<Sqlite>,
+        now: DateTime<Local>,
+    ) {
+        match (&self.start, &self.end) {
+            (None, None) => {
+                query_builder.push("1 = 1");
+            }
+            (Some(start), None) => {
+                query_builder.push(format!("{column} >= "));
+                query_builder.push_bind(start.resolve(now));
+            }
+            (None, Some(end)) => {
+                query_builder.push(format!("{column} < "));
+                query_builder.push_bind(end.resolve(now));
+            }
+            (Some(start), Some(end)) => {
+                query_builder.push(format!("({column} >= "));
+                query_builder.push_bind(start.resolve(now));
+                query_builder.push(format!(" AND {column} < "));
+                query_builder.push_bind(end.resolve(now));
+                query_builder.push(")");
+            }
+        }
+    }
+}
+
+fn local_midnight_millis(date: NaiveDate) -> i64 {
+    let midnight = date.and_time(NaiveTime::MIN);
+
+    match Local.from_local_datetime(&midnight).earliest() {
+        Some(time) => time.timestamp_millis(),
+        // midnight might be skipped by DST changes in some timezones
+        None => midnight.and_utc().timestamp_millis(),
+    }
+}
+
+/// Parses the range after a time predicate keyword like `imported`
+pub(super) fn parse_time_range(input: &str) -> PResult<'_, TimeRange> {
+    expect(SearchParseErrorKind::ExpectedTime, parse_range)(input)
+}
+
+fn parse_range(input: &str) -> PResult<'_, TimeRange> {
+    if let Ok((rest, _)) = keyword_with_space("between")(input) {
+        let (rest, start) = parse_period(rest)?;
+        let (rest, _) = preceded(multispace1, keyword_with_space("and"))(rest)?;
+        let (rest, end) = parse_period(rest)?;
+        return Ok((rest, TimeRange::between(start, end)));
+    }
+
+    if let Ok((rest, _)) = keyword_with_space("from")(input) {
+        let (rest, start) = parse_period(rest)?;
+        let (rest, _) = preceded(
+            multispace1,
+            alt((keyword_with_space("to"), keyword_with_space("until"))),
+        )(rest)?;
+        let (rest, end) = parse_period(rest)?;
+        return Ok((rest, TimeRange::between(start, end)));
+    }
+
+    if let Ok((rest, _)) = keyword_with_space("after")(input) {
+        let (rest, period) = parse_period(rest)?;
+        return Ok((rest, TimeRange::new(Some(period.end), None)));
+    }
+
+    if let Ok((rest, _)) = keyword_with_space("since")(input) {
+        let (rest, period) = parse_period(rest)?;
+        return Ok((rest, TimeRange::new(Some(period.start), None)));
+    }
+
+    if let Ok((rest, _)) = keyword_with_space("before")(input) {
+        let (rest, period) = parse_period(rest)?;
+        return Ok((rest, TimeRange::new(None, Some(period.start))));
+    }
+
+    let (input, _) = opt(keyword_with_space("on"))(input)?;
+    let (rest, period) = parse_period(input)?;
+
+    // `imported 3 days ago` is everything since then
+    if period.is_instant() {
+        return Ok((rest, TimeRange::new(Some(period.start), None)));
+    }
+
+    Ok((rest, TimeRange::new(Some(period.start), Some(period.end))))
+}
+
+fn keyword_with_space<'a>(word: &'static str) -> impl FnMut(&'a str) -> PResult<'a, &'a str> {
+    move |input| {
+        let (rest, matched) = keyword(word)(input)?;
+        let (rest, _) = multispace1(rest)?;
+        Ok((rest, matched))
+    }
+}
+
+fn parse_period(input: &str) -> PResult<'_, Period> {
+    if let Ok((rest, _)) = keyword("now")(input) {
+        return Ok((rest, Period::new(TimePoint::Now, TimePoint::Now)));
+    }
+
+    if let Ok((rest, _)) = keyword("today")(input) {
+        return Ok((rest, Period::calendar(CalendarUnit::Day, 0)));
+    }
+
+    if let Ok((rest, _)) = keyword("yesterday")(input) {
+        return Ok((rest, Period::calendar(CalendarUnit::Day, -1)));
+    }
+
+    if let Ok((rest, _)) = keyword_with_space("this")(input) {
+        let (rest, unit) = parse_calendar_unit(rest)?;
+        return Ok((rest, Period::calendar(unit, 0)));
+    }
+
+    if let Ok((rest, _)) = alt((keyword_with_space("last"), keyword_with_space("past")))(input) {
+        // `last 3 days`
+        if let Ok((rest, (amount, unit))) = parse_amount(rest) {
+            let period = Period::new(TimePoint::Ago { amount, unit }, TimePoint::Now);
+            return Ok((rest, period));
+        }
+
+        // `last month`
+        let (rest, unit) = parse_calendar_unit(rest)?;
+        return Ok((rest, Period::calendar(unit, -1)));
+    }
+
+    // `3 days ago`
+    if let Ok((rest, (amount, unit))) = parse_amount(input) {
+        let (rest, _) = preceded(multispace1, keyword("ago"))(rest)?;
+        let point = TimePoint::Ago { amount, unit };
+        return Ok((rest, Period::new(point.clone(), point)));
+    }
+
+    parse_date(input)
+}
+
+/// `3 days`
+fn parse_amount(input: &str) -> PResult<'_, (u32, TimeUnit)> {
+    let (rest, digits) = digit1(input)?;
+    let (rest, _) = multispace1(rest)?;
+    let (rest, unit) = parse_time_unit(rest)?;
+
+    match digits.parse() {
+        Ok(amount) => Ok((rest, (amount, unit))),
+        Err(_) => failure(
+            input,
+            digits.len(),
+            SearchParseErrorKind::InvalidTime(digits.to_string()),
+        ),
+    }
+}
+
+fn parse_unit_word(input: &str) -> PResult<'_, String> {
+    let (rest, word) = alpha1(input)?;
+    let (rest, _) = peek(not(satisfy(is_word_char)))(rest)?;
+
+    let word = word.to_lowercase();
+    let singular = match word.strip_suffix('s') {
+        Some(singular) if !singular.is_empty() => singular.to_string(),
+        _ => word,
+    };
+
+    Ok((rest, singular))
+}
+
+fn parse_time_unit(input: &str) -> PResult<'_, TimeUnit> {
+    let (rest, word) = parse_unit_word(input)?;
+
+    let unit = match word.as_str() {
+        "second" | "sec" => TimeUnit::Second,
+        "minute" | "min" => TimeUnit::Minute,
+        "hour" => TimeUnit::Hour,
+        "day" => TimeUnit::Day,
+        "week" => TimeUnit::Week,
+        "month" => TimeUnit::Month,
+        "year" => TimeUnit::Year,
+        _ => {
+            return Err(nom::Err::Error(RawError::new(
+                input,
+                word.len(),
+                SearchParseErrorKind::ExpectedTime,
+            )));
+        }
+    };
+
+    Ok((rest, unit))
+}
+
+fn parse_calendar_unit(input: &str) -> PResult<'_, CalendarUnit> {
+    let (rest, word) = parse_unit_word(input)?;
+
+    let unit = match word.as_str() {
+        "day" => CalendarUnit::Day,
+        "week" => CalendarUnit::Week,
+        "month" => CalendarUnit::Month,
+        "year" => CalendarUnit::Year,
+        _ => {
+            return Err(nom::Err::Error(RawError::new(
+                input,
+                word.len(),
+                SearchParseErrorKind::ExpectedTime,
+            )));
+        }
+    };
+
+    Ok((rest, unit))
+}
+
+fn is_digit(c: char) -> bool {
+    c.is_ascii_digit()
+}
+
+/// `2024`, `2024-05` or `2024-05-13`, in local time
+fn parse_date(input: &str) -> PResult<'_, Period> {
+    let (rest, year) = take_while_m_n(4, 4, is_digit)(input)?;
+    let (rest, month) = opt(preceded(char('-'), take_while_m_n(1, 2, is_digit)))(rest)?;
+    let (rest, day) = match month {
+        Some(_) => opt(preceded(char('-'), take_while_m_n(1, 2, is_digit)))(rest)?,
+        None => (rest, None),
+    };
+    let (rest, _) = peek(not(satisfy(is_word_char)))(rest)?;
+
+    let raw = &input[..input.len() - rest.len()];
+    let invalid = || {
+        failure(
+            input,
+            raw.len(),
+            SearchParseErrorKind::InvalidTime(raw.to_string()),
+        )
+    };
+
+    // these are all digits, parsing can't fail
+    let year: i32 = year.parse().unwrap();
+    let month: Option<u32> = month.map(|m| m.parse().unwrap());
+    let day: Option<u32> = day.map(|d| d.parse().unwrap());
+
+    let Some(start) = NaiveDate::from_ymd_opt(year, month.unwrap_or(1), day.unwrap_or(1)) else {
+        return invalid();
+    };
+
+    let end = match (month, day) {
+        (Some(_), Some(_)) => start.succ_opt(),
+        (Some(_), None) => start.checked_add_months(Months::new(1)),
+        _ => start.checked_add_months(Months::new(12)),
+    };
+
+    let Some(end) = end else {
+        return invalid();
+    };
+
+    let period = Period::new(
+        TimePoint::Absolute(local_midnight_millis(start)),
+        TimePoint::Absolute(local_midnight_millis(end)),
+    );
+
+    Ok((rest, period))
+}
+
+#[test]
+fn test_parse_time_range() {
+    let (_, range) = parse_time_range("between 3 days ago and now").unwrap();
+    assert_eq!(
+        range,
+        TimeRange::new(
+            Some(TimePoint::Ago {
+                amount: 3,
+                unit: TimeUnit::Day
+            }),
+            Some(TimePoint::Now)
+        )
+    );
+
+    let (_, range) = parse_time_range("yesterday").unwrap();
+    assert_eq!(
+        range,
+        TimeRange::new(
+            Some(TimePoint::StartOf {
+                unit: CalendarUnit::Day,
+                offset: -1
+            }),
+            Some(TimePoint::StartOf {
+                unit: CalendarUnit::Day,
+                offset: 0
+            })
+        )
+    );
+
+    let (_, range) = parse_time_range("after 2024-02").unwrap();
+    let march = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+    assert_eq!(
+        range,
+        TimeRange::new(
+            Some(TimePoint::Absolute(local_midnight_millis(march))),
+            None
+        )
+    );
+
+    let err = parse_time_range("2024-02-30").unwrap_err();
+    assert!(matches!(
+        err,
+        nom::Err::Failure(RawError {
+            kind: SearchParseErrorKind::InvalidTime(_),
+            ..
+        })
+    ));
+
+    let err = parse_time_range("last fortnight").unwrap_err();
+    assert!(matches!(
+        err,
+        nom::Err::Failure(RawError {
+            kind: SearchParseErrorKind::ExpectedTime,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_resolve_time_point() {
+    // Wednesday
+    let now = Local.with_ymd_and_hms(2024, 5, 15, 12, 30, 0).unwrap();
+    let midnight = |y, m, d| local_midnight_millis(NaiveDate::from_ymd_opt(y, m, d).unwrap());
+
+    let start_of_week = TimePoint::StartOf {
+        unit: CalendarUnit::Week,
+        offset: 0,
+    };
+    assert_eq!(start_of_week.resolve(now), midnight(2024, 5, 13));
+
+    let start_of_last_month = TimePoint::StartOf {
+        unit: CalendarUnit::Month,
+        offset: -1,
+    };
+    assert_eq!(start_of_last_month.resolve(now), midnight(2024, 4, 1));
+
+    let next_year = TimePoint::StartOf {
+        unit: CalendarUnit::Year,
+        offset: 1,
+    };
+    assert_eq!(next_year.resolve(now), midnight(2025, 1, 1));
+
+    let two_hours_ago = TimePoint::Ago {
+        amount: 2,
+        unit: TimeUnit::Hour,
+    };
+    assert_eq!(
+        two_hours_ago.resolve(now),
+        now.timestamp_millis() - 2 * 60 * 60 * 1000
+    );
+}
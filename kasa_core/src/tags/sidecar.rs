@@ -0,0 +1,484 @@
+//! Exporting and importing the tags of media as sidecar files next to the media files
+//!
+//! Sidecars are written next to every path of the media:
+//! - `Txt`, `image.txt` with comma separated tags, the caption files used by training tools
+//! - `Json`, `image.png.json` like the metadata files of gallery-dl, with every tag in `tags` and the tags of
+//!   each source type in `tags_<source type>`
+//! - `Xmp`, `image.png.xmp` with the tags as `dc:subject` keywords
+//!
+//! Implied tags aren't exported, the implications of the importing database add them back.
+
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use indexmap::IndexMap;
+use kasa_python::ExtractedTag;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::{Pool, Sqlite, query_as, query_scalar};
+
+use super::{
+    IMPLICATION_SOURCE, insert_tags, insert_tags_with_source_types, parse_tags,
+    xmp::{parse_xmp_subjects, xmp_packet},
+};
+
+/// `HashTagPair.source` of tags imported from sidecar files
+pub const SIDECAR_SOURCE: &str = "sidecar";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, specta::Type)]
+pub enum SidecarFormat {
+    Txt,
+    Json,
+    Xmp,
+}
+
+impl SidecarFormat {
+    /// Path of the sidecar of the media file
+    pub fn sidecar_path(&self, media_path: &Path) -> PathBuf {
+        match self {
+            SidecarFormat::Txt => media_path.with_extension("txt"),
+            SidecarFormat::Json => append_extension(media_path, "json"),
+            SidecarFormat::Xmp => append_extension(media_path, "xmp"),
+        }
+    }
+}
+
+pub(super) fn append_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    path.into()
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub struct SidecarReport {
+    /// Sidecars that were written or imported
+    pub done: Vec<String>,
+    /// Media without a path, or without a sidecar when importing
+    pub missing: Vec<String>,
+    /// Sidecars that couldn't be read or written, with the error
+    pub failed: Vec<(String, String)>,
+}
+
+/// Tag names and their source types, in the order they were added
+type SidecarTags = Vec<(String, Option<String>)>;
+
+/// Claims the sidecar for the media, fails with the media that claimed it first if it was another one
+///
+/// `Txt` sidecars replace the extension, so `image.png` and `image.jpg` share `image.txt`.
+fn claim_sidecar(
+    claimed: &mut HashMap<PathBuf, String>,
+    sidecar_path: &Path,
+    hash: &str,
+) -> Result<(), String> {
+    match claimed.entry(sidecar_path.to_path_buf()) {
+        Entry::Occupied(owner) if owner.get() != hash => Err(format!(
+            "Skipped, it is also the sidecar of media {}",
+            owner.get()
+        )),
+        Entry::Occupied(_) => Ok(()),
+        Entry::Vacant(entry) => {
+            entry.insert(hash.to_string());
+            Ok(())
+        }
+    }
+}
+
+pub(super) async fn get_media_paths(hash: &str, pool: &Pool<Sqlite>) -> sqlx::Result<Vec<String>> {
+    query_scalar("SELECT path FROM Path WHERE hash = ?")
+        .bind(hash)
+        .fetch_all(pool)
+        .await
+}
+
+fn to_txt(tags: &SidecarTags) -> String {
+    tags.iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn to_json(tags: &SidecarTags) -> String {
+    let mut typed: IndexMap<&str, Vec<&str>> = IndexMap::new();
+    for (name, source_type) in tags {
+        if let Some(source_type) = source_type {
+            typed.entry(source_type).or_default().push(name);
+        }
+    }
+
+    let mut json = Map::new();
+    json.insert(
+        "tags".to_string(),
+        tags.iter().map(|(name, _)| name.as_str()).collect(),
+    );
+    for (source_type, names) in typed {
+        json.insert(format!("tags_{source_type}"), names.into());
+    }
+
+    serde_json::to_string_pretty(&json).unwrap()
+}
+
+/// Tags of a json value, gallery-dl uses both arrays and space separated strings
+fn json_tag_list(value: &Value) -> Vec<String> {
+    match value {
+        Value::Array(items) => items
+            .iter()
+            .filter_map(|item| item.as_str())
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect(),
+        Value::String(tags) => tags.split_whitespace().map(str::to_string).collect(),
+        _ => vec![],
+    }
+}
+
+fn from_json(text: &str) -> serde_json::Result<SidecarTags> {
+    let json: Map<String, Value> = serde_json::from_str(text)?;
+
+    let mut tags: IndexMap<String, Option<String>> = IndexMap::new();
+    if let Some(value) = json.get("tags") {
+        for tag in json_tag_list(value) {
+            tags.insert(tag, None);
+        }
+    }
+    for (key, value) in &json {
+        let Some(source_type) = key.strip_prefix("tags_") else {
+            continue;
+        };
+        for tag in json_tag_list(value) {
+            tags.insert(tag, Some(source_type.to_string()));
+        }
+    }
+
+    Ok(tags.into_iter().collect())
+}
+
+fn serialize(format: SidecarFormat, tags: &SidecarTags) -> String {
+    match format {
+        SidecarFormat::Txt => to_txt(tags),
+        SidecarFormat::Json => to_json(tags),
+        SidecarFormat::Xmp => {
+            let names: Vec<String> = tags.iter().map(|(name, _)| name.clone()).collect();
+            xmp_packet(&names)
+        }
+    }
+}
+
+fn deserialize(format: SidecarFormat, text: &str) -> Result<SidecarTags, String> {
+    match format {
+        SidecarFormat::Txt => Ok(parse_tags(text)
+            .into_iter()
+            .map(|tag| (tag.to_string(), None))
+            .collect()),
+        SidecarFormat::Json => from_json(text).map_err(|e| e.to_string()),
+        SidecarFormat::Xmp => Ok(parse_xmp_subjects(text)
+            .into_iter()
+            .map(|tag| (tag, None))
+            .collect()),
+    }
+}
+
+/// Writes the tags of every media as a sidecar next to each of its paths, existing sidecars are replaced
+///
+/// A sidecar shared by several media is only written for the first one, the others are reported as failed.
+pub async fn export_sidecars_impl(
+    hashes: Vec<String>,
+    format: SidecarFormat,
+    pool: &Pool<Sqlite>,
+) -> sqlx::Result<SidecarReport> {
+    let mut report = SidecarReport::default();
+    let mut claimed = HashMap::new();
+
+    for hash in hashes {
+        let paths = get_media_paths(&hash, pool).await?;
+        if paths.is_empty() {
+            report.missing.push(hash);
+            continue;
+        }
+
+        let tags: SidecarTags = query_as(
+            "SELECT tag_name, source_type FROM HashTagPair WHERE hash = ? AND source IS NOT ? \
+            ORDER BY rowid",
+        )
+        .bind(&hash)
+        .bind(IMPLICATION_SOURCE)
+        .fetch_all(pool)
+        .await?;
+        let text = serialize(format, &tags);
+
+        for path in paths {
+            let sidecar_path = format.sidecar_path(Path::new(&path));
+            let sidecar_path_str = sidecar_path.to_string_lossy().to_string();
+
+            if let Err(e) = claim_sidecar(&mut claimed, &sidecar_path, &hash) {
+                report.failed.push((sidecar_path_str, e));
+                continue;
+            }
+
+            match fs::write(&sidecar_path, &text) {
+                Ok(()) => report.done.push(sidecar_path_str),
+                Err(e) => report.failed.push((sidecar_path_str, e.to_string())),
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Adds the tags in the sidecars next to the paths of every media, with `SIDECAR_SOURCE` as their source
+///
+/// Tags the media already has are kept as they are. A sidecar shared by several media is only imported for the
+/// first one, the others are reported as failed.
+pub async fn import_sidecars_impl(
+    hashes: Vec<String>,
+    format: SidecarFormat,
+    pool: &Pool<Sqlite>,
+) -> sqlx::Result<SidecarReport> {
+    let mut report = SidecarReport::default();
+    let mut claimed = HashMap::new();
+
+    for hash in hashes {
+        let mut tags: IndexMap<String, Option<String>> = IndexMap::new();
+        let mut found = false;
+
+        for path in get_media_paths(&hash, pool).await? {
+            let sidecar_path = format.sidecar_path(Path::new(&path));
+            if !sidecar_path.exists() {
+                continue;
+            }
+            found = true;
+            let sidecar_path_str = sidecar_path.to_string_lossy().to_string();
+
+            if let Err(e) = claim_sidecar(&mut claimed, &sidecar_path, &hash) {
+                report.failed.push((sidecar_path_str, e));
+                continue;
+            }
+
+            let sidecar_tags = fs::read_to_string(&sidecar_path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| deserialize(format, &text));
+
+            match sidecar_tags {
+                Ok(sidecar_tags) => {
+                    tags.extend(sidecar_tags);
+                    report.done.push(sidecar_path_str);
+                }
+                Err(e) => report.failed.push((sidecar_path_str, e)),
+            }
+        }
+
+        if !found {
+            report.missing.push(hash);
+            continue;
+        }
+
+        let (typed, untyped): (Vec<_>, Vec<_>) = tags
+            .into_iter()
+            .partition(|(_, source_type)| source_type.is_some());
+
+        if !untyped.is_empty() {
+            let names = untyped.into_iter().map(|(name, _)| name).collect();
+            insert_tags(
+                names,
+                pool,
+                Some(hash.clone()),
+                Some(SIDECAR_SOURCE.to_string()),
+            )
+            .await;
+        }
+
+        if !typed.is_empty() {
+            let typed = typed
+                .into_iter()
+                .map(|(name, source_type)| ExtractedTag {
+                    _type: source_type.unwrap(),
+                    name,
+                })
+                .collect();
+            insert_tags_with_source_types(
+                typed,
+                pool,
+                Some(hash),
+                Some(SIDECAR_SOURCE.to_string()),
+            )
+            .await;
+        }
+    }
+
+    Ok(report)
+}
+
+#[test]
+fn test_sidecar_formats() {
+    let tags: SidecarTags = vec![
+        ("alice".to_string(), Some("character".to_string())),
+        ("long_hair".to_string(), None),
+        ("smile".to_string(), Some("general".to_string())),
+    ];
+    let untyped: SidecarTags = tags.iter().map(|(name, _)| (name.clone(), None)).collect();
+
+    assert_eq!(to_txt(&tags), "alice, long_hair, smile");
+
+    for format in [SidecarFormat::Txt, SidecarFormat::Xmp] {
+        assert_eq!(
+            deserialize(format, &serialize(format, &tags)).unwrap(),
+            untyped
+        );
+    }
+    assert_eq!(
+        deserialize(SidecarFormat::Json, &serialize(SidecarFormat::Json, &tags)).unwrap(),
+        tags
+    );
+
+    // metadata written by gallery-dl
+    let gallery_dl = r#"{"id": 1, "tags": "long_hair smile", "tags_artist": "bob"}"#;
+    assert_eq!(
+        from_json(gallery_dl).unwrap(),
+        vec![
+            ("long_hair".to_string(), None),
+            ("smile".to_string(), None),
+            ("bob".to_string(), Some("artist".to_string())),
+        ]
+    );
+
+    let media = Path::new("/media/image.png");
+    assert_eq!(
+        SidecarFormat::Txt.sidecar_path(media),
+        Path::new("/media/image.txt")
+    );
+    assert_eq!(
+        SidecarFormat::Json.sidecar_path(media),
+        Path::new("/media/image.png.json")
+    );
+}
+
+#[sqlx::test]
+async fn test_sidecar_round_trip(pool: Pool<Sqlite>) {
+    use sqlx::migrate;
+
+    use super::implications::add_implication_impl;
+    use crate::test_util::db_utils::{insert_image, insert_path_row};
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    for hash in ["1", "2"] {
+        insert_image(&pool, hash).await;
+
+        let path = dir.path().join(format!("{hash}.png"));
+        insert_path_row(&pool, hash, &path.to_string_lossy(), "").await;
+    }
+
+    insert_tags_with_source_types(
+        vec![
+            ExtractedTag::new("character", "alice"),
+            ExtractedTag::new("general", "cat_ears"),
+        ],
+        &pool,
+        Some("1".to_string()),
+        None,
+    )
+    .await;
+    add_implication_impl("cat_ears", "animal_ears", &pool)
+        .await
+        .unwrap();
+
+    let hashes = vec!["1".to_string(), "2".to_string(), "3".to_string()];
+
+    for format in [SidecarFormat::Txt, SidecarFormat::Json, SidecarFormat::Xmp] {
+        let report = export_sidecars_impl(hashes.clone(), format, &pool)
+            .await
+            .unwrap();
+        assert_eq!(report.done.len(), 2);
+        assert_eq!(report.missing, vec!["3"]);
+    }
+
+    let txt = fs::read_to_string(dir.path().join("1.txt")).unwrap();
+    assert_eq!(txt, "alice, cat_ears");
+
+    // the media without tags gets the tags of the sidecar of the other one
+    fs::copy(dir.path().join("1.png.json"), dir.path().join("2.png.json")).unwrap();
+    let report = import_sidecars_impl(hashes, SidecarFormat::Json, &pool)
+        .await
+        .unwrap();
+    assert_eq!(report.missing, vec!["3"]);
+    assert!(report.failed.is_empty());
+
+    let imported: HashMap<String, (Option<String>, Option<String>)> =
+        query_as("SELECT tag_name, source, source_type FROM HashTagPair WHERE hash = '2'")
+            .fetch_all(&pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(name, source, source_type)| (name, (source, source_type)))
+            .collect();
+    let sidecar = |source_type: &str| {
+        (
+            Some(SIDECAR_SOURCE.to_string()),
+            Some(source_type.to_string()),
+        )
+    };
+    assert_eq!(
+        imported,
+        HashMap::from([
+            ("alice".to_string(), sidecar("character")),
+            ("cat_ears".to_string(), sidecar("general")),
+            (
+                "animal_ears".to_string(),
+                (Some(IMPLICATION_SOURCE.to_string()), None)
+            ),
+        ])
+    );
+
+    // tags of the media exporting are unchanged
+    let original_sources: Vec<Option<String>> =
+        query_scalar("SELECT source FROM HashTagPair WHERE hash = '1' AND tag_name = 'alice'")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(original_sources, vec![None]);
+}
+
+#[sqlx::test]
+async fn test_sidecar_collisions(pool: Pool<Sqlite>) {
+    use sqlx::migrate;
+
+    use crate::test_util::db_utils::{insert_image, insert_path_row};
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    for (hash, name, tag) in [("1", "image.png", "cat"), ("2", "image.jpg", "dog")] {
+        insert_image(&pool, hash).await;
+        let path = dir.path().join(name);
+        insert_path_row(&pool, hash, &path.to_string_lossy(), "").await;
+        insert_tags(vec![tag.to_string()], &pool, Some(hash.to_string()), None).await;
+    }
+
+    let hashes = vec!["1".to_string(), "2".to_string()];
+    let sidecar = dir.path().join("image.txt").to_string_lossy().to_string();
+
+    // `image.txt` keeps the tags of the first media instead of the last one written
+    let report = export_sidecars_impl(hashes.clone(), SidecarFormat::Txt, &pool)
+        .await
+        .unwrap();
+    assert_eq!(report.done, vec![sidecar.clone()]);
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].0, sidecar);
+    assert_eq!(fs::read_to_string(&sidecar).unwrap(), "cat");
+
+    let report = import_sidecars_impl(hashes, SidecarFormat::Txt, &pool)
+        .await
+        .unwrap();
+    assert_eq!(report.done, vec![sidecar.clone()]);
+    assert_eq!(report.failed.len(), 1);
+
+    let tags: Vec<String> = query_scalar("SELECT tag_name FROM HashTagPair WHERE hash = '2'")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(tags, vec!["dog"]);
+}
@@ -0,0 +1,674 @@
+//! Writing the tags of media back into the files, so they aren't lost with the database
+//!
+//! The tags are written as XMP `dc:subject` keywords, either to an `image.png.xmp` sidecar next to every path
+//! or embedded in the file itself for JPEG, PNG and WebP. Other XMP properties already in the sidecar or the
+//! file are kept. Implied tags aren't written, like with exported sidecars.
+//!
+//! Embedding changes the contents of the file and so its hash, the media is moved to the new hash once all of
+//! its paths are written. The paths of a media are written all or none, if one of them fails the others keep or
+//! get back their original contents.
+
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result, bail};
+use img_parts::{
+    Bytes,
+    jpeg::{Jpeg, JpegSegment, markers},
+    png::{Png, PngChunk},
+    riff::{RiffChunk, RiffContent},
+    vp8::VP8Kind,
+    webp::{CHUNK_ALPH, CHUNK_VP8L, CHUNK_VP8X, CHUNK_XMP, WebP},
+};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, QueryBuilder, Sqlite, query, query_scalar};
+
+use super::{
+    IMPLICATION_SOURCE,
+    sidecar::{SidecarFormat, append_extension, get_media_paths},
+    xmp::set_xmp_subjects,
+};
+use crate::xxhash::xxhash;
+
+/// Tables with rows of a media, keyed by its hash
+const MEDIA_HASH_TABLES: [&str; 10] = [
+    "Media",
+    "Image",
+    "Exif",
+    "Path",
+    "HashTagPair",
+    "RawTagsField",
+    "MediaGroupEntry",
+    "MediaSource",
+    "MediaView",
+    "TagHistory",
+];
+
+/// Prefix of the APP1 segment with the XMP packet of JPEGs
+const JPEG_XMP_NAMESPACE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// Largest contents of a JPEG segment, its length is an u16 that includes itself
+const JPEG_MAX_SEGMENT: usize = 65533;
+
+const PNG_ITXT: [u8; 4] = *b"iTXt";
+/// Keyword of the `iTXt` chunk with the XMP packet of PNGs
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+
+const WEBP_XMP_FLAG: u8 = 0b0000_0100;
+const WEBP_ALPHA_FLAG: u8 = 0b0001_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, specta::Type)]
+pub enum TagWriteTarget {
+    /// `image.png.xmp` next to the file
+    Sidecar,
+    /// In the file itself
+    Embedded,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub struct TagFileWrite {
+    pub path: String,
+    pub keywords: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub struct TagWriteReport {
+    /// Files that were written, or would be written with a dry run
+    pub written: Vec<TagFileWrite>,
+    /// Media without a path
+    pub missing: Vec<String>,
+    /// Files that couldn't be written, with the error
+    pub failed: Vec<(String, String)>,
+}
+
+fn jpeg_xmp(segment: &JpegSegment) -> Option<&[u8]> {
+    if segment.marker() != markers::APP1 {
+        return None;
+    }
+
+    segment.contents().strip_prefix(JPEG_XMP_NAMESPACE)
+}
+
+fn embed_jpeg(bytes: Bytes, keywords: &[String]) -> Result<Bytes> {
+    let mut jpeg = Jpeg::from_bytes(bytes)?;
+
+    let existing = jpeg
+        .segments()
+        .iter()
+        .find_map(jpeg_xmp)
+        .map(|xmp| String::from_utf8_lossy(xmp).to_string());
+    let xmp = set_xmp_subjects(existing.as_deref(), keywords);
+
+    let mut contents = JPEG_XMP_NAMESPACE.to_vec();
+    contents.extend(xmp.as_bytes());
+    if contents.len() > JPEG_MAX_SEGMENT {
+        bail!("The keywords don't fit in the XMP segment of a JPEG");
+    }
+
+    let segments = jpeg.segments_mut();
+    segments.retain(|segment| jpeg_xmp(segment).is_none());
+    // after the JFIF and EXIF segments
+    let position = segments
+        .iter()
+        .position(|segment| !matches!(segment.marker(), markers::APP0 | markers::APP1))
+        .unwrap_or(segments.len());
+    segments.insert(
+        position,
+        JpegSegment::new_with_contents(markers::APP1, contents.into()),
+    );
+
+    Ok(jpeg.encoder().bytes())
+}
+
+/// The packet of an `iTXt` chunk with the XMP keyword, `None` for other chunks and compressed packets
+fn png_xmp(chunk: &PngChunk) -> Option<&[u8]> {
+    if chunk.kind() != PNG_ITXT {
+        return None;
+    }
+
+    // keyword\0 compression_flag compression_method language\0 translated_keyword\0 text
+    let rest = chunk.contents().strip_prefix(PNG_XMP_KEYWORD)?;
+    let [0, 0, _, rest @ ..] = rest else {
+        return None;
+    };
+    let (_, rest) = rest.split_at(rest.iter().position(|b| *b == 0)? + 1);
+    let (_, text) = rest.split_at(rest.iter().position(|b| *b == 0)? + 1);
+
+    Some(text)
+}
+
+fn is_png_xmp(chunk: &PngChunk) -> bool {
+    chunk.kind() == PNG_ITXT
+        && chunk
+            .contents()
+            .starts_with(&[PNG_XMP_KEYWORD, b"\0"].concat())
+}
+
+fn embed_png(bytes: Bytes, keywords: &[String]) -> Result<Bytes> {
+    let mut png = Png::from_bytes(bytes)?;
+
+    let existing = png
+        .chunks()
+        .iter()
+        .find_map(png_xmp)
+        .map(|xmp| String::from_utf8_lossy(xmp).to_string());
+    let xmp = set_xmp_subjects(existing.as_deref(), keywords);
+
+    let mut contents = PNG_XMP_KEYWORD.to_vec();
+    // uncompressed, without a language or a translated keyword
+    contents.extend([0, 0, 0, 0, 0]);
+    contents.extend(xmp.as_bytes());
+
+    let chunks = png.chunks_mut();
+    chunks.retain(|chunk| !is_png_xmp(chunk));
+    // right after IHDR
+    chunks.insert(
+        1.min(chunks.len()),
+        PngChunk::new(PNG_ITXT, contents.into()),
+    );
+
+    Ok(png.encoder().bytes())
+}
+
+fn embed_webp(bytes: Bytes, keywords: &[String]) -> Result<Bytes> {
+    let mut webp = WebP::from_bytes(bytes)?;
+
+    let existing = webp
+        .chunk_by_id(CHUNK_XMP)
+        .and_then(|chunk| chunk.content().data())
+        .map(|xmp| String::from_utf8_lossy(xmp).to_string());
+    let xmp = set_xmp_subjects(existing.as_deref(), keywords);
+
+    // metadata needs the extended format, simple files only have the bitstream
+    if webp.kind() != VP8Kind::VP8X {
+        let (width, height) = webp
+            .dimensions()
+            .context("Couldn't read the dimensions of the WebP")?;
+
+        let lossless_alpha = webp
+            .chunk_by_id(CHUNK_VP8L)
+            .and_then(|chunk| chunk.content().data())
+            .and_then(|data| data.get(1..5))
+            .is_some_and(|header| u32::from_le_bytes(header.try_into().unwrap()) & (1 << 28) != 0);
+        let flags = if lossless_alpha || webp.has_chunk(CHUNK_ALPH) {
+            WEBP_ALPHA_FLAG
+        } else {
+            0
+        };
+
+        let mut contents = vec![flags, 0, 0, 0];
+        contents.extend(&(width - 1).to_le_bytes()[..3]);
+        contents.extend(&(height - 1).to_le_bytes()[..3]);
+        webp.chunks_mut().insert(
+            0,
+            RiffChunk::new(CHUNK_VP8X, RiffContent::Data(contents.into())),
+        );
+    }
+
+    if let Some(vp8x) = webp
+        .chunks_mut()
+        .iter_mut()
+        .find(|chunk| chunk.id() == CHUNK_VP8X)
+        && let RiffContent::Data(contents) = vp8x.content_mut()
+        && !contents.is_empty()
+    {
+        let mut flagged = contents.to_vec();
+        flagged[0] |= WEBP_XMP_FLAG;
+        *contents = flagged.into();
+    }
+
+    webp.remove_chunks_by_id(CHUNK_XMP);
+    webp.chunks_mut().push(RiffChunk::new(
+        CHUNK_XMP,
+        RiffContent::Data(xmp.into_bytes().into()),
+    ));
+
+    Ok(webp.encoder().bytes())
+}
+
+/// Contents of the file with the keywords embedded
+fn embed_keywords(bytes: Vec<u8>, mime: &str, keywords: &[String]) -> Result<Bytes> {
+    match mime {
+        "image/jpeg" => embed_jpeg(bytes.into(), keywords),
+        "image/png" => embed_png(bytes.into(), keywords),
+        "image/webp" => embed_webp(bytes.into(), keywords),
+        _ => bail!("Writing tags into {mime} files isn't supported"),
+    }
+}
+
+/// Writes to a temporary file first, so the file isn't left half written
+fn write_file(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let temp_path = append_extension(path, "kasa_tmp");
+    fs::write(&temp_path, contents)?;
+    fs::rename(&temp_path, path)
+}
+
+/// Moves every row of the media to the hash of its rewritten files
+async fn move_media(
+    hash: &str,
+    new_hash: &str,
+    filesize: usize,
+    pool: &Pool<Sqlite>,
+    pool_thumbs: &Pool<Sqlite>,
+) -> sqlx::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    for table in MEDIA_HASH_TABLES {
+        let mut query_builder: QueryBuilder<Sqlite> =
+            QueryBuilder::new(format!("UPDATE {table} SET hash = "));
+        query_builder.push_bind(new_hash);
+        query_builder.push(" WHERE hash = ");
+        query_builder.push_bind(hash);
+
+        query_builder.build().execute(&mut *tx).await?;
+    }
+
+    query("UPDATE Media SET filesize = ? WHERE hash = ?")
+        .bind(filesize as i64)
+        .bind(new_hash)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    query("UPDATE Thumbs SET hash = ? WHERE hash = ?")
+        .bind(new_hash)
+        .bind(hash)
+        .execute(pool_thumbs)
+        .await?;
+
+    Ok(())
+}
+
+/// Embeds the keywords in every path of the media and moves the media to the hash of the new contents
+///
+/// The paths are written all or none, as the media can't be split between two hashes. Every file is embedded
+/// before any of them is written, and the written ones get their original contents back if a later one fails.
+async fn embed_media_keywords(
+    hash: &str,
+    paths: Vec<String>,
+    keywords: &[String],
+    dry_run: bool,
+    report: &mut TagWriteReport,
+    pool: &Pool<Sqlite>,
+    pool_thumbs: &Pool<Sqlite>,
+) -> sqlx::Result<()> {
+    let mime: Option<String> = query_scalar("SELECT mime FROM Media WHERE hash = ?")
+        .bind(hash)
+        .fetch_one(pool)
+        .await?;
+    let mime = mime.unwrap_or_default();
+
+    // (path, original contents, new contents), every path has the same contents so they end up with the same hash
+    let mut rewrites = vec![];
+    let mut failed = vec![];
+
+    for path in paths {
+        let rewrite = fs::read(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| {
+                let contents = embed_keywords(bytes.clone(), &mime, keywords)?;
+                Ok((bytes, contents))
+            });
+
+        match rewrite {
+            Ok((original, contents)) => rewrites.push((path, original, contents)),
+            Err(e) => failed.push((path, e.to_string())),
+        }
+    }
+
+    let Some((_, _, contents)) = rewrites.first() else {
+        report.failed.extend(failed);
+        return Ok(());
+    };
+    let new_hash = xxhash(contents).to_string();
+    let filesize = contents.len();
+
+    if failed.is_empty()
+        && new_hash != hash
+        && query_scalar::<_, i64>("SELECT COUNT(*) FROM Media WHERE hash = ?")
+            .bind(&new_hash)
+            .fetch_one(pool)
+            .await?
+            > 0
+    {
+        failed.extend(rewrites.drain(..).map(|(path, _, _)| {
+            (
+                path,
+                "A file with the same contents is already indexed".to_string(),
+            )
+        }));
+    }
+
+    if !dry_run && failed.is_empty() {
+        let mut written: Vec<(&str, &[u8])> = vec![];
+
+        for (path, original, contents) in &rewrites {
+            match write_file(Path::new(path), contents) {
+                Ok(()) => written.push((path, original)),
+                Err(e) => {
+                    failed.push((path.clone(), e.to_string()));
+                    break;
+                }
+            }
+        }
+
+        if !failed.is_empty() {
+            for (path, original) in written {
+                if let Err(e) = write_file(Path::new(path), original) {
+                    failed.push((
+                        path.to_string(),
+                        format!("Couldn't restore the original contents: {e}"),
+                    ));
+                }
+            }
+        }
+    }
+
+    if !failed.is_empty() {
+        let skipped = rewrites
+            .into_iter()
+            .map(|(path, _, _)| path)
+            .filter(|path| !failed.iter().any(|(failed, _)| failed == path))
+            .map(|path| {
+                (
+                    path,
+                    "Another file of the media couldn't be written".to_string(),
+                )
+            })
+            .collect_vec();
+        report.failed.extend(failed);
+        report.failed.extend(skipped);
+        return Ok(());
+    }
+
+    report
+        .written
+        .extend(rewrites.into_iter().map(|(path, _, _)| TagFileWrite {
+            path,
+            keywords: keywords.to_vec(),
+        }));
+
+    if !dry_run && new_hash != hash {
+        move_media(hash, &new_hash, filesize, pool, pool_thumbs).await?;
+    }
+
+    Ok(())
+}
+
+/// Writes the tags of every media to its files as XMP keywords
+///
+/// With `dry_run` nothing is written, the report lists the files that would be written instead.
+pub async fn write_tags_to_files_impl(
+    hashes: Vec<String>,
+    target: TagWriteTarget,
+    dry_run: bool,
+    pool: &Pool<Sqlite>,
+    pool_thumbs: &Pool<Sqlite>,
+) -> sqlx::Result<TagWriteReport> {
+    let mut report = TagWriteReport::default();
+
+    for hash in hashes {
+        let paths = get_media_paths(&hash, pool).await?;
+        if paths.is_empty() {
+            report.missing.push(hash);
+            continue;
+        }
+
+        let keywords: Vec<String> = query_scalar(
+            "SELECT tag_name FROM HashTagPair WHERE hash = ? AND source IS NOT ? ORDER BY rowid",
+        )
+        .bind(&hash)
+        .bind(IMPLICATION_SOURCE)
+        .fetch_all(pool)
+        .await?;
+
+        let written = |path: String| TagFileWrite {
+            path,
+            keywords: keywords.clone(),
+        };
+
+        match target {
+            TagWriteTarget::Sidecar => {
+                for path in paths {
+                    let sidecar_path = SidecarFormat::Xmp.sidecar_path(Path::new(&path));
+                    let sidecar_path_str = sidecar_path.to_string_lossy().to_string();
+
+                    let existing = fs::read_to_string(&sidecar_path).ok();
+                    let xmp = set_xmp_subjects(existing.as_deref(), &keywords);
+
+                    let result = if dry_run {
+                        Ok(())
+                    } else {
+                        write_file(&sidecar_path, xmp.as_bytes())
+                    };
+
+                    match result {
+                        Ok(()) => report.written.push(written(sidecar_path_str)),
+                        Err(e) => report.failed.push((sidecar_path_str, e.to_string())),
+                    }
+                }
+            }
+            TagWriteTarget::Embedded => {
+                embed_media_keywords(
+                    &hash,
+                    paths,
+                    &keywords,
+                    dry_run,
+                    &mut report,
+                    pool,
+                    pool_thumbs,
+                )
+                .await?;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[sqlx::test]
+async fn test_write_tags_to_files(pool: Pool<Sqlite>) {
+    use sqlx::migrate;
+
+    use super::insert_tags;
+    use crate::{
+        index::postprocess::embedded_tags::get_embedded_tags_from_img,
+        test_util::db_utils::{insert_media_row, insert_path_row},
+    };
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+    // the thumbnails live in their own database, the table is enough here
+    query(include_str!("../../../migrations/thumbs/0_create.sql"))
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let chunk = |kind: &[u8; 4], contents: &'static [u8]| {
+        PngChunk::new(*kind, Bytes::from_static(contents))
+            .encoder()
+            .bytes()
+    };
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png.extend(chunk(b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]));
+    png.extend(chunk(b"IEND", &[]));
+
+    let jpeg = b"\xFF\xD8\xFF\xE0\x00\x10JFIF\x00\x01\x01\x00\x00\x01\x00\x01\x00\x00\
+        \xFF\xDA\x00\x08\x01\x01\x00\x00\x3F\x00\x12\x34\xFF\xD9"
+        .to_vec();
+
+    // lossless 2x3 image with alpha
+    let vp8l_header = (1u32 | (2 << 14) | (1 << 28)).to_le_bytes();
+    let vp8l = [&[0x2F][..], &vp8l_header, &[0, 0, 0]].concat();
+    let mut webp = b"RIFF".to_vec();
+    webp.extend((4 + 8 + vp8l.len() as u32).to_le_bytes());
+    webp.extend(b"WEBPVP8L");
+    webp.extend((vp8l.len() as u32).to_le_bytes());
+    webp.extend(&vp8l);
+
+    let dir = tempfile::tempdir().unwrap();
+    let files = [
+        ("1", "image/png", "1.png", png),
+        ("2", "image/jpeg", "2.jpg", jpeg),
+        ("3", "image/webp", "3.webp", webp),
+        ("4", "image/gif", "4.gif", b"GIF89a".to_vec()),
+    ];
+    for (hash, mime, name, contents) in &files {
+        insert_media_row(&pool, hash, "", "Image", 0, mime, 0, 0, 0, true, false).await;
+        let path = dir.path().join(name);
+        fs::write(&path, contents).unwrap();
+        insert_path_row(&pool, hash, &path.to_string_lossy(), "").await;
+        insert_tags(
+            vec!["cat".to_string(), "black & white".to_string()],
+            &pool,
+            Some(hash.to_string()),
+            None,
+        )
+        .await;
+    }
+
+    let hashes: Vec<String> = ["1", "2", "3", "4", "5"].map(String::from).to_vec();
+    let keywords = vec!["cat".to_string(), "black & white".to_string()];
+
+    let report =
+        write_tags_to_files_impl(hashes.clone(), TagWriteTarget::Embedded, true, &pool, &pool)
+            .await
+            .unwrap();
+    assert_eq!(report.written.len(), 3);
+    assert_eq!(report.written[0].keywords, keywords);
+    assert_eq!(report.missing, vec!["5"]);
+    assert_eq!(report.failed.len(), 1);
+    // a dry run doesn't touch the files
+    assert_eq!(fs::read(dir.path().join("1.png")).unwrap(), files[0].3);
+
+    let report = write_tags_to_files_impl(hashes, TagWriteTarget::Embedded, false, &pool, &pool)
+        .await
+        .unwrap();
+    assert_eq!(report.written.len(), 3);
+
+    for (hash, _, name, _) in &files[..3] {
+        let path = dir.path().join(name);
+        let embedded: Vec<String> = get_embedded_tags_from_img(&path)
+            .unwrap()
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect();
+        assert_eq!(embedded, keywords);
+
+        // the media follows the new contents of its file
+        let new_hash = xxhash(&fs::read(&path).unwrap()).to_string();
+        let tags: i64 = query_scalar("SELECT COUNT(*) FROM HashTagPair WHERE hash = ?")
+            .bind(&new_hash)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(tags, 2);
+        let old: i64 = query_scalar("SELECT COUNT(*) FROM Path WHERE hash = ?")
+            .bind(hash)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(old, 0);
+    }
+
+    let webp = WebP::from_bytes(fs::read(dir.path().join("3.webp")).unwrap().into()).unwrap();
+    let vp8x = webp
+        .chunk_by_id(CHUNK_VP8X)
+        .unwrap()
+        .content()
+        .data()
+        .unwrap();
+    // flags, reserved, the width and the height minus one
+    assert_eq!(
+        vp8x[..],
+        [WEBP_ALPHA_FLAG | WEBP_XMP_FLAG, 0, 0, 0, 1, 0, 0, 2, 0, 0]
+    );
+
+    // existing sidecars keep their other properties
+    let path = dir.path().join("4.gif");
+    fs::write(
+        dir.path().join("4.gif.xmp"),
+        r#"<x:xmpmeta><rdf:RDF><rdf:Description xmp:Rating="5"/></rdf:RDF></x:xmpmeta>"#,
+    )
+    .unwrap();
+    let report = write_tags_to_files_impl(
+        vec!["4".to_string()],
+        TagWriteTarget::Sidecar,
+        false,
+        &pool,
+        &pool,
+    )
+    .await
+    .unwrap();
+    assert_eq!(report.written.len(), 1);
+
+    let sidecar = fs::read_to_string(SidecarFormat::Xmp.sidecar_path(&path)).unwrap();
+    assert_eq!(super::xmp::parse_xmp_subjects(&sidecar), keywords);
+    assert_eq!(super::xmp::parse_xmp_rating(&sidecar), Some(5));
+}
+
+#[sqlx::test]
+async fn test_embed_rollback(pool: Pool<Sqlite>) {
+    use sqlx::migrate;
+
+    use super::insert_tags;
+    use crate::test_util::db_utils::{insert_media_row, insert_path_row};
+
+    migrate!("../migrations/db").run(&pool).await.unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let png = fs::read("src/ai_slop/test_assets/comfy_example.png").unwrap();
+    let hash = xxhash(&png).to_string();
+    insert_media_row(
+        &pool,
+        &hash,
+        "",
+        "Image",
+        0,
+        "image/png",
+        0,
+        0,
+        0,
+        true,
+        false,
+    )
+    .await;
+    insert_tags(vec!["cat".to_string()], &pool, Some(hash.clone()), None).await;
+
+    let paths = ["a.png", "b.png"].map(|name| dir.path().join(name));
+    for path in &paths {
+        fs::write(path, &png).unwrap();
+        insert_path_row(&pool, &hash, &path.to_string_lossy(), "").await;
+    }
+    // the temporary file of `b.png` can't be created
+    fs::create_dir(append_extension(&paths[1], "kasa_tmp")).unwrap();
+
+    let report = write_tags_to_files_impl(
+        vec![hash.clone()],
+        TagWriteTarget::Embedded,
+        false,
+        &pool,
+        &pool,
+    )
+    .await
+    .unwrap();
+    assert!(report.written.is_empty());
+    let failed_paths: Vec<&str> = report
+        .failed
+        .iter()
+        .map(|(path, _)| path.as_str())
+        .collect();
+    assert_eq!(
+        failed_paths,
+        vec![paths[1].to_string_lossy(), paths[0].to_string_lossy()]
+    );
+
+    // `a.png` was written first, it got its original contents back and the media kept its hash
+    for path in &paths {
+        assert_eq!(fs::read(path).unwrap(), png);
+    }
+    let path_hashes: Vec<String> = query_scalar("SELECT DISTINCT hash FROM Path")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(path_hashes, vec![hash]);
+}
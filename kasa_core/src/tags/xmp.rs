@@ -0,0 +1,200 @@
+//! Reading and writing XMP packets
+//!
+//! Only the `dc:subject` keywords, the description and the rating are handled, this isn't a general XMP
+//! parser. Packets are matched with regexes, which is enough for the packets written by common tools and by
+//! `xmp_packet`.
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+static SUBJECT_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<dc:subject\b[^>]*>(.*?)</dc:subject>").unwrap());
+
+static DESCRIPTION_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<dc:description\b[^>]*>(.*?)</dc:description>").unwrap());
+
+static LIST_ITEM_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<rdf:li\b[^>]*>(.*?)</rdf:li>").unwrap());
+
+/// Both `xmp:Rating="3"` and `<xmp:Rating>3</xmp:Rating>`
+static RATING_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"xmp:Rating\s*(?:=\s*["']|>)\s*(-?\d+)"#).unwrap());
+
+/// Items of the `rdf:Bag`, `rdf:Seq` or `rdf:Alt` of the property matched by `property_regex`
+fn parse_xmp_list(xmp: &str, property_regex: &Regex) -> Vec<String> {
+    let Some(property) = property_regex.captures(xmp) else {
+        return vec![];
+    };
+
+    LIST_ITEM_REGEX
+        .captures_iter(&property[1])
+        .map(|item| unescape(item[1].trim()))
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Keywords in `dc:subject`, empty if there are none
+pub(crate) fn parse_xmp_subjects(xmp: &str) -> Vec<String> {
+    parse_xmp_list(xmp, &SUBJECT_REGEX)
+}
+
+/// First `dc:description`, which is a list of the description in different languages
+pub(crate) fn parse_xmp_description(xmp: &str) -> Option<String> {
+    parse_xmp_list(xmp, &DESCRIPTION_REGEX).into_iter().next()
+}
+
+/// `xmp:Rating`, from 1 to 5 stars, 0 is unrated and -1 rejected
+pub(crate) fn parse_xmp_rating(xmp: &str) -> Option<i64> {
+    RATING_REGEX
+        .captures(xmp)
+        .and_then(|rating| rating[1].parse().ok())
+}
+
+/// `dc:subject` element with the keywords
+fn subject_element(keywords: &[String]) -> String {
+    let items: String = keywords
+        .iter()
+        .map(|keyword| format!("     <rdf:li>{}</rdf:li>\n", escape(keyword)))
+        .collect();
+
+    format!("<dc:subject>\n    <rdf:Bag>\n{items}    </rdf:Bag>\n   </dc:subject>")
+}
+
+/// Standalone XMP packet with the keywords as `dc:subject`
+pub(crate) fn xmp_packet(keywords: &[String]) -> String {
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+        <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n \
+        <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n  \
+        <rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n   \
+        {}\n  \
+        </rdf:Description>\n \
+        </rdf:RDF>\n\
+        </x:xmpmeta>\n\
+        <?xpacket end=\"w\"?>\n",
+        subject_element(keywords)
+    )
+}
+
+/// Replaces the `dc:subject` keywords of an existing packet, keeping the rest of its properties
+///
+/// A new packet is made if there is no packet or it has no `rdf:RDF` to add the keywords to.
+pub(crate) fn set_xmp_subjects(xmp: Option<&str>, keywords: &[String]) -> String {
+    let Some(xmp) = xmp else {
+        return xmp_packet(keywords);
+    };
+
+    if let Some(subject) = SUBJECT_REGEX.find(xmp) {
+        return format!(
+            "{}{}{}",
+            &xmp[..subject.start()],
+            subject_element(keywords),
+            &xmp[subject.end()..]
+        );
+    }
+
+    match xmp.find("</rdf:RDF>") {
+        Some(end) => format!(
+            "{}  <rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n   \
+            {}\n  </rdf:Description>\n {}",
+            &xmp[..end],
+            subject_element(keywords),
+            &xmp[end..]
+        ),
+        None => xmp_packet(keywords),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn unescape(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+
+        let entity = &rest[1..end];
+        let character = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+
+        match character {
+            Some(character) => {
+                output.push(character);
+                rest = &rest[end + 1..];
+            }
+            // not an entity, keep the `&` as is
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+#[test]
+fn test_xmp_subjects() {
+    let keywords = vec![
+        "cat".to_string(),
+        "black & white".to_string(),
+        "<3".to_string(),
+    ];
+    assert_eq!(parse_xmp_subjects(&xmp_packet(&keywords)), keywords);
+
+    // written by other tools, with attributes and without a `dc:subject`
+    let xmp = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF><rdf:Description rdf:about=""
+        xmp:Rating="3"><dc:subject><rdf:Bag><rdf:li xml:lang="x-default">sunset</rdf:li>
+        <rdf:li>caf&#233; &#x26; bar</rdf:li><rdf:li></rdf:li></rdf:Bag></dc:subject>
+        </rdf:Description></rdf:RDF></x:xmpmeta>"#;
+    assert_eq!(parse_xmp_subjects(xmp), vec!["sunset", "café & bar"]);
+    assert_eq!(parse_xmp_rating(xmp), Some(3));
+    assert_eq!(parse_xmp_description(xmp), None);
+
+    let xmp = r#"<dc:description><rdf:Alt><rdf:li xml:lang="x-default">A &lt;red&gt; boat</rdf:li>
+        </rdf:Alt></dc:description><xmp:Rating>-1</xmp:Rating>"#;
+    assert_eq!(parse_xmp_description(xmp).unwrap(), "A <red> boat");
+    assert_eq!(parse_xmp_rating(xmp), Some(-1));
+    assert_eq!(
+        parse_xmp_subjects("<rdf:Description dc:title=\"a & b\"/>"),
+        Vec::<String>::new()
+    );
+
+    // the other properties are kept when the keywords are replaced
+    let tagged = r#"<rdf:RDF><rdf:Description xmp:Rating="4"><dc:subject><rdf:Bag><rdf:li>dog</rdf:li>
+        </rdf:Bag></dc:subject></rdf:Description></rdf:RDF>"#;
+    let replaced = set_xmp_subjects(Some(tagged), &keywords);
+    assert_eq!(parse_xmp_subjects(&replaced), keywords);
+    assert_eq!(parse_xmp_rating(&replaced), Some(4));
+
+    let rated = r#"<x:xmpmeta><rdf:RDF><rdf:Description xmp:Rating="2"/></rdf:RDF></x:xmpmeta>"#;
+    let added = set_xmp_subjects(Some(rated), &keywords);
+    assert_eq!(parse_xmp_subjects(&added), keywords);
+    assert_eq!(parse_xmp_rating(&added), Some(2));
+    assert!(added.ends_with("</rdf:RDF></x:xmpmeta>"));
+
+    assert_eq!(set_xmp_subjects(None, &keywords), xmp_packet(&keywords));
+}
@@ -5,7 +5,7 @@ use crate::db::schema::Media;
 pub async fn _insert_media_row(pool: &Pool<Sqlite>, media: &Media) {
     // Too long SQL strings cause rustfmt to die
 
-    let sql = "INSERT INTO Media(hash, thumb_path, media_type, filesize, mime, thumbnail_x, thumbnail_y, time_added, has_file_ref, hide) VALUES (?,?,?,?,?,?,?,?,?,?)";
+    let sql = "INSERT INTO Media(hash, thumb_path, media_type, filesize, mime, thumbnail_x, thumbnail_y, time_added, has_file_ref, hide, is_valid) VALUES (?,?,?,?,?,?,?,?,?,?,?)";
 
     query(sql)
         .bind(&media.hash)
@@ -18,6 +18,7 @@ pub async fn _insert_media_row(pool: &Pool<Sqlite>, media: &Media) {
         .bind(media.time_added)
         .bind(media.has_file_ref)
         .bind(media.hide)
+        .bind(media.is_valid)
         .execute(pool)
         .await
         .unwrap();
@@ -54,6 +55,47 @@ pub async fn insert_media_row(
     _insert_media_row(pool, media).await;
 }
 
+/// Inserts a PNG image with every other column zeroed
+#[cfg(test)]
+pub async fn insert_image(pool: &Pool<Sqlite>, hash: &str) {
+    insert_image_added_at(pool, hash, 0).await;
+}
+
+#[cfg(test)]
+pub async fn insert_image_added_at(pool: &Pool<Sqlite>, hash: &str, time_added: i64) {
+    insert_media_row(
+        pool,
+        hash,
+        "",
+        "Image",
+        0,
+        "image/png",
+        0,
+        0,
+        time_added,
+        true,
+        false,
+    )
+    .await;
+}
+
+/// Hashes of the media matching the search in the order it returns them, tags are expanded like the app does
+#[cfg(test)]
+pub async fn search_hashes(input: &str, pool: &Pool<Sqlite>) -> Vec<String> {
+    use crate::tags::search::SearchCriteria;
+
+    let mut criteria = SearchCriteria::parse_from_str(input).unwrap();
+    criteria.expand_tags(pool).await.unwrap();
+
+    let media: Vec<Media> = criteria
+        .to_query()
+        .build_query_as()
+        .fetch_all(pool)
+        .await
+        .unwrap();
+    media.into_iter().map(|m| m.hash).collect()
+}
+
 pub async fn insert_hash_tag_pair_row(hash: &str, tag_name: &str, pool: &Pool<Sqlite>) {
     query("INSERT INTO HashTagPair(hash, tag_name) VALUES (?,?)")
         .bind(hash)
@@ -74,3 +116,19 @@ pub async fn insert_path_row(pool: &Pool<Sqlite>, hash: &str, path: &str, import
         .await
         .unwrap();
 }
+
+#[cfg(test)]
+pub async fn insert_image_row(
+    pool: &Pool<Sqlite>,
+    hash: &str,
+    resolution_x: i64,
+    resolution_y: i64,
+) {
+    query("INSERT INTO Image(hash, resolution_x, resolution_y) VALUES (?,?,?)")
+        .bind(hash)
+        .bind(resolution_x)
+        .bind(resolution_y)
+        .execute(pool)
+        .await
+        .unwrap();
+}
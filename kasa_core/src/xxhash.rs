@@ -1,13 +1,16 @@
-use log::error;
-use std::{fs::File, path::Path};
+use std::{
+    fs::File,
+    io::{self, ErrorKind, Read},
+    path::Path,
+};
 
 #[inline]
-pub fn streaming_xxhash(path: &Path) -> u128 {
-    let mut file = File::open(path).unwrap();
+pub fn streaming_xxhash(path: &Path) -> io::Result<u128> {
+    let mut file = File::open(path)?;
     let mut buf = [0u8; 4096];
     let mut hasher = xxhash_rust::xxh3::Xxh3::new();
     loop {
-        match std::io::Read::read(&mut file, &mut buf) {
+        match file.read(&mut buf) {
             Ok(0) => {
                 // end of file
                 break;
@@ -15,18 +18,17 @@ pub fn streaming_xxhash(path: &Path) -> u128 {
             Ok(len) => {
                 hasher.update(&buf[..len]);
             }
-            Err(error) => match error.kind() {
-                std::io::ErrorKind::UnexpectedEof => {
-                    println!("unexpected end of file reached");
-                    break;
-                }
-                _ => {
-                    error!("error reading chunk: {}", error);
-                    break;
-                }
-            },
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            // a partially read file would get a wrong hash
+            Err(error) => return Err(error),
         }
     }
 
-    hasher.digest128()
+    Ok(hasher.digest128())
+}
+
+/// Same hash as `streaming_xxhash` for contents that are already in memory
+#[inline]
+pub fn xxhash(bytes: &[u8]) -> u128 {
+    xxhash_rust::xxh3::xxh3_128(bytes)
 }
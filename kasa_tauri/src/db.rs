@@ -13,6 +13,9 @@ use kasa_core::{
 };
 use sqlx::{Pool, Sqlite, query, sqlite::SqlitePoolOptions};
 use tauri::{AppHandle, Manager};
+
+use crate::index::watch_index_sources;
+
 #[derive(Default)]
 pub struct DbStore {
     pub db: Mutex<Option<Pool<Sqlite>>>,
@@ -68,7 +71,7 @@ pub async fn are_dbs_mounted(handle: AppHandle) -> bool {
 
 #[tauri::command(async)]
 #[specta::specta]
-/// Mounts the dbs into db_store, runs any pending migrations
+/// Mounts the dbs into db_store, runs any pending migrations and starts watching the index sources
 pub async fn connect_dbs(handle: AppHandle) {
     let config = get_config_impl();
 
@@ -105,6 +108,10 @@ pub async fn connect_dbs(handle: AppHandle) {
     let db_store = handle.state::<DbStore>();
     *db_store.db.lock().await = Some(pool_db);
     *db_store.thumbs_db.lock().await = Some(pool_thumbs);
+
+    if let Err(e) = watch_index_sources(handle).await {
+        error!("Couldn't start the file watcher: {e}");
+    }
 }
 
 #[tauri::command(async)]
@@ -1,12 +1,17 @@
+use kasa_core::db::schema::IndexError;
 use kasa_core::index::{
+    index_errors::get_index_errors_impl,
     index_sources::{
         add_index_source_impl, cleanup_unreferenced_files_impl, get_index_paths_impl,
         index_all_impl, nuke_all_indexes_impl, nuke_selected_index_impl, remove_index_source_impl,
     },
-    indexer::index,
+    indexer::index_with_progress,
+    progress::{CancellationToken, IndexReport},
+    watcher::{IndexWatcher, WatcherError, watch_index_sources_impl},
 };
-use sqlx::{Pool, Sqlite, pool::PoolOptions};
+use log::{error, warn};
 use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Mutex;
 
 use crate::db::DbStore;
 
@@ -21,6 +26,9 @@ pub async fn add_index_source(handle: AppHandle, path: String) {
     if let Some(db) = connection_guard.as_ref() {
         add_index_source_impl(&path, db).await;
     }
+
+    drop(connection_guard);
+    restart_watcher_if_running(handle).await;
 }
 
 #[tauri::command(async)]
@@ -33,27 +41,58 @@ pub async fn remove_index_source(handle: AppHandle, path: String) {
     if let Some(db) = connection_guard.as_ref() {
         remove_index_source_impl(&path, db).await;
     }
+
+    drop(connection_guard);
+    restart_watcher_if_running(handle).await;
 }
 
 #[tauri::command(async)]
 #[specta::specta]
-pub async fn index_all(handle: AppHandle) -> Result<(), ()> {
+/// Returns a report for each index source, files that couldn't be indexed are in their `errors`, fails if the index
+/// sources couldn't be read
+pub async fn index_all(handle: AppHandle) -> Result<Vec<IndexReport>, ()> {
     let h = handle.clone();
     let connection_state = h.state::<DbStore>();
     let connection_guard = connection_state.db.lock().await;
     let connection_guard_thumbs = connection_state.thumbs_db.lock().await;
 
-    if let (Some(_db), Some(_thumbs_db)) =
+    let mut reports = vec![];
+    if let (Some(db), Some(thumbs_db)) =
         (connection_guard.as_ref(), connection_guard_thumbs.as_ref())
     {
-        let p: Pool<Sqlite> = PoolOptions::new().connect("").await.unwrap();
-        index_all_impl(&p, &p).await;
-        //index_all_impl(db, thumbs_db).await;
+        let cancel = new_cancellation_token(&handle).await;
+        reports = index_all_impl(
+            db,
+            thumbs_db,
+            |progress| handle.emit("index_progress", progress).unwrap(),
+            &cancel,
+        )
+        .await
+        .map_err(|e| error!("Couldn't get the index sources: {e}"))?;
 
         handle.emit("media_updated", "").unwrap()
     }
 
-    Ok(())
+    Ok(reports)
+}
+
+/// Token of the running `index_all` or `index_path`, cancelling it stops the indexing after the current chunk
+#[derive(Default)]
+pub struct IndexingStore(Mutex<Option<CancellationToken>>);
+
+async fn new_cancellation_token(handle: &AppHandle) -> CancellationToken {
+    let cancel = CancellationToken::default();
+    *handle.state::<IndexingStore>().0.lock().await = Some(cancel.clone());
+    cancel
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Stops the running indexing once the chunk being indexed is written, the progress events report it as cancelled
+pub async fn cancel_indexing(handle: AppHandle) {
+    if let Some(cancel) = handle.state::<IndexingStore>().0.lock().await.as_ref() {
+        cancel.cancel();
+    }
 }
 
 #[tauri::command(async)]
@@ -71,17 +110,47 @@ pub async fn get_index_paths(handle: AppHandle) -> Vec<String> {
 
 #[tauri::command(async)]
 #[specta::specta]
-pub async fn index_path(handle: AppHandle, path: String) {
+pub async fn index_path(handle: AppHandle, path: String) -> Option<IndexReport> {
     let connection_state = handle.state::<DbStore>();
     let connection_guard = connection_state.db.lock().await;
     let connection_guard_thumbs = connection_state.thumbs_db.lock().await;
 
+    let mut report = None;
     if let (Some(db), Some(thumbs)) = (connection_guard.as_ref(), connection_guard_thumbs.as_ref())
     {
-        index(&path, db, thumbs).await;
+        let cancel = new_cancellation_token(&handle).await;
+        report = Some(
+            index_with_progress(
+                &path,
+                db,
+                thumbs,
+                |progress| handle.emit("index_progress", progress).unwrap(),
+                &cancel,
+            )
+            .await,
+        );
     }
 
-    handle.emit("media_updated", "").unwrap()
+    handle.emit("media_updated", "").unwrap();
+
+    report
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Gets the files that couldn't be indexed in the last index of their sources
+pub async fn get_index_errors(handle: AppHandle) -> Vec<IndexError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(db) = connection_guard.as_ref() {
+        get_index_errors_impl(db).await.unwrap_or_else(|e| {
+            error!("Couldn't get the index errors: {e}");
+            vec![]
+        })
+    } else {
+        vec![]
+    }
 }
 
 #[tauri::command(async)]
@@ -128,3 +197,47 @@ pub async fn cleanup_unreferenced_files(handle: AppHandle) {
 
     handle.emit("media_updated", "").unwrap()
 }
+
+/// The running file watcher, the index sources stop being watched when it is dropped
+#[derive(Default)]
+pub struct WatcherStore(Mutex<Option<IndexWatcher>>);
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Starts keeping the index up to date with the changes to the files in the index sources, restarts the watcher if
+/// it was already running
+pub async fn watch_index_sources(handle: AppHandle) -> Result<(), WatcherError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+    let connection_guard_thumbs = connection_state.thumbs_db.lock().await;
+
+    if let (Some(db), Some(thumbs)) = (connection_guard.as_ref(), connection_guard_thumbs.as_ref())
+    {
+        let emit_handle = handle.clone();
+        let watcher = watch_index_sources_impl(db, thumbs, move || {
+            emit_handle.emit("media_updated", "").unwrap()
+        })
+        .await?;
+
+        *handle.state::<WatcherStore>().0.lock().await = Some(watcher);
+    } else {
+        warn!("DB connection wasn't initialized yet, the file watcher wasn't started");
+    }
+
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn stop_watching_index_sources(handle: AppHandle) {
+    *handle.state::<WatcherStore>().0.lock().await = None;
+}
+
+/// Restarts the watcher so it picks up the added or removed index sources, does nothing if it isn't running
+async fn restart_watcher_if_running(handle: AppHandle) {
+    let is_running = handle.state::<WatcherStore>().0.lock().await.is_some();
+
+    if is_running && let Err(e) = watch_index_sources(handle).await {
+        error!("Couldn't restart the file watcher: {e}");
+    }
+}
@@ -24,10 +24,14 @@ use file_picker::new_linux_file_picker_dialog_save_file;
 use file_picker::open_file_manager_with_file_selected;
 use image::get_thumbnail;
 use image::get_thumbnail_from_db;
+use index::cancel_indexing;
 use index::cleanup_unreferenced_files;
+use index::get_index_errors;
 use index::index_path;
 use index::nuke_all_indexes;
 use index::nuke_selected_index;
+use index::stop_watching_index_sources;
+use index::watch_index_sources;
 use index::*;
 use log::LevelFilter;
 use log::warn;
@@ -38,18 +42,52 @@ use media::get_media_type;
 use media::get_swf_resolution;
 use media::get_tags;
 use media::get_tags_grouped_by_source_categories;
+use media::mark_viewed;
 use media_server::MediaServerStore;
 use media_server::close_server;
 use media_server::serve_media;
+use search::ActiveSearch;
 use search::SearchState;
+use search::delete_search_preset;
+use search::get_search_presets;
+use search::rename_search_preset;
+use search::save_search_preset;
 use search::search;
+use search::search_page;
+use search::search_preset;
 use search::set_search_store;
 use specta_typescript::BigIntExportBehavior;
 use specta_typescript::Typescript;
+use tags::add_tag_alias;
+use tags::add_tag_implication;
+use tags::assign_tags_to_group;
+use tags::bulk_edit_tags;
+use tags::cleanup_orphaned_tags;
+use tags::create_tag_group;
+use tags::delete_tag_group;
 use tags::delete_tags;
+use tags::export_tag_sidecars;
+use tags::get_implied_tags;
 use tags::get_list_of_all_tags_with_details;
+use tags::get_tag_aliases;
+use tags::get_tag_groups;
+use tags::get_tag_history;
+use tags::get_tag_implication_mode;
+use tags::get_tag_implications;
 use tags::get_tags_as_text;
+use tags::import_tag_sidecars;
+use tags::merge_tags;
+use tags::remove_tag_alias;
+use tags::remove_tag_implication;
+use tags::rename_tag;
+use tags::rename_tag_group;
+use tags::revert_tag_batch;
+use tags::set_tag_color;
+use tags::set_tag_group_color;
+use tags::set_tag_implication_mode;
+use tags::undo_bulk_tag_edit;
 use tags::update_tags;
+use tags::write_tags_to_files;
 use tauri_specta::{Builder, collect_commands};
 use utils::get_env_var;
 use utils::image_path_to_rgba_bytes;
@@ -132,6 +170,12 @@ pub fn run() {
             set_config_value,
             set_config_resolution_value,
             search,
+            search_page,
+            save_search_preset,
+            search_preset,
+            get_search_presets,
+            rename_search_preset,
+            delete_search_preset,
             serve_media,
             close_server,
             get_media_type,
@@ -139,8 +183,12 @@ pub fn run() {
             remove_index_source,
             get_index_paths,
             index_all,
+            cancel_indexing,
+            get_index_errors,
             download_and_index,
             index_path,
+            watch_index_sources,
+            stop_watching_index_sources,
             image_path_to_rgba_bytes,
             open_with_system_default_app,
             new_linux_file_picker_dialog_multiple_folder_select,
@@ -156,11 +204,38 @@ pub fn run() {
             nuke_db_versioning,
             get_tags_grouped_by_source_categories,
             get_list_of_all_tags_with_details,
+            add_tag_alias,
+            remove_tag_alias,
+            get_tag_aliases,
+            add_tag_implication,
+            remove_tag_implication,
+            get_tag_implications,
+            get_implied_tags,
+            get_tag_implication_mode,
+            set_tag_implication_mode,
+            create_tag_group,
+            rename_tag_group,
+            set_tag_group_color,
+            delete_tag_group,
+            get_tag_groups,
+            assign_tags_to_group,
+            set_tag_color,
+            rename_tag,
+            merge_tags,
+            cleanup_orphaned_tags,
+            bulk_edit_tags,
+            undo_bulk_tag_edit,
+            get_tag_history,
+            revert_tag_batch,
+            export_tag_sidecars,
+            import_tag_sidecars,
+            write_tags_to_files,
             open_file_manager_with_file_selected,
             set_search_store,
             set_db_path,
             set_thumbs_db_path,
             get_media_name,
+            mark_viewed,
             get_download_progress
         ]
     });
@@ -195,6 +270,9 @@ pub fn run() {
         .manage(PythonStore::default())
         .manage(ExtractorsStore::default())
         .manage(SearchState::default())
+        .manage(ActiveSearch::default())
+        .manage(WatcherStore::default())
+        .manage(IndexingStore::default())
         .run(context)
         .expect("error while running tauri application");
 }
@@ -3,6 +3,7 @@ use kasa_core::groups::get_group_info_impl;
 use kasa_core::media::{
     MediaInfo, SourceCategoryGroupedTags, TagWithDetails, get_info_impl, get_media_name_impl,
     get_media_type_impl, get_tags_detailed_impl, get_tags_grouped_by_source_categories_impl,
+    mark_viewed_impl,
 };
 use kasa_core::thumbnail::thumbnail_flash::get_flash_resolution_impl;
 use log::error;
@@ -106,3 +107,17 @@ pub async fn get_media_name(handle: AppHandle, hash: String) -> String {
         "".to_string()
     }
 }
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Should be called when the media is opened, for sorting searches by last viewed
+pub async fn mark_viewed(handle: AppHandle, hash: String) {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        mark_viewed_impl(&hash, pool).await;
+    } else {
+        error!("No connection to database , could not mark media as viewed");
+    }
+}
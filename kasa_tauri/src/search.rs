@@ -1,8 +1,12 @@
-use kasa_core::{
-    db::schema::Media,
-    tags::{presets::new_or_update_preset_impl, search::SearchCriteria},
+use kasa_core::tags::{
+    presets::{
+        PresetError, PresetListEntry, delete_preset_impl, get_preset_impl, get_preset_list_impl,
+        new_or_update_preset_impl, rename_preset_impl,
+    },
+    search::{SearchCriteria, SearchCursor, SearchError},
 };
-use log::trace;
+use log::{error, trace};
+use sqlx::{Pool, Sqlite};
 use tauri::{AppHandle, Emitter, Manager};
 use tokio::sync::Mutex;
 
@@ -10,6 +14,21 @@ use tokio::sync::Mutex;
 #[derive(Debug, Default)]
 pub struct SearchState(Mutex<SearchCriteria>);
 
+/// Number of media loaded into the media cache at a time
+const SEARCH_PAGE_SIZE: u32 = 500;
+
+/// The last search ran with `search`, with the tags already expanded
+#[derive(Debug)]
+struct LoadedSearch {
+    criteria: SearchCriteria,
+    /// Cursor of the next page, `None` once every page is in the media cache
+    next_cursor: Option<SearchCursor>,
+}
+
+/// The active search, only its first page is fetched by `search`, the others are loaded with `search_page`
+#[derive(Debug, Default)]
+pub struct ActiveSearch(Mutex<Option<LoadedSearch>>);
+
 use crate::db::{DbStore, MediaCache};
 
 #[tauri::command(async)]
@@ -17,7 +36,12 @@ use crate::db::{DbStore, MediaCache};
 /// `input_raw`: user tags
 /// `width`: viewport width for layout
 /// `gaps`: gaps between images  
-pub async fn search(handle: AppHandle, input_raw: String) {
+///
+/// Loads the first page of the search into the media cache and returns the number of media it matches, `None` if
+/// the db isn't connected
+///
+/// Returns the parse error with its location if `input_raw` is not a valid search, or the db error if it failed
+pub async fn search(handle: AppHandle, input_raw: String) -> Result<Option<i64>, SearchError> {
     // TODO remove width and gaps
     //
     let connection_state = handle.state::<DbStore>();
@@ -27,23 +51,89 @@ pub async fn search(handle: AppHandle, input_raw: String) {
     let search_guard = search_state.0.lock().await;
 
     if let Some(pool) = connection_guard.as_ref() {
-        let mut search_criteria = SearchCriteria::parse_from_str(&input_raw);
+        let mut search_criteria = SearchCriteria::parse_from_str(&input_raw)?;
 
         search_criteria.merge(&search_guard);
+        Ok(Some(run_search(&handle, search_criteria, pool).await?))
+    } else {
+        Ok(None)
+    }
+}
 
-        let mut query = search_criteria.to_query();
-        let media: Vec<Media> = query.build_query_as().fetch_all(pool).await.unwrap();
-        //let media = (&input_raw, pool).await;
+/// Makes the search the active one and fills the media cache with its first page, returns its total
+async fn run_search(
+    handle: &AppHandle,
+    mut search_criteria: SearchCriteria,
+    pool: &Pool<Sqlite>,
+) -> sqlx::Result<i64> {
+    search_criteria.expand_tags(pool).await?;
 
-        //let rows = calculate_layout(media, width as f64, 0, gaps); // TODO get from config
+    let page = search_criteria
+        .fetch_page(None, SEARCH_PAGE_SIZE, pool)
+        .await?;
 
-        let state = handle.state::<MediaCache>();
+    //let rows = calculate_layout(media, width as f64, 0, gaps); // TODO get from config
 
-        *state.media.lock().await = Some(media);
+    let state = handle.state::<MediaCache>();
 
-        handle.emit("cache_updated", "").unwrap();
-        trace!("cache_updated via search");
-    }
+    *state.media.lock().await = Some(page.media);
+    *handle.state::<ActiveSearch>().0.lock().await = Some(LoadedSearch {
+        criteria: search_criteria,
+        next_cursor: page.next_cursor,
+    });
+
+    handle.emit("cache_updated", "").unwrap();
+    trace!("cache_updated via search");
+
+    Ok(page.total.unwrap_or_default())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Appends the next page of the active search to the media cache
+///
+/// Returns `false` if there was no page left to load, no search was made yet or the page couldn't be fetched
+pub async fn search_page(handle: AppHandle) -> bool {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    let active_search = handle.state::<ActiveSearch>();
+    let mut active_guard = active_search.0.lock().await;
+
+    let (Some(pool), Some(active)) = (connection_guard.as_ref(), active_guard.as_mut()) else {
+        return false;
+    };
+    let Some(cursor) = active.next_cursor.take() else {
+        return false;
+    };
+
+    let page = match active
+        .criteria
+        .fetch_page(Some(&cursor), SEARCH_PAGE_SIZE, pool)
+        .await
+    {
+        Ok(page) => page,
+        Err(e) => {
+            error!("Couldn't load the next page of the search: {e}");
+            // the same page is tried again next time
+            active.next_cursor = Some(cursor);
+            return false;
+        }
+    };
+    active.next_cursor = page.next_cursor;
+
+    let state = handle.state::<MediaCache>();
+    state
+        .media
+        .lock()
+        .await
+        .get_or_insert_default()
+        .extend(page.media);
+
+    handle.emit("cache_updated", "").unwrap();
+    trace!("cache_updated via search_page");
+
+    true
 }
 
 /// Called when the search store con
@@ -59,22 +149,86 @@ pub async fn set_search_store(handle: AppHandle, search_criteria: SearchCriteria
     trace!("cache_updated via search");
 }
 
-pub async fn new_or_update_preset(
+#[tauri::command(async)]
+#[specta::specta]
+/// Saves `input_raw` along with the tag picker selection as `name`, replacing the preset with the same name
+pub async fn save_search_preset(
     handle: AppHandle,
-    includes: Vec<String>,
-    excludes: Vec<String>,
-    name: &str,
-) {
+    name: String,
+    input_raw: String,
+) -> Result<(), PresetError> {
     let connection_state = handle.state::<DbStore>();
     let connection_guard = connection_state.db.lock().await;
 
+    let search_state = handle.state::<SearchState>();
+    let search_guard = search_state.0.lock().await;
+
     if let Some(pool) = connection_guard.as_ref() {
-        let result = new_or_update_preset_impl(includes, excludes, name, pool).await;
+        let mut search_criteria = SearchCriteria::parse_from_str(&input_raw)?;
+        search_criteria.merge(&search_guard);
 
-        if result.is_err() {
-            trace!("Failed to update preset: {:?}", result);
-        } else {
-            trace!("Preset updated: {}", name);
-        }
+        new_or_update_preset_impl(&name, &search_criteria, pool).await?;
+        trace!("Preset updated: {}", name);
     }
+
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Runs the preset like `search` does, without merging the tag picker selection into it
+pub async fn search_preset(handle: AppHandle, name: String) -> Result<Option<i64>, PresetError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        let search_criteria = get_preset_impl(&name, pool).await?;
+        Ok(Some(run_search(&handle, search_criteria, pool).await?))
+    } else {
+        Ok(None)
+    }
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn get_search_presets(handle: AppHandle) -> Result<Vec<PresetListEntry>, PresetError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        get_preset_list_impl(pool).await
+    } else {
+        error!("No connection to database, could not get search presets");
+        Ok(vec![])
+    }
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn rename_search_preset(
+    handle: AppHandle,
+    old_name: String,
+    new_name: String,
+) -> Result<(), PresetError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        rename_preset_impl(&old_name, &new_name, pool).await?;
+    }
+
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn delete_search_preset(handle: AppHandle, name: String) -> Result<(), PresetError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        delete_preset_impl(&name, pool).await?;
+    }
+
+    Ok(())
 }
@@ -1,8 +1,30 @@
 use kasa_core::tags::{
-    AllTagsOrderingCriteria, TagWithCount, get_list_of_all_tags_with_details_impl,
-    get_tags_as_text_impl, remove_tags, update_tags_impl,
+    AllTagsOrderingCriteria, TagWithCount,
+    aliases::{AliasError, TagAlias, add_alias_impl, get_aliases_impl, remove_alias_impl},
+    bulk::{
+        BulkTagError, BulkTagOperation, BulkTagUndo, BulkTarget, bulk_edit_tags_impl,
+        undo_bulk_tag_edit_impl,
+    },
+    cleanup::{TagCleanupReport, cleanup_orphaned_tags_impl},
+    get_list_of_all_tags_with_details_impl, get_tags_as_text_impl,
+    groups::{
+        GroupError, TagGroupWithCount, assign_tags_to_group_impl, create_group_impl,
+        delete_group_impl, get_groups_impl, rename_group_impl, set_group_color_impl,
+        set_tag_color_impl,
+    },
+    history::{HistoryError, TagChange, get_tag_history_impl, revert_tag_batch_impl},
+    implications::{
+        ImplicationError, ImplicationMode, TagImplication, add_implication_impl,
+        get_implication_mode, get_implications_impl, get_implied_tags_impl,
+        remove_implication_impl, set_implication_mode_impl,
+    },
+    remove_tags,
+    rename::{RenameError, merge_tags_impl, rename_tag_impl},
+    sidecar::{SidecarFormat, SidecarReport, export_sidecars_impl, import_sidecars_impl},
+    update_tags_impl,
+    writeback::{TagWriteReport, TagWriteTarget, write_tags_to_files_impl},
 };
-use log::trace;
+use log::{error, trace};
 use tauri::{AppHandle, Emitter, Manager};
 
 use crate::db::DbStore;
@@ -66,3 +88,521 @@ pub async fn get_list_of_all_tags_with_details(
         None
     }
 }
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Makes `alias` an alias of `canonical`, media tagged with `alias` is moved to `canonical`
+pub async fn add_tag_alias(
+    handle: AppHandle,
+    alias: String,
+    canonical: String,
+) -> Result<(), AliasError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        add_alias_impl(&alias, &canonical, pool).await?;
+        handle.emit("tags_updated", "").unwrap();
+        trace!("Alias added: {} -> {}", alias, canonical);
+    } else {
+        println!("DB connection wasn't initialized yet!");
+    }
+
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn remove_tag_alias(handle: AppHandle, alias: String) -> Result<(), AliasError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        remove_alias_impl(&alias, pool).await?;
+        handle.emit("tags_updated", "").unwrap();
+    } else {
+        println!("DB connection wasn't initialized yet!");
+    }
+
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn get_tag_aliases(handle: AppHandle) -> Result<Vec<TagAlias>, AliasError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        get_aliases_impl(pool).await
+    } else {
+        println!("DB connection wasn't initialized yet!");
+        Ok(vec![])
+    }
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Makes `tag` imply `implied`, fails if that would create a cycle
+pub async fn add_tag_implication(
+    handle: AppHandle,
+    tag: String,
+    implied: String,
+) -> Result<(), ImplicationError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        add_implication_impl(&tag, &implied, pool).await?;
+        handle.emit("tags_updated", "").unwrap();
+        trace!("Implication added: {} -> {}", tag, implied);
+    } else {
+        println!("DB connection wasn't initialized yet!");
+    }
+
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn remove_tag_implication(
+    handle: AppHandle,
+    tag: String,
+    implied: String,
+) -> Result<(), ImplicationError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        remove_implication_impl(&tag, &implied, pool).await?;
+        handle.emit("tags_updated", "").unwrap();
+    } else {
+        println!("DB connection wasn't initialized yet!");
+    }
+
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn get_tag_implications(
+    handle: AppHandle,
+) -> Result<Vec<TagImplication>, ImplicationError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        get_implications_impl(pool).await
+    } else {
+        println!("DB connection wasn't initialized yet!");
+        Ok(vec![])
+    }
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Every tag implied by `tag`, directly or through other tags
+pub async fn get_implied_tags(
+    handle: AppHandle,
+    tag: String,
+) -> Result<Vec<String>, ImplicationError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        get_implied_tags_impl(&tag, pool).await
+    } else {
+        println!("DB connection wasn't initialized yet!");
+        Ok(vec![])
+    }
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn get_tag_implication_mode(
+    handle: AppHandle,
+) -> Result<ImplicationMode, ImplicationError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        Ok(get_implication_mode(pool).await?)
+    } else {
+        println!("DB connection wasn't initialized yet!");
+        Ok(ImplicationMode::default())
+    }
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Stores the implied tags of every media with `Materialize`, removes them with `QueryTime`
+pub async fn set_tag_implication_mode(
+    handle: AppHandle,
+    mode: ImplicationMode,
+) -> Result<(), ImplicationError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        set_implication_mode_impl(mode, pool).await?;
+        handle.emit("tags_updated", "").unwrap();
+    } else {
+        println!("DB connection wasn't initialized yet!");
+    }
+
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn create_tag_group(
+    handle: AppHandle,
+    name: String,
+    color: Option<String>,
+) -> Result<(), GroupError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        create_group_impl(&name, color, pool).await?;
+        handle.emit("tags_updated", "").unwrap();
+    } else {
+        println!("DB connection wasn't initialized yet!");
+    }
+
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Renames the group, its tags stay in it
+pub async fn rename_tag_group(
+    handle: AppHandle,
+    old_name: String,
+    new_name: String,
+) -> Result<(), GroupError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        rename_group_impl(&old_name, &new_name, pool).await?;
+        handle.emit("tags_updated", "").unwrap();
+    } else {
+        println!("DB connection wasn't initialized yet!");
+    }
+
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn set_tag_group_color(
+    handle: AppHandle,
+    name: String,
+    color: Option<String>,
+) -> Result<(), GroupError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        set_group_color_impl(&name, color, pool).await?;
+        handle.emit("tags_updated", "").unwrap();
+    } else {
+        println!("DB connection wasn't initialized yet!");
+    }
+
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Deletes the group, its tags are left without a group
+pub async fn delete_tag_group(handle: AppHandle, name: String) -> Result<(), GroupError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        delete_group_impl(&name, pool).await?;
+        handle.emit("tags_updated", "").unwrap();
+    } else {
+        println!("DB connection wasn't initialized yet!");
+    }
+
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn get_tag_groups(handle: AppHandle) -> Result<Vec<TagGroupWithCount>, GroupError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        get_groups_impl(pool).await
+    } else {
+        println!("DB connection wasn't initialized yet!");
+        Ok(vec![])
+    }
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Moves the tags to the group, or out of their groups if `group` is `None`
+pub async fn assign_tags_to_group(
+    handle: AppHandle,
+    tags: Vec<String>,
+    group: Option<String>,
+) -> Result<(), GroupError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        assign_tags_to_group_impl(tags, group.as_deref(), pool).await?;
+        handle.emit("tags_updated", "").unwrap();
+    } else {
+        println!("DB connection wasn't initialized yet!");
+    }
+
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Sets the own color of the tag, `override_group_color` makes it win over the group color
+pub async fn set_tag_color(
+    handle: AppHandle,
+    tag: String,
+    color: Option<String>,
+    override_group_color: bool,
+) -> Result<(), GroupError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        set_tag_color_impl(&tag, color, override_group_color, pool).await?;
+        handle.emit("tags_updated", "").unwrap();
+    } else {
+        println!("DB connection wasn't initialized yet!");
+    }
+
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Renames the tag everywhere it is used, fails if `new_name` already exists
+pub async fn rename_tag(
+    handle: AppHandle,
+    old_name: String,
+    new_name: String,
+) -> Result<(), RenameError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        rename_tag_impl(&old_name, &new_name, pool).await?;
+        handle.emit("tags_updated", "").unwrap();
+        trace!("Tag renamed: {} -> {}", old_name, new_name);
+    } else {
+        println!("DB connection wasn't initialized yet!");
+    }
+
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Merges the tags into `target`, media that had any of them is tagged with `target` instead
+pub async fn merge_tags(
+    handle: AppHandle,
+    sources: Vec<String>,
+    target: String,
+) -> Result<(), RenameError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        merge_tags_impl(sources, &target, pool).await?;
+        handle.emit("tags_updated", "").unwrap();
+    } else {
+        println!("DB connection wasn't initialized yet!");
+    }
+
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Removes tags without media that have `delete_on_no_references_left` set, `dry_run` only reports them
+pub async fn cleanup_orphaned_tags(
+    handle: AppHandle,
+    dry_run: bool,
+) -> Result<TagCleanupReport, ()> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        let report = cleanup_orphaned_tags_impl(dry_run, pool)
+            .await
+            .map_err(|e| error!("Couldn't clean up orphaned tags: {e}"))?;
+
+        if !dry_run {
+            handle.emit("tags_updated", "").unwrap();
+        }
+
+        Ok(report)
+    } else {
+        println!("DB connection wasn't initialized yet!");
+        Err(())
+    }
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Adds, removes or replaces tags of every target media at once, returns what is needed to undo it
+pub async fn bulk_edit_tags(
+    handle: AppHandle,
+    target: BulkTarget,
+    operation: BulkTagOperation,
+) -> Result<BulkTagUndo, BulkTagError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        let undo = bulk_edit_tags_impl(target, operation, None, pool).await?;
+        handle.emit("tags_updated", "").unwrap();
+
+        Ok(undo)
+    } else {
+        println!("DB connection wasn't initialized yet!");
+        Ok(BulkTagUndo::default())
+    }
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn undo_bulk_tag_edit(handle: AppHandle, undo: BulkTagUndo) -> Result<(), BulkTagError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        undo_bulk_tag_edit_impl(undo, pool).await?;
+        handle.emit("tags_updated", "").unwrap();
+    } else {
+        println!("DB connection wasn't initialized yet!");
+    }
+
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Tags added to and removed from the media by hand, newest first
+pub async fn get_tag_history(
+    handle: AppHandle,
+    hash: String,
+) -> Result<Vec<TagChange>, HistoryError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        get_tag_history_impl(&hash, pool).await
+    } else {
+        println!("DB connection wasn't initialized yet!");
+        Ok(vec![])
+    }
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Reverts every change of the batch, returns the batch of the revert or `None` if nothing changed
+pub async fn revert_tag_batch(handle: AppHandle, batch: i64) -> Result<Option<i64>, HistoryError> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        let revert_batch = revert_tag_batch_impl(batch, pool).await?;
+        handle.emit("tags_updated", "").unwrap();
+
+        Ok(revert_batch)
+    } else {
+        println!("DB connection wasn't initialized yet!");
+        Ok(None)
+    }
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Writes the tags of the media as sidecar files next to their paths
+pub async fn export_tag_sidecars(
+    handle: AppHandle,
+    hashes: Vec<String>,
+    format: SidecarFormat,
+) -> Result<SidecarReport, ()> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        export_sidecars_impl(hashes, format, pool)
+            .await
+            .map_err(|e| error!("Couldn't export tag sidecars: {e}"))
+    } else {
+        println!("DB connection wasn't initialized yet!");
+        Err(())
+    }
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Adds the tags in the sidecar files next to the paths of the media
+pub async fn import_tag_sidecars(
+    handle: AppHandle,
+    hashes: Vec<String>,
+    format: SidecarFormat,
+) -> Result<SidecarReport, ()> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+
+    if let Some(pool) = connection_guard.as_ref() {
+        let report = import_sidecars_impl(hashes, format, pool)
+            .await
+            .map_err(|e| error!("Couldn't import tag sidecars: {e}"))?;
+        handle.emit("tags_updated", "").unwrap();
+
+        Ok(report)
+    } else {
+        println!("DB connection wasn't initialized yet!");
+        Err(())
+    }
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+/// Writes the tags of the media into their files or XMP sidecars, `dry_run` only reports what would be written
+pub async fn write_tags_to_files(
+    handle: AppHandle,
+    hashes: Vec<String>,
+    target: TagWriteTarget,
+    dry_run: bool,
+) -> Result<TagWriteReport, ()> {
+    let connection_state = handle.state::<DbStore>();
+    let connection_guard = connection_state.db.lock().await;
+    let connection_guard_thumbs = connection_state.thumbs_db.lock().await;
+
+    if let (Some(pool), Some(pool_thumbs)) =
+        (connection_guard.as_ref(), connection_guard_thumbs.as_ref())
+    {
+        let report = write_tags_to_files_impl(hashes, target, dry_run, pool, pool_thumbs)
+            .await
+            .map_err(|e| error!("Couldn't write tags to files: {e}"))?;
+        // embedding the tags moves the media to the hash of the new contents
+        if target == TagWriteTarget::Embedded && !dry_run {
+            handle.emit("media_updated", "").unwrap();
+        }
+
+        Ok(report)
+    } else {
+        println!("DB connection wasn't initialized yet!");
+        Err(())
+    }
+}
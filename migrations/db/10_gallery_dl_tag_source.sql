@@ -0,0 +1,2 @@
+-- Tags imported from gallery-dl used to be stored without a source, they are the only tags with a type but no source
+UPDATE HashTagPair SET source = 'gallery_dl' WHERE source IS NULL AND source_type IS NOT NULL;
@@ -0,0 +1,5 @@
+-- Last time a media was opened, used to sort by recently viewed
+CREATE TABLE IF NOT EXISTS MediaView (
+    hash TEXT NOT NULL UNIQUE,
+    last_viewed INT NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Saving a preset replaces the one with the same name
+DELETE FROM SearchPreset WHERE rowid NOT IN (SELECT MAX(rowid) FROM SearchPreset GROUP BY name);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_search_preset__name ON SearchPreset(name);
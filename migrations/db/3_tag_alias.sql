@@ -0,0 +1,7 @@
+-- Alternative spellings of tags, tags are stored and searched under their canonical name
+CREATE TABLE IF NOT EXISTS TagAlias (
+    alias TEXT NOT NULL UNIQUE,
+    canonical TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_tag_alias__canonical ON TagAlias(canonical);
@@ -0,0 +1,14 @@
+-- Media tagged with `tag` is also `implied`, like `cat_ears` implying `animal_ears`
+CREATE TABLE IF NOT EXISTS TagImplication (
+    tag TEXT NOT NULL,
+    implied TEXT NOT NULL,
+    UNIQUE (tag, implied)
+);
+
+CREATE INDEX IF NOT EXISTS idx_tag_implication__implied ON TagImplication(implied);
+
+-- Options that belong to the database instead of the global config
+CREATE TABLE IF NOT EXISTS Setting (
+    key TEXT NOT NULL UNIQUE,
+    value TEXT NOT NULL
+);
@@ -0,0 +1,4 @@
+-- Tags reference their group by name in `TagDetail._group`
+DELETE FROM TagGroup WHERE rowid NOT IN (SELECT MAX(rowid) FROM TagGroup GROUP BY name);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_tag_group__name ON TagGroup(name);
+CREATE INDEX IF NOT EXISTS idx_tag_detail__group ON TagDetail(_group);
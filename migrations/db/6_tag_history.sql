@@ -0,0 +1,13 @@
+-- Append-only journal of the tags added to and removed from media, changes made together share a `batch`
+CREATE TABLE IF NOT EXISTS TagHistory (
+    batch INTEGER NOT NULL,
+    hash TEXT NOT NULL,
+    tag_name TEXT NOT NULL,
+    added BOOLEAN NOT NULL,
+    source TEXT,
+    source_type TEXT,
+    time INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_tag_history__hash ON TagHistory(hash);
+CREATE INDEX IF NOT EXISTS idx_tag_history__batch ON TagHistory(batch);
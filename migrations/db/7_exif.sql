@@ -0,0 +1,18 @@
+-- Camera metadata of images, only images with EXIF data have a row
+CREATE TABLE IF NOT EXISTS Exif (
+    hash TEXT NOT NULL UNIQUE,
+    make TEXT,
+    model TEXT,
+    lens TEXT,
+    exposure_time REAL,
+    f_number REAL,
+    iso INT,
+    focal_length REAL,
+    taken_at INT,
+    orientation INT,
+    latitude REAL,
+    longitude REAL,
+    altitude REAL
+);
+
+CREATE INDEX IF NOT EXISTS idx_exif__taken_at ON Exif(taken_at);
@@ -0,0 +1,7 @@
+-- Size and modification time of the file when it was last hashed, files that still match them aren't rehashed
+ALTER TABLE Path ADD COLUMN filesize INT;
+ALTER TABLE Path ADD COLUMN mtime INT;
+
+-- The known files of each indexed chunk are looked up by path, and missing ones are pruned per index source
+CREATE INDEX IF NOT EXISTS idx_path__path ON Path(path);
+CREATE INDEX IF NOT EXISTS idx_path__imported_from ON Path(imported_from);
@@ -0,0 +1,11 @@
+-- Files that couldn't be indexed, the errors of an index source are replaced when it gets indexed again
+CREATE TABLE IF NOT EXISTS IndexError (
+    path TEXT NOT NULL UNIQUE,
+    imported_from TEXT NOT NULL,
+    stage TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    message TEXT NOT NULL,
+    time INT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_index_error__imported_from ON IndexError(imported_from);
@@ -0,0 +1,45 @@
+import type { SearchParseError, SearchParseErrorKind } from '$lib/tauri_bindings';
+
+// Same messages as `SearchParseErrorKind` in kasa_core
+const messages: Record<Extract<SearchParseErrorKind, string>, string> = {
+	ExpectedExpression: 'Expected a tag or an expression',
+	UnclosedParenthesis: 'Parenthesis is never closed',
+	UnmatchedClosingParenthesis: "Closing parenthesis doesn't have a matching opening one",
+	UnclosedQuote: 'Quote is never closed',
+	UnexpectedInput: "Unexpected input, expected ',' or the end of the search",
+	ExpectedTime: 'Expected a time like `today`, `3 days ago` or `2024-05-13`'
+};
+
+function kindMessage(kind: SearchParseErrorKind): string {
+	if (typeof kind === 'string') {
+		return messages[kind];
+	}
+
+	if ('InvalidTime' in kind) {
+		return `Invalid time: ${kind.InvalidTime}`;
+	}
+
+	if ('InvalidPredicateValue' in kind) {
+		const { field, value } = kind.InvalidPredicateValue;
+		return `Invalid value \`${value}\` for \`${field}\``;
+	}
+
+	if ('InvalidComparison' in kind) {
+		return `\`${kind.InvalidComparison}\` can only be compared with \`:\`, \`=\` or \`!=\``;
+	}
+
+	return `Invalid order criteria: ${kind.InvalidOrderCriteria}`;
+}
+
+/**
+ * The message of the error with its byte span and the part of the search it points at, the span is in bytes of the
+ * UTF-8 search while js strings are UTF-16
+ */
+export function describeSearchError(searchContents: string, error: SearchParseError): string {
+	const { start, end } = error.span;
+	const bytes = new TextEncoder().encode(searchContents);
+	const part = new TextDecoder().decode(bytes.slice(start, end));
+
+	const location = part.length > 0 ? `at ${start}..${end}: ${part}` : `at ${start}..${end}`;
+	return `${kindMessage(error.kind)} ${location}`;
+}
@@ -1,11 +1,29 @@
+import { commands } from "$lib/tauri_bindings";
+import { describeSearchError } from "./SearchError";
+
 function createSearchStore() {
 
     let searchContents = $state("");
+    let searchError: string | null = $state(null);
 
     return {
         get searchContents() { return searchContents },
         set searchContents(value: string) {
             searchContents = value;
+        },
+        /** Message of the last search if it wasn't valid, shown under the search bar */
+        get searchError() { return searchError },
+
+        async search() {
+            const result = await commands.search(searchContents);
+
+            if (result.status === "ok") {
+                searchError = null;
+            } else if ("InvalidSearch" in result.error) {
+                searchError = describeSearchError(searchContents, result.error.InvalidSearch);
+            } else {
+                searchError = `Database error: ${result.error.Database}`;
+            }
         }
     }
 }
@@ -49,7 +49,7 @@ async getConfig() : Promise<GlobalConfig> {
     return await TAURI_INVOKE("get_config");
 },
 /**
- * Mounts the dbs into db_store, runs any pending migrations
+ * Mounts the dbs into db_store, runs any pending migrations and starts watching the index sources
  */
 async connectDbs() : Promise<void> {
     await TAURI_INVOKE("connect_dbs");
@@ -70,9 +70,73 @@ async setConfigResolutionValue(height: number, width: number) : Promise<void> {
  * `input_raw`: user tags
  * `width`: viewport width for layout
  * `gaps`: gaps between images
+ * 
+ * Loads the first page of the search into the media cache and returns the number of media it matches, `None` if
+ * the db isn't connected
+ * 
+ * Returns the parse error with its location if `input_raw` is not a valid search, or the db error if it failed
  */
-async search(inputRaw: string) : Promise<void> {
-    await TAURI_INVOKE("search", { inputRaw });
+async search(inputRaw: string) : Promise<Result<number | null, SearchError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("search", { inputRaw }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Appends the next page of the active search to the media cache
+ * 
+ * Returns `false` if there was no page left to load, no search was made yet or the page couldn't be fetched
+ */
+async searchPage() : Promise<boolean> {
+    return await TAURI_INVOKE("search_page");
+},
+/**
+ * Saves `input_raw` along with the tag picker selection as `name`, replacing the preset with the same name
+ */
+async saveSearchPreset(name: string, inputRaw: string) : Promise<Result<null, PresetError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("save_search_preset", { name, inputRaw }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Runs the preset like `search` does, without merging the tag picker selection into it
+ */
+async searchPreset(name: string) : Promise<Result<number | null, PresetError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("search_preset", { name }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getSearchPresets() : Promise<Result<PresetListEntry[], PresetError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_search_presets") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async renameSearchPreset(oldName: string, newName: string) : Promise<Result<null, PresetError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("rename_search_preset", { oldName, newName }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async deleteSearchPreset(name: string) : Promise<Result<null, PresetError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("delete_search_preset", { name }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
 },
 /**
  * Returns the pointer to close the server
@@ -101,7 +165,11 @@ async removeIndexSource(path: string) : Promise<void> {
 async getIndexPaths() : Promise<string[]> {
     return await TAURI_INVOKE("get_index_paths");
 },
-async indexAll() : Promise<Result<null, null>> {
+/**
+ * Returns a report for each index source, files that couldn't be indexed are in their `errors`, fails if the index
+ * sources couldn't be read
+ */
+async indexAll() : Promise<Result<IndexReport[], null>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("index_all") };
 } catch (e) {
@@ -109,11 +177,38 @@ async indexAll() : Promise<Result<null, null>> {
     else return { status: "error", error: e  as any };
 }
 },
+/**
+ * Stops the running indexing once the chunk being indexed is written, the progress events report it as cancelled
+ */
+async cancelIndexing() : Promise<void> {
+    await TAURI_INVOKE("cancel_indexing");
+},
+/**
+ * Gets the files that couldn't be indexed in the last index of their sources
+ */
+async getIndexErrors() : Promise<IndexError[]> {
+    return await TAURI_INVOKE("get_index_errors");
+},
 async downloadAndIndex(url: string) : Promise<void> {
     await TAURI_INVOKE("download_and_index", { url });
 },
-async indexPath(path: string) : Promise<void> {
-    await TAURI_INVOKE("index_path", { path });
+async indexPath(path: string) : Promise<IndexReport | null> {
+    return await TAURI_INVOKE("index_path", { path });
+},
+/**
+ * Starts keeping the index up to date with the changes to the files in the index sources, restarts the watcher if
+ * it was already running
+ */
+async watchIndexSources() : Promise<Result<null, WatcherError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("watch_index_sources") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async stopWatchingIndexSources() : Promise<void> {
+    await TAURI_INVOKE("stop_watching_index_sources");
 },
 async imagePathToRgbaBytes(path: string) : Promise<RawImage> {
     return await TAURI_INVOKE("image_path_to_rgba_bytes", { path });
@@ -160,6 +255,265 @@ async getTagsGroupedBySourceCategories(hash: string) : Promise<SourceCategoryGro
 async getListOfAllTagsWithDetails(orderingCriteria: AllTagsOrderingCriteria) : Promise<TagWithCount[] | null> {
     return await TAURI_INVOKE("get_list_of_all_tags_with_details", { orderingCriteria });
 },
+/**
+ * Makes `alias` an alias of `canonical`, media tagged with `alias` is moved to `canonical`
+ */
+async addTagAlias(alias: string, canonical: string) : Promise<Result<null, AliasError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("add_tag_alias", { alias, canonical }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async removeTagAlias(alias: string) : Promise<Result<null, AliasError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("remove_tag_alias", { alias }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getTagAliases() : Promise<Result<TagAlias[], AliasError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_tag_aliases") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Makes `tag` imply `implied`, fails if that would create a cycle
+ */
+async addTagImplication(tag: string, implied: string) : Promise<Result<null, ImplicationError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("add_tag_implication", { tag, implied }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async removeTagImplication(tag: string, implied: string) : Promise<Result<null, ImplicationError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("remove_tag_implication", { tag, implied }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getTagImplications() : Promise<Result<TagImplication[], ImplicationError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_tag_implications") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Every tag implied by `tag`, directly or through other tags
+ */
+async getImpliedTags(tag: string) : Promise<Result<string[], ImplicationError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_implied_tags", { tag }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getTagImplicationMode() : Promise<Result<ImplicationMode, ImplicationError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_tag_implication_mode") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Stores the implied tags of every media with `Materialize`, removes them with `QueryTime`
+ */
+async setTagImplicationMode(mode: ImplicationMode) : Promise<Result<null, ImplicationError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_tag_implication_mode", { mode }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async createTagGroup(name: string, color: string | null) : Promise<Result<null, GroupError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("create_tag_group", { name, color }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Renames the group, its tags stay in it
+ */
+async renameTagGroup(oldName: string, newName: string) : Promise<Result<null, GroupError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("rename_tag_group", { oldName, newName }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setTagGroupColor(name: string, color: string | null) : Promise<Result<null, GroupError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_tag_group_color", { name, color }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Deletes the group, its tags are left without a group
+ */
+async deleteTagGroup(name: string) : Promise<Result<null, GroupError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("delete_tag_group", { name }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getTagGroups() : Promise<Result<TagGroupWithCount[], GroupError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_tag_groups") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Moves the tags to the group, or out of their groups if `group` is `None`
+ */
+async assignTagsToGroup(tags: string[], group: string | null) : Promise<Result<null, GroupError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("assign_tags_to_group", { tags, group }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Sets the own color of the tag, `override_group_color` makes it win over the group color
+ */
+async setTagColor(tag: string, color: string | null, overrideGroupColor: boolean) : Promise<Result<null, GroupError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_tag_color", { tag, color, overrideGroupColor }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Renames the tag everywhere it is used, fails if `new_name` already exists
+ */
+async renameTag(oldName: string, newName: string) : Promise<Result<null, RenameError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("rename_tag", { oldName, newName }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Merges the tags into `target`, media that had any of them is tagged with `target` instead
+ */
+async mergeTags(sources: string[], target: string) : Promise<Result<null, RenameError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("merge_tags", { sources, target }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Removes tags without media that have `delete_on_no_references_left` set, `dry_run` only reports them
+ */
+async cleanupOrphanedTags(dryRun: boolean) : Promise<Result<TagCleanupReport, null>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("cleanup_orphaned_tags", { dryRun }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Adds, removes or replaces tags of every target media at once, returns what is needed to undo it
+ */
+async bulkEditTags(target: BulkTarget, operation: BulkTagOperation) : Promise<Result<BulkTagUndo, BulkTagError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("bulk_edit_tags", { target, operation }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async undoBulkTagEdit(undo: BulkTagUndo) : Promise<Result<null, BulkTagError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("undo_bulk_tag_edit", { undo }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Tags added to and removed from the media by hand, newest first
+ */
+async getTagHistory(hash: string) : Promise<Result<TagChange[], HistoryError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_tag_history", { hash }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Reverts every change of the batch, returns the batch of the revert or `None` if nothing changed
+ */
+async revertTagBatch(batch: number) : Promise<Result<number | null, HistoryError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("revert_tag_batch", { batch }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Writes the tags of the media as sidecar files next to their paths
+ */
+async exportTagSidecars(hashes: string[], format: SidecarFormat) : Promise<Result<SidecarReport, null>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("export_tag_sidecars", { hashes, format }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Adds the tags in the sidecar files next to the paths of the media
+ */
+async importTagSidecars(hashes: string[], format: SidecarFormat) : Promise<Result<SidecarReport, null>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("import_tag_sidecars", { hashes, format }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Writes the tags of the media into their files or XMP sidecars, `dry_run` only reports what would be written
+ */
+async writeTagsToFiles(hashes: string[], target: TagWriteTarget, dryRun: boolean) : Promise<Result<TagWriteReport, null>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("write_tags_to_files", { hashes, target, dryRun }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async openFileManagerWithFileSelected(filePath: string) : Promise<void> {
     await TAURI_INVOKE("open_file_manager_with_file_selected", { filePath });
 },
@@ -178,6 +532,12 @@ async setThumbsDbPath(path: string) : Promise<void> {
 async getMediaName(hash: string) : Promise<string> {
     return await TAURI_INVOKE("get_media_name", { hash });
 },
+/**
+ * Should be called when the media is opened, for sorting searches by last viewed
+ */
+async markViewed(hash: string) : Promise<void> {
+    await TAURI_INVOKE("mark_viewed", { hash });
+},
 async getDownloadProgress() : Promise<GalleryDlStatuses> {
     return await TAURI_INVOKE("get_download_progress");
 }
@@ -193,17 +553,30 @@ async getDownloadProgress() : Promise<GalleryDlStatuses> {
 
 /** user-defined types **/
 
+export type AliasError = "EmptyName" | { AliasOfItself: string } | { NotFound: string } | { ImplicationCycle: string[] } | { Database: string }
 export type AllTagsOrderingCriteria = "Alphabetic" | "AlphabeticReverse" | "TagCount" | "TagCountReverse"
+export type BulkTagError = { Search: string } | { Database: string }
+export type BulkTagOperation = { Add: string[] } | { Remove: string[] } | { Replace: string[] }
+/**
+ * Changes made by a bulk edit, passed to `undo_bulk_tag_edit_impl` to revert it
+ */
+export type BulkTagUndo = { added: HashTagPair[]; removed: HashTagPair[] }
+/**
+ * Media a bulk edit applies to
+ */
+export type BulkTarget = { Hashes: string[] } | { Search: SearchCriteria }
+export type CalendarUnit = "Day" | "Week" | "Month" | "Year"
+export type Comparison = "Eq" | "NotEq" | "Lt" | "Le" | "Gt" | "Ge"
 export type Database = { db_path: string }
 /**
- * Placeholder search until I implement proper search parsing
- * Only supports searching for Media that have the tags
+ * Unix timestamps in milliseconds, compared against `Media.time_added`
  */
 export type DateRange = { start: number; end: number }
 export type Downloader = { output_path: string; gdl_config_path: string | null }
 export type GalleryDlStatus = { bytes_total: number; bytes_downloaded: number; bytes_per_second: number }
 export type GalleryDlStatuses = { [key in string]: GalleryDlStatus }
 export type GlobalConfig = { Database: Database; Thumbnails: Thumbs; Downloader: Downloader }
+export type GroupError = "EmptyName" | { NotFound: string } | { NameTaken: string } | { Database: string }
 /**
  * File-tag pairs
  */
@@ -212,15 +585,150 @@ export type HashTagPair = { hash: string; tag_name: string; source: string | nul
  * Tag "category" from the source
  */
 source_type: string | null }
+export type HistoryError = { BatchNotFound: number } | { Database: string }
 export type ImagePlacement = { x_relative: number; y_relative: number; width: number; height: number; hash: string }
 export type ImageRow = { index: number; height: number; images: ImagePlacement[] }
+export type ImplicationError = "EmptyName" | { ImpliesItself: string } | { Cycle: string[] } | { NotFound: [string, string] } | { Database: string }
+/**
+ * How implied tags are applied, stored per database
+ */
+export type ImplicationMode = "Materialize" | "QueryTime"
 export type ImportInfo = { importSource: string; importLink: string | null }
+/**
+ * Files that couldn't be indexed in the last index of their source
+ */
+export type IndexError = { path: string; imported_from: string; stage: IndexStage; 
+/**
+ * `io::ErrorKind` of the error, `Database` if it couldn't be written to the db
+ */
+kind: string; message: string; time: number }
+/**
+ * Progress of indexing an index source, reported after every chunk
+ */
+export type IndexProgress = { 
+/**
+ * The index source being indexed
+ */
+source: string; 
+/**
+ * Files found while walking the source
+ */
+discovered: number; 
+/**
+ * Files of supported formats that were hashed
+ */
+hashed: number; 
+/**
+ * Files that weren't hashed again as their size and modification time didn't change
+ */
+skipped: number; 
+/**
+ * Files of supported formats written to the db
+ */
+written: number; 
+/**
+ * Files that couldn't be indexed, their errors are in `IndexError`
+ */
+failed: number; 
+/**
+ * The last path of the latest chunk
+ */
+currentPath: string | null; 
+/**
+ * Discovered files per second
+ */
+throughput: number; 
+/**
+ * The indexing was stopped with a `CancellationToken` before the whole source was walked
+ */
+cancelled: boolean }
+/**
+ * Result of indexing an index source
+ */
+export type IndexReport = { progress: IndexProgress; 
+/**
+ * Files that couldn't be indexed, the rest of the source is indexed regardless
+ */
+errors: IndexError[] }
+/**
+ * The step of indexing a file failed at
+ */
+export type IndexStage = "Walk" | "Metadata" | "Hash" | "Write"
 export type MediaInfo = { meta: MetaEntry[]; import: ImportInfo; paths: string[]; tags: TagWithDetails[]; sourceCategoryGroupedTags: SourceCategoryGroupedTags; rawTagsField: string; hash: string; mediaType: string; mime: string | null; aspectRatio: number; fileName: string }
 export type MetaEntry = { name: string; value: string; isValueMonospaced: boolean; isOneLine: boolean }
-export type OrderCriteria = "NewestFirst" | "OldestFirst" | "None"
+export type NumericField = "Width" | "Height" | "FileSize" | "AspectRatio" | "TagCount"
+export type OrderCriteria = "NewestFirst" | "OldestFirst" | "None" | { Random: number } | { FileSize: SortDirection } | { PixelCount: SortDirection } | { TagCount: SortDirection } | { FileName: SortDirection } | { LastViewed: SortDirection }
+export type Orientation = "Portrait" | "Landscape" | "Square"
+export type Predicate = { Imported: TimeRange } | { Taken: TimeRange } | { Modified: TimeRange } | { Numeric: { field: NumericField; comparison: Comparison; value: number } } | { Orientation: Orientation } | { MediaType: string } | { Mime: string } | { TagSource: string | null } | { TagCategory: string } | { Camera: string } | { Lens: string }
+export type PresetError = "EmptyName" | { NotFound: string } | { NameTaken: string } | { InvalidSearch: SearchParseError } | { Database: string }
+export type PresetListEntry = { name: string; 
+/**
+ * Number of media the preset currently matches
+ */
+media_count: number }
 export type RawImage = { width: number; height: number; bytes: number[] }
-export type SearchCriteria = { contains_tags: string[]; contains_tags_or_group: string[][]; excludes_tags: string[]; order_by: OrderCriteria; date_range: DateRange | null }
+export type RenameError = "EmptyName" | { NotFound: string } | { TargetExists: string } | { SameTag: string } | { ImplicationCycle: string[] } | { Database: string }
+export type SearchCriteria = { contains_tags: string[]; contains_tags_or_group: string[][]; excludes_tags: string[]; order_by: OrderCriteria; date_range: DateRange | null; 
+/**
+ * Parsed search bar input, the tag lists above are set by the tag picker
+ */
+expr?: SearchExpr | null }
+/**
+ * Error of running a search typed by the user
+ */
+export type SearchError = { InvalidSearch: SearchParseError } | { Database: string }
+/**
+ * Boolean expression tree of a search query
+ */
+export type SearchExpr = { Tag: string } | { TagPattern: string } | { FuzzyTag: string } | { AnyTag: string[] } | { And: SearchExpr[] } | { Or: SearchExpr[] } | { Not: SearchExpr } | { Predicate: Predicate }
+export type SearchParseError = { kind: SearchParseErrorKind; span: Span }
+export type SearchParseErrorKind = "ExpectedExpression" | "UnclosedParenthesis" | "UnmatchedClosingParenthesis" | "UnclosedQuote" | "UnexpectedInput" | "ExpectedTime" | { InvalidTime: string } | { InvalidPredicateValue: { field: string; value: string } } | { InvalidComparison: string } | { InvalidOrderCriteria: string }
+export type SidecarFormat = "Txt" | "Json" | "Xmp"
+export type SidecarReport = { 
+/**
+ * Sidecars that were written or imported
+ */
+done: string[]; 
+/**
+ * Media without a path, or without a sidecar when importing
+ */
+missing: string[]; 
+/**
+ * Sidecars that couldn't be read or written, with the error
+ */
+failed: [string, string][] }
+export type SortDirection = "Ascending" | "Descending"
 export type SourceCategoryGroupedTags = { source_categories: { [key in string]: HashTagPair[] }; uncategorized: HashTagPair[] }
+/**
+ * Byte offsets of the offending part of the search input, `end` is exclusive
+ */
+export type Span = { start: number; end: number }
+export type TagAlias = { alias: string; canonical: string }
+/**
+ * A tag added to or removed from a media
+ */
+export type TagChange = { 
+/**
+ * Changes made by the same edit share the batch
+ */
+batch: number; hash: string; tag_name: string; 
+/**
+ * `false` if the tag was removed
+ */
+added: boolean; source: string | null; source_type: string | null; 
+/**
+ * Unix timestamp in milliseconds
+ */
+time: number }
+export type TagCleanupReport = { 
+/**
+ * Tags without media that are (or would be) removed from `Tag` and `TagDetail`
+ */
+removed: string[]; 
+/**
+ * Tags without media kept because `delete_on_no_references_left` is off
+ */
+kept: string[] }
 /**
  * Additional Tag details, all info about tags is here instead of `Tag` table, so we don't deal with limitations
  * of virtual tables
@@ -234,12 +742,56 @@ delete_on_no_references_left: boolean; color: string | null; group: string | nul
  * Should this tag use its own color instead of the group one
  */
 override_group_color: boolean }
-export type TagQueryOutput = { name: string; count: number; tag_details: TagDetail }
-export type TagWithCount = { tag_name: string; count: number; details: TagDetail }
+export type TagFileWrite = { path: string; keywords: string[] }
+export type TagGroupWithCount = { name: string; color: string | null; 
+/**
+ * Number of tags in the group
+ */
+tag_count: number }
+export type TagImplication = { tag: string; implied: string }
+export type TagQueryOutput = { name: string; count: number; tag_details: TagDetail; 
+/**
+ * Group of the tag, `None` if it has none or it doesn't exist anymore
+ */
+resolved_group: string | null; 
+/**
+ * Color the tag is shown with, see `tags::groups`
+ */
+resolved_color: string | null }
+export type TagWithCount = { tag_name: string; count: number; details: TagDetail; 
+/**
+ * Group of the tag, `None` if it has none or it doesn't exist anymore
+ */
+resolved_group: string | null; 
+/**
+ * Color the tag is shown with, see `groups`
+ */
+resolved_color: string | null }
 export type TagWithDetails = { hash_tag_pair: HashTagPair; details: TagDetail }
+export type TagWriteReport = { 
+/**
+ * Files that were written, or would be written with a dry run
+ */
+written: TagFileWrite[]; 
+/**
+ * Media without a path
+ */
+missing: string[]; 
+/**
+ * Files that couldn't be written, with the error
+ */
+failed: [string, string][] }
+export type TagWriteTarget = "Sidecar" | "Embedded"
 export type ThumbnailFormat = "png" | "jpeg" | "avif"
 export type Thumbs = { resolution: [number, number]; thumbnail_format: ThumbnailFormat; thumbs_db_path: string }
 export type ThumbsDBInfo = { path: string; size: string; image_count: number; height: number; width: number; format: string }
+export type TimePoint = "Now" | { Absolute: number } | { Ago: { amount: number; unit: TimeUnit } } | { StartOf: { unit: CalendarUnit; offset: number } }
+/**
+ * `start` is inclusive, `end` is exclusive, `None` means the range is unbounded on that side
+ */
+export type TimeRange = { start: TimePoint | null; end: TimePoint | null }
+export type TimeUnit = "Second" | "Minute" | "Hour" | "Day" | "Week" | "Month" | "Year"
+export type WatcherError = { Watch: string } | { Database: string }
 
 /** tauri-specta globals **/
 